@@ -0,0 +1,9 @@
+#version 330 core
+flat in uint Id;
+
+out uint FragId;
+
+void main()
+{
+	FragId = Id;
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 5) in mat4 aInstanceModel;
+
+flat out uint Id;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+uniform bool instanced;
+uniform uint id;
+
+void main()
+{
+	mat4 world = instanced ? model * aInstanceModel : model;
+	Id = instanced ? id + uint(gl_InstanceID) : id;
+	gl_Position = projection * view * world * vec4(aPos, 1.0);
+}
\ No newline at end of file
@@ -2,6 +2,7 @@
 out vec4 FragColor;
 
 in vec2 TexCoords;
+in vec4 Color;
 
 uniform sampler2D texture_diffuse1;
 
@@ -17,5 +18,7 @@ float linearizeDepth(float depth)
 void main()
 {
 	float depth = linearizeDepth(gl_FragCoord.z) / far;
-	FragColor = vec4(vec3(depth), 1.0);
+	// Depth shades the vertex colour. The floor keeps the colour of nearby
+	// surfaces, like the picked cube's highlight, from going black.
+	FragColor = vec4(Color.rgb * (0.2 + 0.8 * depth), 1.0);
 }
\ No newline at end of file
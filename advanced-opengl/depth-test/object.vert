@@ -2,15 +2,23 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in vec4 aColor;
+layout (location = 4) in vec4 aTangent;
+layout (location = 5) in mat4 aInstanceModel;
+layout (location = 9) in vec4 aInstanceColor;
 
 out vec2 TexCoords;
+out vec4 Color;
 
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
+uniform bool instanced;
 
 void main()
 {
+	mat4 world = instanced ? model * aInstanceModel : model;
 	TexCoords = aTexCoords;
-	gl_Position = projection * view * model * vec4(aPos, 1.0);
+	Color = instanced ? aColor * aInstanceColor : aColor;
+	gl_Position = projection * view * world * vec4(aPos, 1.0);
 }
\ No newline at end of file
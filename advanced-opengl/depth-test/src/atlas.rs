@@ -0,0 +1,336 @@
+use crate::gpu;
+use crate::model::Mesh;
+use crate::texture::{self, Image, Pixels, TextureOptions};
+
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasOptions {
+	// Largest width and height the atlas may grow to.
+	pub max_size: u32,
+	// Texels repeated around each image so filtering and the smaller mip
+	// levels don't pick up its neighbours.
+	pub padding: u32,
+}
+
+impl Default for AtlasOptions {
+	fn default() -> AtlasOptions {
+		AtlasOptions {
+			max_size: 4096,
+			padding: 4,
+		}
+	}
+}
+
+// Where an image landed, in texels from the top left of the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+// Shelf packing: the tallest images first, left to right in rows. Returns
+// the atlas size and a rect per size, in the order given, or `None` when
+// they don't fit in `max_size`.
+pub fn pack(
+	sizes: &[(u32, u32)],
+	options: &AtlasOptions,
+) -> Option<(u32, u32, std::vec::Vec<Rect>)> {
+	let padded = |size: u32| size + 2 * options.padding;
+	let area: u64 = sizes
+		.iter()
+		.map(|&(width, height)| padded(width) as u64 * padded(height) as u64)
+		.sum();
+	let widest = sizes.iter().map(|&(width, _)| padded(width)).max()?;
+
+	let mut order: std::vec::Vec<usize> = (0..sizes.len()).collect();
+	order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].1));
+
+	let mut width = ((area as f64).sqrt().ceil() as u32)
+		.max(widest)
+		.next_power_of_two();
+	while width <= options.max_size {
+		let mut rects = vec![
+			Rect {
+				x: 0,
+				y: 0,
+				width: 0,
+				height: 0,
+			};
+			sizes.len()
+		];
+		let (mut x, mut y, mut shelf) = (0, 0, 0);
+		for &i in order.iter() {
+			let (w, h) = sizes[i];
+			if x + padded(w) > width {
+				x = 0;
+				y += shelf;
+				shelf = 0;
+			}
+			rects[i] = Rect {
+				x: x + options.padding,
+				y: y + options.padding,
+				width: w,
+				height: h,
+			};
+			x += padded(w);
+			shelf = shelf.max(padded(h));
+		}
+		let height = y + shelf;
+		if height <= options.max_size {
+			return Some((width, height, rects));
+		}
+		width *= 2;
+	}
+	None
+}
+
+// Maps texture coordinates of a whole image to its part of the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+	pub offset: cgmath::Vector2<f32>,
+	pub scale: cgmath::Vector2<f32>,
+}
+
+impl Region {
+	// `flipped` says whether the atlas was uploaded bottom row first, as
+	// `TextureOptions::flip_vertically` does.
+	pub fn new(rect: Rect, width: u32, height: u32, flipped: bool) -> Region {
+		let (w, h) = (width as f32, height as f32);
+		let y = if flipped {
+			height - rect.y - rect.height
+		} else {
+			rect.y
+		};
+		Region {
+			offset: cgmath::vec2(rect.x as f32 / w, y as f32 / h),
+			scale: cgmath::vec2(rect.width as f32 / w, rect.height as f32 / h),
+		}
+	}
+
+	pub fn map(&self, uv: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+		cgmath::vec2(
+			self.offset.x + uv.x * self.scale.x,
+			self.offset.y + uv.y * self.scale.y,
+		)
+	}
+}
+
+pub struct Atlas {
+	pub texture: gpu::Texture2D,
+	// One per image, in the order they were given.
+	pub regions: std::vec::Vec<Region>,
+}
+
+// Packs the images into one RGBA texture. Grey images are expanded and
+// missing alpha is opaque, whatever their precision they end up 8-bit.
+pub fn build_atlas(
+	images: &[Image],
+	atlas_options: &AtlasOptions,
+	texture_options: &TextureOptions,
+) -> Option<Atlas> {
+	let sizes: std::vec::Vec<(u32, u32)> =
+		images.iter().map(|img| (img.width, img.height)).collect();
+	let (width, height, rects) = pack(&sizes, atlas_options)?;
+
+	let mut pixels = vec![0u8; width as usize * height as usize * 4];
+	let padding = atlas_options.padding as i64;
+	for (img, rect) in images.iter().zip(rects.iter()) {
+		// The padding repeats the image's edge texels.
+		for y in -padding..rect.height as i64 + padding {
+			for x in -padding..rect.width as i64 + padding {
+				let sx = x.clamp(0, rect.width as i64 - 1) as u32;
+				let sy = y.clamp(0, rect.height as i64 - 1) as u32;
+				let dx = (rect.x as i64 + x) as usize;
+				let dy = (rect.y as i64 + y) as usize;
+				let i = (dy * width as usize + dx) * 4;
+				pixels[i..i + 4].copy_from_slice(&rgba(img, sx, sy));
+			}
+		}
+	}
+
+	let atlas = Image {
+		width,
+		height,
+		channels: 4,
+		pixels: Pixels::U8(pixels),
+	};
+	let regions = rects
+		.iter()
+		.map(|&rect| {
+			Region::new(rect, width, height, texture_options.flip_vertically)
+		})
+		.collect();
+	Some(Atlas {
+		texture: texture::texture_from_image(atlas, texture_options),
+		regions,
+	})
+}
+
+fn rgba(img: &Image, x: u32, y: u32) -> [u8; 4] {
+	let byte = |channel| (img.sample(x, y, channel) * 255.0).round() as u8;
+	match img.channels {
+		1 => [byte(0), byte(0), byte(0), 255],
+		2 => [byte(0), byte(0), byte(0), byte(1)],
+		3 => [byte(0), byte(1), byte(2), 255],
+		_ => [byte(0), byte(1), byte(2), byte(3)],
+	}
+}
+
+// Repeating textures can't share an atlas, only meshes whose texture
+// coordinates stay within the image can.
+pub fn can_remap(mesh: &Mesh) -> bool {
+	let epsilon = 1e-4;
+	mesh.vertices.iter().all(|vertex| {
+		let uv = vertex.tex_coords;
+		uv.x >= -epsilon
+			&& uv.x <= 1.0 + epsilon
+			&& uv.y >= -epsilon
+			&& uv.y <= 1.0 + epsilon
+	})
+}
+
+pub fn remap_tex_coords(mesh: &mut Mesh, region: &Region) {
+	for vertex in mesh.vertices.iter_mut() {
+		vertex.tex_coords = region.map(vertex.tex_coords);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::Vertex;
+
+	fn padded(rect: &Rect, padding: u32) -> (u32, u32, u32, u32) {
+		(
+			rect.x - padding,
+			rect.y - padding,
+			rect.x + rect.width + padding,
+			rect.y + rect.height + padding,
+		)
+	}
+
+	#[test]
+	fn packed_rects_keep_their_padding_apart() {
+		let sizes = [(64, 32), (16, 16), (100, 8), (30, 60), (5, 5), (16, 16)];
+		let options = AtlasOptions {
+			max_size: 1024,
+			padding: 2,
+		};
+		let (width, height, rects) = pack(&sizes, &options).unwrap();
+		assert!(width.is_power_of_two());
+		assert_eq!(rects.len(), sizes.len());
+
+		for (rect, &(w, h)) in rects.iter().zip(sizes.iter()) {
+			assert_eq!((rect.width, rect.height), (w, h));
+			// The padding stays inside the atlas on every side.
+			assert!(rect.x >= 2 && rect.y >= 2, "{:?}", rect);
+			let (_, _, right, bottom) = padded(rect, 2);
+			assert!(right <= width && bottom <= height, "{:?}", rect);
+		}
+		for (i, a) in rects.iter().enumerate() {
+			for b in rects[i + 1..].iter() {
+				let (a0, a1) = (padded(a, 2), padded(b, 2));
+				let apart = a0.2 <= a1.0
+					|| a1.2 <= a0.0 || a0.3 <= a1.1
+					|| a1.3 <= a0.1;
+				assert!(apart, "{:?} overlaps {:?}", a, b);
+			}
+		}
+	}
+
+	#[test]
+	fn unpadded_images_fill_the_atlas() {
+		let options = AtlasOptions {
+			max_size: 64,
+			padding: 0,
+		};
+		let (width, height, rects) = pack(&[(32, 32); 4], &options).unwrap();
+		assert_eq!((width, height), (64, 64));
+		let mut corners: std::vec::Vec<(u32, u32)> =
+			rects.iter().map(|rect| (rect.x, rect.y)).collect();
+		corners.sort();
+		assert_eq!(corners, [(0, 0), (0, 32), (32, 0), (32, 32)]);
+	}
+
+	#[test]
+	fn nothing_packs_past_max_size() {
+		let options = AtlasOptions {
+			max_size: 128,
+			padding: 0,
+		};
+		assert!(pack(&[(200, 10)], &options).is_none());
+		assert!(pack(&[(64, 64); 5], &options).is_none());
+		assert!(pack(&[(64, 64); 4], &options).is_some());
+		assert!(pack(&[], &options).is_none());
+		// Padding counts towards the size.
+		let padded = AtlasOptions {
+			max_size: 64,
+			padding: 1,
+		};
+		assert!(pack(&[(64, 64)], &padded).is_none());
+	}
+
+	#[test]
+	fn regions_map_onto_their_rect() {
+		let rect = Rect {
+			x: 16,
+			y: 4,
+			width: 32,
+			height: 16,
+		};
+		let region = Region::new(rect, 64, 32, false);
+		assert_eq!(region.offset, cgmath::vec2(0.25, 0.125));
+		assert_eq!(region.scale, cgmath::vec2(0.5, 0.5));
+		assert_eq!(
+			region.map(cgmath::vec2(0.0, 0.0)),
+			cgmath::vec2(0.25, 0.125)
+		);
+		assert_eq!(
+			region.map(cgmath::vec2(1.0, 1.0)),
+			cgmath::vec2(0.75, 0.625)
+		);
+
+		// Uploaded bottom row first, the rect is counted from the bottom.
+		let flipped = Region::new(rect, 64, 32, true);
+		assert_eq!(flipped.offset, cgmath::vec2(0.25, 0.375));
+		assert_eq!(flipped.scale, region.scale);
+		assert_eq!(
+			flipped.map(cgmath::vec2(0.5, 1.0)),
+			cgmath::vec2(0.5, 0.875)
+		);
+	}
+
+	fn mesh(tex_coords: &[(f32, f32)]) -> Mesh {
+		let mut mesh = Mesh::default();
+		mesh.vertices = tex_coords
+			.iter()
+			.map(|&(u, v)| Vertex {
+				position: cgmath::vec3(0.0, 0.0, 0.0),
+				normal: cgmath::vec3(0.0, 0.0, 1.0),
+				tex_coords: cgmath::vec2(u, v),
+				color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+				tangent: cgmath::vec4(1.0, 0.0, 0.0, 1.0),
+			})
+			.collect();
+		mesh
+	}
+
+	#[test]
+	fn only_coordinates_within_the_image_remap() {
+		assert!(can_remap(&mesh(&[(0.0, 0.0), (1.0, 1.0), (0.5, 0.25)])));
+		// Exporters round a little past the edges.
+		assert!(can_remap(&mesh(&[(-0.00005, 1.00005)])));
+		assert!(!can_remap(&mesh(&[(0.0, 0.0), (1.5, 0.5)])));
+		assert!(!can_remap(&mesh(&[(0.5, -0.1)])));
+
+		let mut quad = mesh(&[(0.0, 0.0), (1.0, 1.0)]);
+		let region = Region {
+			offset: cgmath::vec2(0.5, 0.25),
+			scale: cgmath::vec2(0.25, 0.5),
+		};
+		remap_tex_coords(&mut quad, &region);
+		assert_eq!(quad.vertices[0].tex_coords, cgmath::vec2(0.5, 0.25));
+		assert_eq!(quad.vertices[1].tex_coords, cgmath::vec2(0.75, 0.75));
+	}
+}
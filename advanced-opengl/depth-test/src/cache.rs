@@ -0,0 +1,565 @@
+use crate::bounds;
+use crate::model::{LoadOptions, Lod, Material, Mesh, Vertex};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// Layout, all little endian:
+//
+//   magic "LGLM", format version u32, options hash u64
+//   source count u32, per source: path, size u64, mtime secs u64,
+//   mtime nanos u32, content hash u64
+//   payload length u64, payload checksum u64, payload
+//
+// Strings are a u32 byte length followed by UTF-8. The payload holds the
+// meshes exactly as `setup_mesh` expects them, after optimisation and LOD
+// generation, so a hit skips tobj and all of the processing.
+const MAGIC: &[u8; 4] = b"LGLM";
+const VERSION: u32 = 3;
+
+pub fn cache_path(obj_path: &Path) -> PathBuf {
+	let mut path = obj_path.as_os_str().to_owned();
+	path.push(".meshcache");
+	PathBuf::from(path)
+}
+
+// Returns `None` whenever the cache is missing, stale, from another format
+// version or damaged, the caller then falls back to parsing the OBJ.
+pub fn read(
+	obj_path: &Path,
+	options: &LoadOptions,
+) -> Option<std::vec::Vec<Mesh>> {
+	let mut bytes = std::vec::Vec::new();
+	std::fs::File::open(cache_path(obj_path))
+		.ok()?
+		.read_to_end(&mut bytes)
+		.ok()?;
+	let mut reader = Reader {
+		bytes: &bytes,
+		position: 0,
+	};
+
+	if reader.take(4)? != MAGIC || reader.u32()? != VERSION {
+		return None;
+	}
+	if reader.u64()? != options_hash(options) {
+		return None;
+	}
+
+	let sources = source_files(obj_path);
+	if reader.u32()? as usize != sources.len() {
+		return None;
+	}
+	for source in sources.iter() {
+		let path = reader.string()?;
+		let size = reader.u64()?;
+		let mtime = (reader.u64()?, reader.u32()?);
+		let hash = reader.u64()?;
+		if Path::new(&path) != source.as_path() {
+			return None;
+		}
+
+		let metadata = std::fs::metadata(source).ok()?;
+		if metadata.len() == size && modified(&metadata) == Some(mtime) {
+			continue;
+		}
+		// Touched but maybe not changed, e.g. after a fresh checkout.
+		if fnv1a(&std::fs::read(source).ok()?) != hash {
+			return None;
+		}
+	}
+
+	let length = reader.u64()? as usize;
+	let checksum = reader.u64()?;
+	let payload = reader.take(length)?;
+	if fnv1a(payload) != checksum {
+		return None;
+	}
+
+	read_meshes(&mut Reader {
+		bytes: payload,
+		position: 0,
+	})
+}
+
+pub fn write(
+	obj_path: &Path,
+	options: &LoadOptions,
+	meshes: &[Mesh],
+) -> std::io::Result<()> {
+	let mut payload = Writer {
+		bytes: std::vec::Vec::new(),
+	};
+	write_meshes(&mut payload, meshes);
+
+	let mut header = Writer {
+		bytes: std::vec::Vec::new(),
+	};
+	header.bytes.extend_from_slice(MAGIC);
+	header.u32(VERSION);
+	header.u64(options_hash(options));
+
+	let sources = source_files(obj_path);
+	header.u32(sources.len() as u32);
+	for source in sources.iter() {
+		let metadata = std::fs::metadata(source)?;
+		let (secs, nanos) = modified(&metadata).unwrap_or((0, 0));
+		header.string(&source.to_string_lossy());
+		header.u64(metadata.len());
+		header.u64(secs);
+		header.u32(nanos);
+		header.u64(fnv1a(&std::fs::read(source)?));
+	}
+	header.u64(payload.bytes.len() as u64);
+	header.u64(fnv1a(&payload.bytes));
+
+	// Write to a temporary file first so an interrupted run never leaves a
+	// half written cache with a valid looking header behind.
+	let path = cache_path(obj_path);
+	let mut temporary = path.as_os_str().to_owned();
+	temporary.push(".tmp");
+	let mut file = std::fs::File::create(&temporary)?;
+	file.write_all(&header.bytes)?;
+	file.write_all(&payload.bytes)?;
+	drop(file);
+	std::fs::rename(&temporary, &path)
+}
+
+// The OBJ itself plus every material library it pulls in.
+fn source_files(obj_path: &Path) -> std::vec::Vec<PathBuf> {
+	let mut sources = vec![obj_path.to_path_buf()];
+	let directory = obj_path.parent().unwrap_or_else(|| Path::new(""));
+	if let Ok(text) = std::fs::read_to_string(obj_path) {
+		for line in text.lines() {
+			let mut words = line.split_whitespace();
+			if words.next() == Some("mtllib") {
+				for library in words {
+					sources.push(directory.join(library));
+				}
+			}
+		}
+	}
+	sources
+}
+
+fn modified(metadata: &std::fs::Metadata) -> Option<(u64, u32)> {
+	let time = metadata.modified().ok()?;
+	let since_epoch = time.duration_since(std::time::UNIX_EPOCH).ok()?;
+	Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+fn options_hash(options: &LoadOptions) -> u64 {
+	let mut writer = Writer {
+		bytes: std::vec::Vec::new(),
+	};
+	writer.u32(options.optimize as u32);
+	if let Some(lods) = &options.lods {
+		writer.u32(lods.ratios.len() as u32);
+		for &ratio in lods.ratios.iter() {
+			writer.f32(ratio);
+		}
+		writer.f32(lods.max_error);
+	}
+	fnv1a(&writer.bytes)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+	let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+	for &byte in bytes {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+	}
+	hash
+}
+
+fn write_meshes(writer: &mut Writer, meshes: &[Mesh]) {
+	writer.u32(meshes.len() as u32);
+	for mesh in meshes.iter() {
+		writer.u32(mesh.vertices.len() as u32);
+		for vertex in mesh.vertices.iter() {
+			writer.f32s(&[
+				vertex.position.x,
+				vertex.position.y,
+				vertex.position.z,
+				vertex.normal.x,
+				vertex.normal.y,
+				vertex.normal.z,
+				vertex.tex_coords.x,
+				vertex.tex_coords.y,
+				vertex.color.x,
+				vertex.color.y,
+				vertex.color.z,
+				vertex.color.w,
+				vertex.tangent.x,
+				vertex.tangent.y,
+				vertex.tangent.z,
+				vertex.tangent.w,
+			]);
+		}
+
+		writer.u32(mesh.index_type);
+		writer.indices(&mesh.indices);
+		writer.u32(mesh.lods.len() as u32);
+		for lod in mesh.lods.iter() {
+			writer.f32(lod.error);
+			writer.indices(&lod.indices);
+		}
+
+		let (aabb, sphere) = (&mesh.aabb, &mesh.sphere);
+		writer.f32s(&[aabb.min.x, aabb.min.y, aabb.min.z]);
+		writer.f32s(&[aabb.max.x, aabb.max.y, aabb.max.z]);
+		writer.f32s(&[sphere.center.x, sphere.center.y, sphere.center.z]);
+		writer.f32(sphere.radius);
+
+		match &mesh.material {
+			Some(material) => {
+				writer.u32(1);
+				writer.string(&material.name);
+				writer.f32s(&material.ambient);
+				writer.f32s(&material.diffuse);
+				writer.f32s(&material.specular);
+				writer.f32(material.shininess);
+				writer.f32(material.dissolve);
+				writer.string(&material.diffuse_texture);
+				writer.string(&material.specular_texture);
+				writer.string(&material.normal_texture);
+			}
+			None => writer.u32(0),
+		}
+	}
+}
+
+fn read_meshes(reader: &mut Reader) -> Option<std::vec::Vec<Mesh>> {
+	let count = reader.u32()? as usize;
+	let mut meshes = std::vec::Vec::with_capacity(count.min(1024));
+	for _ in 0..count {
+		let mut mesh = Mesh::default();
+
+		let vertex_count = reader.u32()? as usize;
+		mesh.vertices
+			.reserve(vertex_count.min(reader.remaining() / 64));
+		for _ in 0..vertex_count {
+			let position = reader.vec3()?;
+			let normal = reader.vec3()?;
+			let tex_coords = (reader.f32()?, reader.f32()?);
+			let color = (reader.vec3()?, reader.f32()?);
+			let tangent = (reader.vec3()?, reader.f32()?);
+			mesh.vertices.push(Vertex {
+				position: cgmath::vec3(position[0], position[1], position[2]),
+				normal: cgmath::vec3(normal[0], normal[1], normal[2]),
+				tex_coords: cgmath::vec2(tex_coords.0, tex_coords.1),
+				color: cgmath::vec4(
+					color.0[0], color.0[1], color.0[2], color.1,
+				),
+				tangent: cgmath::vec4(
+					tangent.0[0],
+					tangent.0[1],
+					tangent.0[2],
+					tangent.1,
+				),
+			});
+		}
+
+		mesh.index_type = reader.u32()?;
+		if crate::optimize::index_type_for(vertex_count) != mesh.index_type
+			&& mesh.index_type != gl::UNSIGNED_INT
+		{
+			return None;
+		}
+		mesh.indices = reader.indices()?;
+		let lod_count = reader.u32()?;
+		for _ in 0..lod_count {
+			let error = reader.f32()?;
+			let indices = reader.indices()?;
+			mesh.lods.push(Lod {
+				indices,
+				error,
+				offset: 0,
+			});
+		}
+		if mesh
+			.indices
+			.iter()
+			.chain(mesh.lods.iter().flat_map(|lod| lod.indices.iter()))
+			.any(|&i| i as usize >= vertex_count)
+		{
+			return None;
+		}
+
+		let (min, max) = (reader.vec3()?, reader.vec3()?);
+		mesh.aabb = bounds::Aabb {
+			min: cgmath::point3(min[0], min[1], min[2]),
+			max: cgmath::point3(max[0], max[1], max[2]),
+		};
+		let center = reader.vec3()?;
+		mesh.sphere = bounds::Sphere {
+			center: cgmath::point3(center[0], center[1], center[2]),
+			radius: reader.f32()?,
+		};
+
+		if reader.u32()? == 1 {
+			mesh.material = Some(Material {
+				name: reader.string()?,
+				ambient: reader.vec3()?,
+				diffuse: reader.vec3()?,
+				specular: reader.vec3()?,
+				shininess: reader.f32()?,
+				dissolve: reader.f32()?,
+				diffuse_texture: reader.string()?,
+				specular_texture: reader.string()?,
+				normal_texture: reader.string()?,
+			});
+		}
+
+		meshes.push(mesh);
+	}
+
+	Some(meshes)
+}
+
+struct Writer {
+	bytes: std::vec::Vec<u8>,
+}
+
+impl Writer {
+	fn u32(&mut self, value: u32) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn u64(&mut self, value: u64) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn f32(&mut self, value: f32) {
+		self.bytes.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn f32s(&mut self, values: &[f32]) {
+		for &value in values {
+			self.f32(value);
+		}
+	}
+
+	fn string(&mut self, value: &str) {
+		self.u32(value.len() as u32);
+		self.bytes.extend_from_slice(value.as_bytes());
+	}
+
+	fn indices(&mut self, indices: &[u32]) {
+		self.u32(indices.len() as u32);
+		for &index in indices {
+			self.u32(index);
+		}
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn remaining(&self) -> usize {
+		self.bytes.len() - self.position
+	}
+
+	fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+		if count > self.remaining() {
+			return None;
+		}
+		let slice = &self.bytes[self.position..self.position + count];
+		self.position += count;
+		Some(slice)
+	}
+
+	fn u32(&mut self) -> Option<u32> {
+		let mut bytes = [0; 4];
+		bytes.copy_from_slice(self.take(4)?);
+		Some(u32::from_le_bytes(bytes))
+	}
+
+	fn u64(&mut self) -> Option<u64> {
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(self.take(8)?);
+		Some(u64::from_le_bytes(bytes))
+	}
+
+	fn f32(&mut self) -> Option<f32> {
+		Some(f32::from_bits(self.u32()?))
+	}
+
+	fn vec3(&mut self) -> Option<[f32; 3]> {
+		Some([self.f32()?, self.f32()?, self.f32()?])
+	}
+
+	fn string(&mut self) -> Option<std::string::String> {
+		let length = self.u32()? as usize;
+		std::string::String::from_utf8(self.take(length)?.to_vec()).ok()
+	}
+
+	fn indices(&mut self) -> Option<std::vec::Vec<u32>> {
+		let count = self.u32()? as usize;
+		if count > self.remaining() / 4 {
+			return None;
+		}
+		(0..count).map(|_| self.u32()).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+		vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+		f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n";
+
+	// A scratch directory holding one OBJ, removed again on drop.
+	struct Scratch {
+		directory: PathBuf,
+		obj: PathBuf,
+	}
+
+	impl Scratch {
+		fn new(name: &str) -> Scratch {
+			let directory = std::env::temp_dir().join(format!(
+				"mesh-cache-{}-{}",
+				name,
+				std::process::id()
+			));
+			std::fs::create_dir_all(&directory).unwrap();
+			let obj = directory.join("quad.obj");
+			std::fs::write(&obj, QUAD).unwrap();
+			Scratch { directory, obj }
+		}
+
+		fn path(&self) -> &str {
+			self.obj.to_str().unwrap()
+		}
+	}
+
+	impl Drop for Scratch {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.directory);
+		}
+	}
+
+	fn options() -> LoadOptions {
+		LoadOptions {
+			cache: true,
+			..LoadOptions::default()
+		}
+	}
+
+	fn summary(
+		meshes: &[Mesh],
+	) -> std::vec::Vec<(std::vec::Vec<[f32; 8]>, std::vec::Vec<u32>, u32)> {
+		meshes
+			.iter()
+			.map(|mesh| {
+				let vertices = mesh
+					.vertices
+					.iter()
+					.map(|v| {
+						[
+							v.position.x,
+							v.position.y,
+							v.position.z,
+							v.normal.x,
+							v.normal.y,
+							v.normal.z,
+							v.tex_coords.x,
+							v.tex_coords.y,
+						]
+					})
+					.collect();
+				(vertices, mesh.indices.clone(), mesh.index_type)
+			})
+			.collect()
+	}
+
+	fn load(scratch: &Scratch) -> std::vec::Vec<Mesh> {
+		crate::model::load_meshes(
+			scratch.path(),
+			&options(),
+			&mut std::vec::Vec::new(),
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn round_trip() {
+		let scratch = Scratch::new("round-trip");
+		let meshes = load(&scratch);
+		assert!(cache_path(&scratch.obj).exists());
+		let cached = read(&scratch.obj, &options()).unwrap();
+		assert_eq!(summary(&cached), summary(&meshes));
+		assert_eq!(cached[0].aabb.max, meshes[0].aabb.max);
+	}
+
+	#[test]
+	fn rejects_other_magic_versions_and_options() {
+		let scratch = Scratch::new("header");
+		load(&scratch);
+		let path = cache_path(&scratch.obj);
+		let bytes = std::fs::read(&path).unwrap();
+		assert_eq!(&bytes[0..4], MAGIC);
+		assert_eq!(bytes[4..8], VERSION.to_le_bytes());
+
+		let mut other = bytes.clone();
+		other[0] = b'X';
+		std::fs::write(&path, &other).unwrap();
+		assert!(read(&scratch.obj, &options()).is_none());
+
+		let mut other = bytes.clone();
+		other[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+		std::fs::write(&path, &other).unwrap();
+		assert!(read(&scratch.obj, &options()).is_none());
+
+		std::fs::write(&path, &bytes).unwrap();
+		let optimized = LoadOptions {
+			optimize: true,
+			..options()
+		};
+		assert!(read(&scratch.obj, &optimized).is_none());
+		assert!(read(&scratch.obj, &options()).is_some());
+	}
+
+	#[test]
+	fn rejects_truncated_and_corrupt_caches() {
+		let scratch = Scratch::new("truncated");
+		load(&scratch);
+		let path = cache_path(&scratch.obj);
+		let bytes = std::fs::read(&path).unwrap();
+		for length in 0..bytes.len() {
+			std::fs::write(&path, &bytes[..length]).unwrap();
+			assert!(read(&scratch.obj, &options()).is_none(), "{}", length);
+		}
+
+		let mut corrupt = bytes.clone();
+		*corrupt.last_mut().unwrap() ^= 0xff;
+		std::fs::write(&path, &corrupt).unwrap();
+		assert!(read(&scratch.obj, &options()).is_none());
+	}
+
+	#[test]
+	fn rejects_changed_sources() {
+		let scratch = Scratch::new("stale");
+		load(&scratch);
+		std::fs::write(&scratch.obj, format!("{}f 2/2/1 3/3/1 4/4/1\n", QUAD))
+			.unwrap();
+		assert!(read(&scratch.obj, &options()).is_none());
+	}
+
+	#[test]
+	fn falls_back_to_parsing() {
+		let scratch = Scratch::new("fallback");
+		let parsed = load(&scratch);
+		let path = cache_path(&scratch.obj);
+		let bytes = std::fs::read(&path).unwrap();
+		std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+		assert_eq!(summary(&load(&scratch)), summary(&parsed));
+		// And the damaged cache was replaced.
+		assert_eq!(std::fs::read(&path).unwrap(), bytes);
+	}
+}
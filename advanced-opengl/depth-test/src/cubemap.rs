@@ -0,0 +1,199 @@
+use crate::gpu;
+use crate::shader;
+use crate::texture::{self, Image, Pixels, TextureOptions};
+use cgmath::prelude::*;
+use std::path::Path;
+
+// For each face in OpenGL's order (+X, -X, +Y, -Y, +Z, -Z), the direction
+// through its centre and the directions its texture's s and t grow in, t
+// going down the image rows.
+pub const FACES: [[[f32; 3]; 3]; 6] = [
+	[[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]],
+	[[-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]],
+	[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+	[[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+	[[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],
+	[[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],
+];
+
+// Unnormalised direction through a point on a face, `s` and `t` in -1..1.
+pub fn face_direction(face: usize, s: f32, t: f32) -> cgmath::Vector3<f32> {
+	let [forward, right, down] = FACES[face];
+	cgmath::Vector3::from(forward)
+		+ cgmath::Vector3::from(right) * s
+		+ cgmath::Vector3::from(down) * t
+}
+
+// Cubemap images aren't flipped, their rows already run the way cubemap
+// faces expect.
+fn cube_options(options: &TextureOptions) -> TextureOptions {
+	TextureOptions {
+		flip_vertically: false,
+		wrap_s: gl::CLAMP_TO_EDGE,
+		wrap_t: gl::CLAMP_TO_EDGE,
+		..*options
+	}
+}
+
+fn finish(cube: &gpu::TextureCube, options: &TextureOptions) {
+	cube.bind();
+	unsafe {
+		if options.mipmaps {
+			gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+		}
+		gl::TexParameteri(
+			gl::TEXTURE_CUBE_MAP,
+			gl::TEXTURE_WRAP_R,
+			gl::CLAMP_TO_EDGE as i32,
+		);
+		// Filter across face edges instead of showing the seams.
+		gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+	}
+	texture::apply_sampling(gl::TEXTURE_CUBE_MAP, options, options.mipmaps);
+}
+
+// Six square images in the order of `FACES`, e.g. right, left, top, bottom,
+// front and back of the usual skybox sets.
+pub fn load_cubemap(
+	paths: [&str; 6],
+	options: &TextureOptions,
+) -> gpu::TextureCube {
+	let options = cube_options(options);
+	let cube = gpu::TextureCube::new();
+	cube.bind();
+	for (face, path) in paths.iter().enumerate() {
+		let img = Image::open(Path::new(path));
+		texture::upload_image(
+			gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+			&img,
+			&options,
+		);
+	}
+	finish(&cube, &options);
+	cube
+}
+
+// Builds each face on the CPU from a colour per direction, the direction
+// normalised.
+pub fn cubemap_from_fn<F>(
+	size: u32,
+	options: &TextureOptions,
+	color: F,
+) -> gpu::TextureCube
+where
+	F: Fn(cgmath::Vector3<f32>) -> cgmath::Vector3<f32>,
+{
+	let options = cube_options(options);
+	let cube = gpu::TextureCube::new();
+	cube.bind();
+	for face in 0..6 {
+		let mut pixels =
+			std::vec::Vec::with_capacity((size * size * 3) as usize);
+		for y in 0..size {
+			for x in 0..size {
+				let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+				let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+				let rgb = color(face_direction(face, s, t).normalize());
+				pixels.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+			}
+		}
+		let img = Image {
+			width: size,
+			height: size,
+			channels: 3,
+			pixels: Pixels::F32(pixels),
+		};
+		texture::upload_image(
+			gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+			&img,
+			&options,
+		);
+	}
+	finish(&cube, &options);
+	cube
+}
+
+// Projects an equirectangular panorama, typically an `.hdr`, onto the faces
+// of a `size` sized cubemap on the GPU. Needs equirect.vert and
+// equirect.frag next to the other shaders.
+pub fn cubemap_from_equirectangular(
+	path: &str,
+	size: u32,
+	options: &TextureOptions,
+) -> gpu::TextureCube {
+	let panorama = texture::texture_from_image(
+		Image::open(Path::new(path)),
+		&TextureOptions {
+			flip_vertically: false,
+			wrap_t: gl::CLAMP_TO_EDGE,
+			mipmaps: false,
+			..*options
+		},
+	);
+
+	let options = cube_options(options);
+	let internal_format = if options.half_float {
+		gl::RGB16F
+	} else {
+		gl::RGB32F
+	};
+	let cube = gpu::TextureCube::new();
+	cube.bind();
+	unsafe {
+		for face in 0..6 {
+			gl::TexImage2D(
+				gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+				0,
+				internal_format as i32,
+				size as i32,
+				size as i32,
+				0,
+				gl::RGB,
+				gl::FLOAT,
+				std::ptr::null(),
+			);
+		}
+	}
+
+	let program = shader::create_shader("equirect.vert", "equirect.frag");
+	// The triangle covering the viewport comes from gl_VertexID alone, but
+	// core profiles still want a vertex array bound.
+	let vao = gpu::VertexArray::new();
+	let framebuffer = gpu::Framebuffer::new();
+	let mut viewport = [0; 4];
+	unsafe {
+		gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+		framebuffer.bind();
+		gl::Viewport(0, 0, size as i32, size as i32);
+		gl::Disable(gl::DEPTH_TEST);
+		gl::UseProgram(program);
+		gl::ActiveTexture(gl::TEXTURE0);
+		panorama.bind();
+		shader::set_int(program, "panorama", 0);
+		shader::set_float(program, "size", size as f32);
+		vao.bind();
+
+		for (face, [forward, right, down]) in FACES.iter().enumerate() {
+			gl::FramebufferTexture2D(
+				gl::FRAMEBUFFER,
+				gl::COLOR_ATTACHMENT0,
+				gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+				cube.id(),
+				0,
+			);
+			shader::set_vec3(program, "forward", (*forward).into());
+			shader::set_vec3(program, "right", (*right).into());
+			shader::set_vec3(program, "down", (*down).into());
+			gl::DrawArrays(gl::TRIANGLES, 0, 3);
+		}
+
+		gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+		gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+		gl::Enable(gl::DEPTH_TEST);
+		gl::BindVertexArray(0);
+		gl::DeleteProgram(program);
+	}
+
+	finish(&cube, &options);
+	cube
+}
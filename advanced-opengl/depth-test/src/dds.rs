@@ -0,0 +1,340 @@
+use crate::texture::{Surface, SurfaceFormat};
+use std::io::Read;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 4 + 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+// Header and pixel format flags.
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const DX10_TEXTURE2D: u32 = 3;
+const DX10_TEXTURECUBE: u32 = 0x4;
+
+fn invalid(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+	let mut word = [0; 4];
+	word.copy_from_slice(&bytes[offset..offset + 4]);
+	u32::from_le_bytes(word)
+}
+
+pub fn load_dds(path: &Path) -> std::io::Result<Surface> {
+	let mut bytes = std::vec::Vec::new();
+	std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+	parse_dds(&bytes)
+}
+
+// Single 2D textures with their mip chain. Cubemaps, arrays and volumes are
+// rejected.
+pub fn parse_dds(bytes: &[u8]) -> std::io::Result<Surface> {
+	if bytes.len() < HEADER_SIZE || &bytes[0..4] != b"DDS " {
+		return Err(invalid("not a DDS file"));
+	}
+	if u32_at(bytes, 4) != 124 || u32_at(bytes, 76) != 32 {
+		return Err(invalid("DDS header has the wrong size"));
+	}
+
+	let flags = u32_at(bytes, 8);
+	let height = u32_at(bytes, 12);
+	let width = u32_at(bytes, 16);
+	let level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+		u32_at(bytes, 28).clamp(1, 32)
+	} else {
+		1
+	};
+	if u32_at(bytes, 112) & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+		return Err(invalid("DDS cubemaps and volumes are not supported"));
+	}
+
+	let format_flags = u32_at(bytes, 80);
+	let four_cc = &bytes[84..88];
+	let (format, srgb, data_offset) = if format_flags & DDPF_FOURCC != 0 {
+		if four_cc == b"DX10" {
+			if bytes.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+				return Err(invalid("DDS DX10 header is truncated"));
+			}
+			if u32_at(bytes, HEADER_SIZE + 4) != DX10_TEXTURE2D
+				|| u32_at(bytes, HEADER_SIZE + 8) & DX10_TEXTURECUBE != 0
+				|| u32_at(bytes, HEADER_SIZE + 12) > 1
+			{
+				return Err(invalid("DDS texture is not a single 2D texture"));
+			}
+			let (format, srgb) = dxgi_format(u32_at(bytes, HEADER_SIZE))?;
+			(format, srgb, HEADER_SIZE + DX10_HEADER_SIZE)
+		} else {
+			(four_cc_format(four_cc)?, false, HEADER_SIZE)
+		}
+	} else if format_flags & DDPF_RGB != 0 {
+		(masked_format(bytes, format_flags)?, false, HEADER_SIZE)
+	} else {
+		return Err(invalid("unsupported DDS pixel format"));
+	};
+
+	let mut levels = std::vec::Vec::with_capacity(level_count as usize);
+	let mut offset = data_offset;
+	for level in 0..level_count {
+		if width >> level == 0 && height >> level == 0 {
+			break;
+		}
+		let size = format.level_size(width >> level, height >> level);
+		if offset.saturating_add(size) > bytes.len() {
+			return Err(invalid("DDS mip level is truncated"));
+		}
+		levels.push(bytes[offset..offset + size].to_vec());
+		offset += size;
+	}
+
+	Ok(Surface {
+		format,
+		srgb,
+		width,
+		height,
+		levels,
+	})
+}
+
+// Formats from before the DX10 header, named by a four character code.
+fn four_cc_format(four_cc: &[u8]) -> std::io::Result<SurfaceFormat> {
+	Ok(match four_cc {
+		b"DXT1" => SurfaceFormat::Bc1Alpha,
+		b"DXT2" | b"DXT3" => SurfaceFormat::Bc2,
+		b"DXT4" | b"DXT5" => SurfaceFormat::Bc3,
+		b"ATI1" | b"BC4U" => SurfaceFormat::Bc4,
+		b"BC4S" => SurfaceFormat::Bc4Signed,
+		b"ATI2" | b"BC5U" => SurfaceFormat::Bc5,
+		b"BC5S" => SurfaceFormat::Bc5Signed,
+		_ => return Err(invalid("unsupported DDS four character code")),
+	})
+}
+
+// Uncompressed 32-bit pixels, told apart by where the red channel is.
+fn masked_format(
+	bytes: &[u8],
+	format_flags: u32,
+) -> std::io::Result<SurfaceFormat> {
+	let bit_count = u32_at(bytes, 88);
+	let (red, green, blue) =
+		(u32_at(bytes, 92), u32_at(bytes, 96), u32_at(bytes, 100));
+	let alpha = if format_flags & DDPF_ALPHAPIXELS != 0 {
+		u32_at(bytes, 104)
+	} else {
+		0xff00_0000
+	};
+	match (bit_count, red, green, blue, alpha) {
+		(32, 0xff, 0xff00, 0xff_0000, 0xff00_0000) => Ok(SurfaceFormat::Rgba8),
+		(32, 0xff_0000, 0xff00, 0xff, 0xff00_0000) => Ok(SurfaceFormat::Bgra8),
+		_ => Err(invalid("unsupported DDS pixel layout")),
+	}
+}
+
+fn dxgi_format(format: u32) -> std::io::Result<(SurfaceFormat, bool)> {
+	Ok(match format {
+		2 => (SurfaceFormat::Rgba32F, false),
+		10 => (SurfaceFormat::Rgba16F, false),
+		28 => (SurfaceFormat::Rgba8, false),
+		29 => (SurfaceFormat::Rgba8, true),
+		49 => (SurfaceFormat::Rg8, false),
+		61 => (SurfaceFormat::R8, false),
+		71 => (SurfaceFormat::Bc1Alpha, false),
+		72 => (SurfaceFormat::Bc1Alpha, true),
+		74 => (SurfaceFormat::Bc2, false),
+		75 => (SurfaceFormat::Bc2, true),
+		77 => (SurfaceFormat::Bc3, false),
+		78 => (SurfaceFormat::Bc3, true),
+		80 => (SurfaceFormat::Bc4, false),
+		81 => (SurfaceFormat::Bc4Signed, false),
+		83 => (SurfaceFormat::Bc5, false),
+		84 => (SurfaceFormat::Bc5Signed, false),
+		87 => (SurfaceFormat::Bgra8, false),
+		91 => (SurfaceFormat::Bgra8, true),
+		95 => (SurfaceFormat::Bc6hUnsigned, false),
+		96 => (SurfaceFormat::Bc6hSigned, false),
+		98 => (SurfaceFormat::Bc7, false),
+		99 => (SurfaceFormat::Bc7, true),
+		_ => return Err(invalid("unsupported DXGI format")),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn put(bytes: &mut [u8], offset: usize, value: u32) {
+		bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+	}
+
+	// A header for a `width` by `height` texture claiming `level_count`
+	// mip levels, with the pixel format left for the caller.
+	fn header(width: u32, height: u32, level_count: u32) -> std::vec::Vec<u8> {
+		let mut bytes = vec![0; HEADER_SIZE];
+		bytes[0..4].copy_from_slice(b"DDS ");
+		put(&mut bytes, 4, 124);
+		put(&mut bytes, 8, DDSD_MIPMAPCOUNT);
+		put(&mut bytes, 12, height);
+		put(&mut bytes, 16, width);
+		put(&mut bytes, 28, level_count);
+		put(&mut bytes, 76, 32);
+		bytes
+	}
+
+	fn four_cc(
+		width: u32,
+		height: u32,
+		level_count: u32,
+		code: &[u8; 4],
+	) -> std::vec::Vec<u8> {
+		let mut bytes = header(width, height, level_count);
+		put(&mut bytes, 80, DDPF_FOURCC);
+		bytes[84..88].copy_from_slice(code);
+		bytes
+	}
+
+	// Every level is filled with its own index so they can be told apart.
+	fn with_levels(
+		mut bytes: std::vec::Vec<u8>,
+		sizes: &[usize],
+	) -> std::vec::Vec<u8> {
+		for (level, &size) in sizes.iter().enumerate() {
+			bytes.extend(std::iter::repeat_n(level as u8, size));
+		}
+		bytes
+	}
+
+	fn error(bytes: &[u8]) -> std::string::String {
+		match parse_dds(bytes) {
+			Ok(_) => panic!("parsed an invalid DDS file"),
+			Err(err) => {
+				assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+				err.to_string()
+			}
+		}
+	}
+
+	#[test]
+	fn bc1_mip_chain() {
+		let bytes = with_levels(four_cc(8, 8, 4, b"DXT1"), &[32, 8, 8, 8]);
+		let surface = parse_dds(&bytes).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bc1Alpha);
+		assert!(!surface.srgb);
+		assert_eq!((surface.width, surface.height), (8, 8));
+		let sizes: std::vec::Vec<usize> =
+			surface.levels.iter().map(|level| level.len()).collect();
+		assert_eq!(sizes, vec![32, 8, 8, 8]);
+		for (i, level) in surface.levels.iter().enumerate() {
+			assert!(level.iter().all(|&byte| byte == i as u8));
+		}
+	}
+
+	#[test]
+	fn levels_stop_at_one_pixel() {
+		// Claims more levels than a 4x2 texture has: 4x2, 2x1 and 1x1.
+		let bytes = with_levels(four_cc(4, 2, 10, b"DXT5"), &[16, 16, 16]);
+		let surface = parse_dds(&bytes).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bc3);
+		assert_eq!(surface.levels.len(), 3);
+
+		// Without the mip count flag there is only the top level.
+		let mut bytes = with_levels(four_cc(4, 4, 3, b"ATI2"), &[16]);
+		put(&mut bytes, 8, 0);
+		let surface = parse_dds(&bytes).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bc5);
+		assert_eq!(surface.levels.len(), 1);
+	}
+
+	#[test]
+	fn masked_pixels() {
+		let mut bytes = header(2, 2, 1);
+		put(&mut bytes, 80, DDPF_RGB | DDPF_ALPHAPIXELS);
+		put(&mut bytes, 88, 32);
+		put(&mut bytes, 92, 0xff_0000);
+		put(&mut bytes, 96, 0xff00);
+		put(&mut bytes, 100, 0xff);
+		put(&mut bytes, 104, 0xff00_0000);
+		let surface = parse_dds(&with_levels(bytes.clone(), &[16])).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bgra8);
+		assert_eq!(surface.levels, vec![vec![0; 16]]);
+
+		put(&mut bytes, 92, 0xff);
+		put(&mut bytes, 100, 0xff_0000);
+		let surface = parse_dds(&with_levels(bytes.clone(), &[16])).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Rgba8);
+
+		put(&mut bytes, 88, 24);
+		assert_eq!(
+			error(&with_levels(bytes, &[12])),
+			"unsupported DDS pixel layout"
+		);
+	}
+
+	#[test]
+	fn dx10_headers() {
+		let mut bytes = four_cc(4, 4, 1, b"DX10");
+		let mut dx10 = [0; DX10_HEADER_SIZE];
+		put(&mut dx10, 0, 99);
+		put(&mut dx10, 4, DX10_TEXTURE2D);
+		put(&mut dx10, 12, 1);
+		bytes.extend_from_slice(&dx10);
+		let surface = parse_dds(&with_levels(bytes.clone(), &[16])).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bc7);
+		assert!(surface.srgb);
+		assert_eq!(surface.levels, vec![vec![0; 16]]);
+
+		put(&mut bytes, HEADER_SIZE + 12, 6);
+		assert_eq!(
+			error(&with_levels(bytes.clone(), &[16])),
+			"DDS texture is not a single 2D texture"
+		);
+		put(&mut bytes, HEADER_SIZE + 12, 1);
+		put(&mut bytes, HEADER_SIZE, 1000);
+		assert_eq!(
+			error(&with_levels(bytes, &[16])),
+			"unsupported DXGI format"
+		);
+	}
+
+	#[test]
+	fn malformed_headers() {
+		let good = with_levels(four_cc(4, 4, 1, b"DXT1"), &[8]);
+		parse_dds(&good).unwrap();
+
+		let mut bytes = good.clone();
+		bytes[0] = b'X';
+		assert_eq!(error(&bytes), "not a DDS file");
+		let mut bytes = good.clone();
+		put(&mut bytes, 4, 100);
+		assert_eq!(error(&bytes), "DDS header has the wrong size");
+		let mut bytes = good.clone();
+		put(&mut bytes, 112, DDSCAPS2_CUBEMAP);
+		assert_eq!(error(&bytes), "DDS cubemaps and volumes are not supported");
+		let mut bytes = good.clone();
+		bytes[84..88].copy_from_slice(b"ETC2");
+		assert_eq!(error(&bytes), "unsupported DDS four character code");
+		let mut bytes = good;
+		put(&mut bytes, 80, 0);
+		assert_eq!(error(&bytes), "unsupported DDS pixel format");
+		assert_eq!(
+			error(&four_cc(4, 4, 1, b"DX10")),
+			"DDS DX10 header is truncated"
+		);
+	}
+
+	#[test]
+	fn truncated_files() {
+		let bytes = with_levels(four_cc(8, 8, 4, b"DXT1"), &[32, 8, 8, 8]);
+		for length in 0..bytes.len() {
+			error(&bytes[..length]);
+		}
+		// Sizes a corrupt header can claim don't overflow.
+		let mut bytes = four_cc(8, 8, 1, b"DXT1");
+		put(&mut bytes, 12, u32::MAX);
+		put(&mut bytes, 16, u32::MAX);
+		assert_eq!(error(&bytes), "DDS mip level is truncated");
+	}
+}
@@ -0,0 +1,723 @@
+use crate::model::{Material, Mesh, Model};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+// Writes `path` plus a material library with the same stem next to it.
+// Every mesh becomes its own object, faces reuse one index for the position,
+// texture coordinate and normal as the vertices are already unified.
+pub fn write_obj(model: &Model, path: &Path) -> std::io::Result<()> {
+	let mtl_path = path.with_extension("mtl");
+	let mtl_name = mtl_path.file_name().unwrap().to_string_lossy();
+	let out_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+	let mut obj = std::io::BufWriter::new(std::fs::File::create(path)?);
+	writeln!(obj, "# Exported by learn-gl-rs")?;
+	writeln!(obj, "mtllib {}", mtl_name)?;
+
+	let mut offset = 1;
+	for (i, mesh) in model.meshes.iter().enumerate() {
+		writeln!(obj, "o mesh_{}", i)?;
+		// Vertex colours use the common "v x y z r g b" extension, only
+		// written when the mesh has any so plain OBJs stay plain.
+		let colored = has_colors(mesh);
+		for v in mesh.vertices.iter() {
+			if colored {
+				writeln!(
+					obj,
+					"v {} {} {} {} {} {}",
+					v.position.x,
+					v.position.y,
+					v.position.z,
+					v.color.x,
+					v.color.y,
+					v.color.z
+				)?;
+			} else {
+				writeln!(
+					obj,
+					"v {} {} {}",
+					v.position.x, v.position.y, v.position.z
+				)?;
+			}
+		}
+		for v in mesh.vertices.iter() {
+			writeln!(obj, "vt {} {}", v.tex_coords.x, v.tex_coords.y)?;
+		}
+		for v in mesh.vertices.iter() {
+			writeln!(obj, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z)?;
+		}
+		if let Some(material) = &mesh.material {
+			writeln!(obj, "usemtl {}", material_name(material, i))?;
+		}
+		for tri in mesh.indices.chunks(3) {
+			let (a, b, c) = (tri[0] + offset, tri[1] + offset, tri[2] + offset);
+			writeln!(obj, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+		}
+		offset += mesh.vertices.len() as u32;
+	}
+	obj.flush()?;
+
+	let mut mtl = std::io::BufWriter::new(std::fs::File::create(&mtl_path)?);
+	let mut written: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+	for (i, mesh) in model.meshes.iter().enumerate() {
+		let material = match &mesh.material {
+			Some(material) => material,
+			None => continue,
+		};
+		let name = material_name(material, i);
+		if written.contains(&name) {
+			continue;
+		}
+
+		writeln!(mtl, "newmtl {}", name)?;
+		let [r, g, b] = material.ambient;
+		writeln!(mtl, "Ka {} {} {}", r, g, b)?;
+		let [r, g, b] = material.diffuse;
+		writeln!(mtl, "Kd {} {} {}", r, g, b)?;
+		let [r, g, b] = material.specular;
+		writeln!(mtl, "Ks {} {} {}", r, g, b)?;
+		writeln!(mtl, "Ns {}", material.shininess)?;
+		writeln!(mtl, "d {}", material.dissolve)?;
+		let textures = [
+			("map_Kd", &material.diffuse_texture),
+			("map_Ks", &material.specular_texture),
+			("map_Bump", &material.normal_texture),
+		];
+		for (key, texture) in textures.iter() {
+			if !texture.is_empty() {
+				let texture = texture_path(&model.directory, texture, out_dir);
+				writeln!(mtl, "{} {}", key, texture)?;
+			}
+		}
+		writeln!(mtl)?;
+		written.push(name);
+	}
+	mtl.flush()
+}
+
+// Writes a glTF 2.0 JSON file and its binary buffer with the same stem.
+// glTF puts the texture origin at the top left, OBJ and this repo at the
+// bottom left, so V is flipped on the way out.
+pub fn write_gltf(model: &Model, path: &Path) -> std::io::Result<()> {
+	let bin_path = path.with_extension("bin");
+	let bin_name = bin_path.file_name().unwrap().to_string_lossy().to_string();
+	let out_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+	let mut buffer: std::vec::Vec<u8> = std::vec::Vec::new();
+	let mut buffer_views: std::vec::Vec<std::string::String> =
+		std::vec::Vec::new();
+	let mut accessors: std::vec::Vec<std::string::String> =
+		std::vec::Vec::new();
+	let mut meshes: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+	let mut nodes: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+	let mut materials: std::vec::Vec<std::string::String> =
+		std::vec::Vec::new();
+	let mut material_names: std::vec::Vec<std::string::String> =
+		std::vec::Vec::new();
+	let mut textures: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+	let mut images: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+
+	for (i, mesh) in model.meshes.iter().enumerate() {
+		let mut positions =
+			std::vec::Vec::with_capacity(mesh.vertices.len() * 3);
+		let mut normals = std::vec::Vec::with_capacity(mesh.vertices.len() * 3);
+		let mut tex_coords =
+			std::vec::Vec::with_capacity(mesh.vertices.len() * 2);
+		let mut colors = std::vec::Vec::with_capacity(mesh.vertices.len() * 4);
+		for v in mesh.vertices.iter() {
+			positions.extend_from_slice(&[
+				v.position.x,
+				v.position.y,
+				v.position.z,
+			]);
+			normals.extend_from_slice(&[v.normal.x, v.normal.y, v.normal.z]);
+			tex_coords
+				.extend_from_slice(&[v.tex_coords.x, 1.0 - v.tex_coords.y]);
+			colors.extend_from_slice(&[
+				v.color.x, v.color.y, v.color.z, v.color.w,
+			]);
+		}
+
+		let position_accessor = accessors.len();
+		let view = push_view(
+			&mut buffer,
+			&mut buffer_views,
+			&f32_bytes(&positions),
+			34962,
+		);
+		accessors.push(format!(
+			"{{\"bufferView\":{},\"componentType\":5126,\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+			view,
+			mesh.vertices.len(),
+			json_number(mesh.aabb.min.x),
+			json_number(mesh.aabb.min.y),
+			json_number(mesh.aabb.min.z),
+			json_number(mesh.aabb.max.x),
+			json_number(mesh.aabb.max.y),
+			json_number(mesh.aabb.max.z),
+		));
+
+		let normal_accessor = accessors.len();
+		let view = push_view(
+			&mut buffer,
+			&mut buffer_views,
+			&f32_bytes(&normals),
+			34962,
+		);
+		accessors.push(format!(
+			"{{\"bufferView\":{},\"componentType\":5126,\"count\":{},\"type\":\"VEC3\"}}",
+			view,
+			mesh.vertices.len(),
+		));
+
+		let tex_coord_accessor = accessors.len();
+		let view = push_view(
+			&mut buffer,
+			&mut buffer_views,
+			&f32_bytes(&tex_coords),
+			34962,
+		);
+		accessors.push(format!(
+			"{{\"bufferView\":{},\"componentType\":5126,\"count\":{},\"type\":\"VEC2\"}}",
+			view,
+			mesh.vertices.len(),
+		));
+
+		let color = if has_colors(mesh) {
+			let view = push_view(
+				&mut buffer,
+				&mut buffer_views,
+				&f32_bytes(&colors),
+				34962,
+			);
+			accessors.push(format!(
+				"{{\"bufferView\":{},\"componentType\":5126,\"count\":{},\"type\":\"VEC4\"}}",
+				view,
+				mesh.vertices.len(),
+			));
+			format!(",\"COLOR_0\":{}", accessors.len() - 1)
+		} else {
+			std::string::String::new()
+		};
+
+		let index_accessor = accessors.len();
+		let (index_bytes, component_type) =
+			if mesh.index_type == gl::UNSIGNED_SHORT {
+				let bytes = mesh
+					.indices
+					.iter()
+					.flat_map(|&i| (i as u16).to_le_bytes().to_vec())
+					.collect::<std::vec::Vec<u8>>();
+				(bytes, 5123)
+			} else {
+				let bytes = mesh
+					.indices
+					.iter()
+					.flat_map(|&i| i.to_le_bytes().to_vec())
+					.collect::<std::vec::Vec<u8>>();
+				(bytes, 5125)
+			};
+		let view =
+			push_view(&mut buffer, &mut buffer_views, &index_bytes, 34963);
+		accessors.push(format!(
+			"{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+			view,
+			component_type,
+			mesh.indices.len(),
+		));
+
+		let material = match &mesh.material {
+			Some(material) => {
+				let name = material_name(material, i);
+				match material_names.iter().position(|n| *n == name) {
+					Some(index) => format!(",\"material\":{}", index),
+					None => {
+						materials.push(gltf_material(
+							material,
+							&model.directory,
+							out_dir,
+							&mut textures,
+							&mut images,
+						));
+						material_names.push(name);
+						format!(",\"material\":{}", materials.len() - 1)
+					}
+				}
+			}
+			None => std::string::String::new(),
+		};
+
+		meshes.push(format!(
+			"{{\"name\":\"mesh_{}\",\"primitives\":[{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{},\"TEXCOORD_0\":{}{}}},\"indices\":{}{}}}]}}",
+			i, position_accessor, normal_accessor, tex_coord_accessor, color, index_accessor, material,
+		));
+		nodes.push(format!("{{\"mesh\":{}}}", i));
+	}
+
+	let node_list = (0..nodes.len())
+		.map(|i| i.to_string())
+		.collect::<std::vec::Vec<_>>()
+		.join(",");
+	let mut json = format!(
+		"{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"learn-gl-rs\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[{}]}}],\"nodes\":[{}],\"meshes\":[{}],\"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"uri\":{},\"byteLength\":{}}}]",
+		node_list,
+		nodes.join(","),
+		meshes.join(","),
+		accessors.join(","),
+		buffer_views.join(","),
+		json_string(&bin_name),
+		buffer.len(),
+	);
+	if !materials.is_empty() {
+		json.push_str(&format!(",\"materials\":[{}]", materials.join(",")));
+	}
+	if !textures.is_empty() {
+		json.push_str(&format!(
+			",\"samplers\":[{{\"magFilter\":9729,\"minFilter\":9987,\"wrapS\":10497,\"wrapT\":10497}}],\"textures\":[{}],\"images\":[{}]",
+			textures.join(","),
+			images.join(","),
+		));
+	}
+	json.push('}');
+
+	std::fs::write(&bin_path, &buffer)?;
+	std::fs::write(path, json)
+}
+
+fn material_name(
+	material: &Material,
+	mesh_index: usize,
+) -> std::string::String {
+	if material.name.is_empty() {
+		format!("material_{}", mesh_index)
+	} else {
+		material.name.clone()
+	}
+}
+
+// Loaders fill in white when a format has no colours, anything else came
+// from the file and is worth keeping.
+fn has_colors(mesh: &Mesh) -> bool {
+	mesh.vertices
+		.iter()
+		.any(|v| v.color != cgmath::vec4(1.0, 1.0, 1.0, 1.0))
+}
+
+fn gltf_material(
+	material: &Material,
+	directory: &str,
+	out_dir: &Path,
+	textures: &mut std::vec::Vec<std::string::String>,
+	images: &mut std::vec::Vec<std::string::String>,
+) -> std::string::String {
+	let mut texture_index = |texture: &str| {
+		let uri = json_string(&texture_path(directory, texture, out_dir));
+		let image = match images
+			.iter()
+			.position(|image| *image == format!("{{\"uri\":{}}}", uri))
+		{
+			Some(image) => image,
+			None => {
+				images.push(format!("{{\"uri\":{}}}", uri));
+				images.len() - 1
+			}
+		};
+		let texture = format!("{{\"sampler\":0,\"source\":{}}}", image);
+		match textures.iter().position(|t| *t == texture) {
+			Some(index) => index,
+			None => {
+				textures.push(texture);
+				textures.len() - 1
+			}
+		}
+	};
+
+	let [r, g, b] = material.diffuse;
+	// Blinn-Phong exponent to roughness, the usual sqrt(2 / (n + 2)).
+	let roughness = (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt();
+	let mut pbr = format!(
+		"\"baseColorFactor\":[{},{},{},{}],\"metallicFactor\":0,\"roughnessFactor\":{}",
+		json_number(r),
+		json_number(g),
+		json_number(b),
+		json_number(material.dissolve),
+		json_number(roughness)
+	);
+	if !material.diffuse_texture.is_empty() {
+		pbr.push_str(&format!(
+			",\"baseColorTexture\":{{\"index\":{}}}",
+			texture_index(&material.diffuse_texture)
+		));
+	}
+
+	let mut result = format!(
+		"{{\"name\":{},\"pbrMetallicRoughness\":{{{}}}",
+		json_string(&material.name),
+		pbr
+	);
+	if !material.normal_texture.is_empty() {
+		result.push_str(&format!(
+			",\"normalTexture\":{{\"index\":{}}}",
+			texture_index(&material.normal_texture)
+		));
+	}
+	if material.dissolve < 1.0 {
+		result.push_str(",\"alphaMode\":\"BLEND\"");
+	}
+	result.push('}');
+	result
+}
+
+fn push_view(
+	buffer: &mut std::vec::Vec<u8>,
+	views: &mut std::vec::Vec<std::string::String>,
+	data: &[u8],
+	target: u32,
+) -> usize {
+	// Accessor offsets have to be aligned to their component size.
+	buffer.resize(buffer.len().next_multiple_of(4), 0);
+	views.push(format!(
+		"{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+		buffer.len(),
+		data.len(),
+		target
+	));
+	buffer.extend_from_slice(data);
+	views.len() - 1
+}
+
+fn f32_bytes(values: &[f32]) -> std::vec::Vec<u8> {
+	values
+		.iter()
+		.flat_map(|v| v.to_le_bytes().to_vec())
+		.collect()
+}
+
+// JSON has no NaN or infinities, NaN becomes 0 and the infinities the
+// largest finite floats.
+fn json_number(value: f32) -> std::string::String {
+	if value.is_nan() {
+		"0".into()
+	} else {
+		value.clamp(f32::MIN, f32::MAX).to_string()
+	}
+}
+
+fn json_string(value: &str) -> std::string::String {
+	let mut result = std::string::String::with_capacity(value.len() + 2);
+	result.push('"');
+	for c in value.chars() {
+		match c {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			'\n' => result.push_str("\\n"),
+			'\r' => result.push_str("\\r"),
+			'\t' => result.push_str("\\t"),
+			c if (c as u32) < 0x20 => {
+				result.push_str(&format!("\\u{:04x}", c as u32))
+			}
+			c => result.push(c),
+		}
+	}
+	result.push('"');
+	result
+}
+
+// Texture paths in the model are relative to the directory it was loaded
+// from, rewrite them relative to wherever the export ends up.
+fn texture_path(
+	directory: &str,
+	texture: &str,
+	out_dir: &Path,
+) -> std::string::String {
+	let texture = Path::new(directory).join(texture);
+	let (texture, out_dir) = match (texture.canonicalize(), absolute(out_dir)) {
+		(Ok(texture), Some(out_dir)) => (texture, out_dir),
+		_ => return texture.to_string_lossy().replace('\\', "/"),
+	};
+
+	let texture_parts: std::vec::Vec<Component> =
+		texture.components().collect();
+	let out_parts: std::vec::Vec<Component> = out_dir.components().collect();
+	let common = texture_parts
+		.iter()
+		.zip(out_parts.iter())
+		.take_while(|(a, b)| a == b)
+		.count();
+
+	let mut relative = PathBuf::new();
+	for _ in common..out_parts.len() {
+		relative.push("..");
+	}
+	for part in texture_parts[common..].iter() {
+		relative.push(part.as_os_str());
+	}
+	relative.to_string_lossy().replace('\\', "/")
+}
+
+fn absolute(path: &Path) -> Option<PathBuf> {
+	if path.as_os_str().is_empty() {
+		std::env::current_dir().ok()?.canonicalize().ok()
+	} else {
+		path.canonicalize().ok()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::{LoadOptions, Vertex};
+
+	// A scratch directory, removed again on drop.
+	struct Scratch(PathBuf);
+
+	impl Scratch {
+		fn new(name: &str) -> Scratch {
+			let directory = std::env::temp_dir().join(format!(
+				"export-{}-{}",
+				name,
+				std::process::id()
+			));
+			std::fs::create_dir_all(&directory).unwrap();
+			Scratch(directory)
+		}
+	}
+
+	impl Drop for Scratch {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.0);
+		}
+	}
+
+	fn mesh(positions: &[[f32; 3]], indices: &[u32]) -> Mesh {
+		let mut mesh = Mesh::default();
+		mesh.vertices = positions
+			.iter()
+			.map(|&[x, y, z]| Vertex {
+				position: cgmath::vec3(x, y, z),
+				normal: cgmath::vec3(0.0, 0.0, 1.0),
+				tex_coords: cgmath::vec2(x, y),
+				color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+				tangent: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+			})
+			.collect();
+		mesh.indices = indices.to_vec();
+		mesh.index_type = crate::optimize::index_type_for(positions.len());
+		crate::model::compute_bounds(&mut mesh);
+		mesh
+	}
+
+	fn model() -> Model {
+		let mut model = Model::default();
+		model.meshes.push(mesh(
+			&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+			&[0, 1, 2],
+		));
+		model.meshes.push(mesh(
+			&[
+				[0.0, 0.0, 1.0],
+				[2.0, 0.0, 1.0],
+				[2.0, 2.0, 1.0],
+				[0.0, 2.0, 1.0],
+			],
+			&[0, 1, 2, 0, 2, 3],
+		));
+		model
+	}
+
+	fn positions(mesh: &Mesh) -> std::vec::Vec<[f32; 3]> {
+		mesh.vertices
+			.iter()
+			.map(|v| [v.position.x, v.position.y, v.position.z])
+			.collect()
+	}
+
+	#[test]
+	fn obj_round_trip() {
+		let scratch = Scratch::new("obj");
+		let path = scratch.0.join("model.obj");
+		let model = model();
+		write_obj(&model, &path).unwrap();
+		assert!(path.with_extension("mtl").exists());
+
+		let loaded = crate::model::load_meshes(
+			path.to_str().unwrap(),
+			&LoadOptions::default(),
+			&mut std::vec::Vec::new(),
+		)
+		.unwrap();
+		assert_eq!(loaded.len(), model.meshes.len());
+		for (loaded, original) in loaded.iter().zip(model.meshes.iter()) {
+			assert_eq!(positions(loaded), positions(original));
+			assert_eq!(loaded.indices, original.indices);
+			for (a, b) in loaded.vertices.iter().zip(original.vertices.iter()) {
+				assert_eq!(a.normal, b.normal);
+				assert_eq!(a.tex_coords, b.tex_coords);
+			}
+		}
+	}
+
+	// One material with every texture and another sharing its diffuse one,
+	// in a scratch directory the textures exist in.
+	fn textured_model(directory: &Path) -> Model {
+		for texture in ["paint.png", "shine.png", "bumps.png"].iter() {
+			std::fs::write(directory.join(texture), b"").unwrap();
+		}
+		let mut model = model();
+		model.directory = directory.to_str().unwrap().into();
+		model.meshes[0].material = Some(Material {
+			name: "paint".into(),
+			ambient: [0.1, 0.2, 0.3],
+			diffuse: [0.4, 0.5, 0.6],
+			specular: [0.7, 0.8, 0.9],
+			shininess: 32.0,
+			dissolve: 0.5,
+			diffuse_texture: "paint.png".into(),
+			specular_texture: "shine.png".into(),
+			normal_texture: "bumps.png".into(),
+		});
+		model.meshes[1].material = Some(Material {
+			name: "plain".into(),
+			diffuse: [1.0, 1.0, 1.0],
+			dissolve: 1.0,
+			diffuse_texture: "paint.png".into(),
+			..Material::default()
+		});
+		model
+	}
+
+	#[test]
+	fn obj_round_trip_keeps_materials() {
+		let scratch = Scratch::new("obj-materials");
+		let path = scratch.0.join("model.obj");
+		let model = textured_model(&scratch.0);
+		write_obj(&model, &path).unwrap();
+
+		let loaded = crate::model::load_meshes(
+			path.to_str().unwrap(),
+			&LoadOptions::default(),
+			&mut std::vec::Vec::new(),
+		)
+		.unwrap();
+		for (loaded, original) in loaded.iter().zip(model.meshes.iter()) {
+			let loaded = loaded.material.as_ref().unwrap();
+			let original = original.material.as_ref().unwrap();
+			assert_eq!(loaded.name, original.name);
+			assert_eq!(loaded.ambient, original.ambient);
+			assert_eq!(loaded.diffuse, original.diffuse);
+			assert_eq!(loaded.specular, original.specular);
+			assert_eq!(loaded.shininess, original.shininess);
+			assert_eq!(loaded.dissolve, original.dissolve);
+			assert_eq!(loaded.diffuse_texture, original.diffuse_texture);
+			assert_eq!(loaded.specular_texture, original.specular_texture);
+			assert_eq!(loaded.normal_texture, original.normal_texture);
+		}
+	}
+
+	#[test]
+	fn gltf_materials_share_textures() {
+		let scratch = Scratch::new("gltf-materials");
+		let path = scratch.0.join("model.gltf");
+		write_gltf(&textured_model(&scratch.0), &path).unwrap();
+		let json = std::fs::read_to_string(&path).unwrap();
+
+		assert!(json.contains("\"material\":0"));
+		assert!(json.contains("\"material\":1"));
+		// Shininess 32 is roughness sqrt(2 / 34).
+		assert!(json.contains(&format!(
+			"\"baseColorFactor\":[0.4,0.5,0.6,0.5],\"metallicFactor\":0,\"roughnessFactor\":{},\"baseColorTexture\":{{\"index\":0}}",
+			(2.0f32 / 34.0).sqrt()
+		)));
+		assert!(json.contains("\"normalTexture\":{\"index\":1}"));
+		assert!(json.contains("\"alphaMode\":\"BLEND\""));
+		// Both materials' diffuse textures are one texture and image.
+		assert!(json.contains(
+			"\"baseColorFactor\":[1,1,1,1],\"metallicFactor\":0,\"roughnessFactor\":1,\"baseColorTexture\":{\"index\":0}"
+		));
+		assert!(json.contains(
+			"\"textures\":[{\"sampler\":0,\"source\":0},{\"sampler\":0,\"source\":1}],\"images\":[{\"uri\":\"paint.png\"},{\"uri\":\"bumps.png\"}]"
+		));
+	}
+
+	#[test]
+	fn gltf_numbers_are_finite() {
+		let scratch = Scratch::new("gltf-finite");
+		let path = scratch.0.join("model.gltf");
+		let mut model = model();
+		model.meshes[0].aabb.min.x = f32::NEG_INFINITY;
+		model.meshes[0].material = Some(Material {
+			diffuse: [f32::NAN, f32::INFINITY, 0.5],
+			dissolve: 1.0,
+			..Material::default()
+		});
+		write_gltf(&model, &path).unwrap();
+		let json = std::fs::read_to_string(&path).unwrap();
+
+		assert!(!json.contains("NaN") && !json.contains("inf"));
+		assert!(json.contains(&format!("\"min\":[{},0,0]", f32::MIN)));
+		assert!(json
+			.contains(&format!("\"baseColorFactor\":[0,{},0.5,1]", f32::MAX)));
+	}
+
+	fn floats(bytes: &[u8]) -> std::vec::Vec<f32> {
+		bytes
+			.chunks(4)
+			.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+			.collect()
+	}
+
+	#[test]
+	fn gltf_buffer_layout() {
+		let scratch = Scratch::new("gltf");
+		let path = scratch.0.join("model.gltf");
+		write_gltf(&model(), &path).unwrap();
+		let json = std::fs::read_to_string(&path).unwrap();
+		let buffer = std::fs::read(path.with_extension("bin")).unwrap();
+
+		// Per mesh: positions, normals, texture coordinates and u16
+		// indices, each view starting 4 byte aligned.
+		let views = [
+			(0, 36, 34962),
+			(36, 36, 34962),
+			(72, 24, 34962),
+			(96, 6, 34963),
+			(104, 48, 34962),
+			(152, 48, 34962),
+			(200, 32, 34962),
+			(232, 12, 34963),
+		];
+		let expected: std::vec::Vec<std::string::String> = views
+			.iter()
+			.map(|(offset, length, target)| {
+				format!(
+					"{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+					offset, length, target
+				)
+			})
+			.collect();
+		assert!(
+			json.contains(&format!("\"bufferViews\":[{}]", expected.join(",")))
+		);
+		assert!(json.contains(
+			"\"buffers\":[{\"uri\":\"model.bin\",\"byteLength\":244}]"
+		));
+		assert_eq!(buffer.len(), 244);
+
+		assert_eq!(
+			floats(&buffer[104..152]),
+			[0.0, 0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 2.0, 1.0, 0.0, 2.0, 1.0]
+		);
+		// V is flipped for glTF's top left origin.
+		assert_eq!(
+			floats(&buffer[200..232]),
+			[0.0, 1.0, 2.0, 1.0, 2.0, -1.0, 0.0, -1.0]
+		);
+		let indices: std::vec::Vec<u16> = buffer[232..244]
+			.chunks(2)
+			.map(|b| u16::from_le_bytes([b[0], b[1]]))
+			.collect();
+		assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+		assert!(json.contains("\"componentType\":5123,\"count\":6"));
+		assert!(json.contains("\"min\":[0,0,1],\"max\":[2,2,1]"));
+	}
+}
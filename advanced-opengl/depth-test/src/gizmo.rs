@@ -0,0 +1,721 @@
+use crate::gpu;
+use crate::ray::Ray;
+use crate::shader;
+use crate::vertex;
+use cgmath::prelude::*;
+
+// Where an object is, kept in parts so each can be dragged on its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+	pub translation: cgmath::Vector3<f32>,
+	pub rotation: cgmath::Quaternion<f32>,
+	pub scale: cgmath::Vector3<f32>,
+}
+
+impl Transform {
+	pub fn from_translation(translation: cgmath::Vector3<f32>) -> Transform {
+		Transform {
+			translation,
+			rotation: cgmath::Quaternion::one(),
+			scale: cgmath::vec3(1.0, 1.0, 1.0),
+		}
+	}
+
+	pub fn center(&self) -> cgmath::Point3<f32> {
+		cgmath::Point3::from_vec(self.translation)
+	}
+
+	// Scaled, then rotated, then moved.
+	pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+		cgmath::Matrix4::from_translation(self.translation)
+			* cgmath::Matrix4::from(self.rotation)
+			* cgmath::Matrix4::from_nonuniform_scale(
+				self.scale.x,
+				self.scale.y,
+				self.scale.z,
+			)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+	Translate,
+	Rotate,
+	Scale,
+}
+
+impl Mode {
+	pub fn next(self) -> Mode {
+		match self {
+			Mode::Translate => Mode::Rotate,
+			Mode::Rotate => Mode::Scale,
+			Mode::Scale => Mode::Translate,
+		}
+	}
+}
+
+// A part of the gizmo that can be grabbed. Axes are 0 for x, 1 for y and 2
+// for z.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+	// An arrow, a ring or a scale handle, moving, turning or stretching
+	// along that axis only.
+	Axis(usize),
+	// The square facing along the axis, moving across the other two.
+	Plane(usize),
+	// The middle of the scale gizmo, stretching all axes alike.
+	Uniform,
+}
+
+// Steps things move in while snapping. Moves snap by how far they've gone,
+// so an object off the grid stays off it.
+#[derive(Clone, Copy, Debug)]
+pub struct Snap {
+	pub translate: f32,
+	// Degrees.
+	pub rotate: f32,
+	// Of the factor the drag scales by.
+	pub scale: f32,
+}
+
+impl Default for Snap {
+	fn default() -> Snap {
+		Snap {
+			translate: 0.25,
+			rotate: 15.0,
+			scale: 0.1,
+		}
+	}
+}
+
+// Scales can't be dragged through zero.
+const MIN_SCALE: f32 = 0.01;
+// Segments in each rotation ring, for drawing and grabbing alike.
+const RING_SEGMENTS: usize = 48;
+// As fractions of the gizmo's size.
+const GRAB_RADIUS: f32 = 0.08;
+const PLANE_FROM: f32 = 0.25;
+const PLANE_TO: f32 = 0.45;
+const BOX_SIZE: f32 = 0.08;
+// The arrows and scale handles start outside the middle box.
+const AXIS_FROM: f32 = 0.15;
+// Cosine of the view ray with an axis past which it's seen end on, and
+// with a plane's normal under which the plane is seen edge on. Handles
+// there can't be dragged so they can't be grabbed.
+const END_ON: f32 = 0.98;
+const EDGE_ON: f32 = 0.05;
+
+struct Drag {
+	// Switching modes mid-drag doesn't change what the drag does.
+	mode: Mode,
+	handle: Handle,
+	start: Transform,
+	// Fixed for the whole drag, so the gizmo growing or shrinking on screen
+	// doesn't change how far the object goes.
+	size: f32,
+	axes: [cgmath::Vector3<f32>; 3],
+	// Faces the camera, for uniform scaling.
+	normal: cgmath::Vector3<f32>,
+	// Where the handle was grabbed, from the object's centre.
+	grabbed: cgmath::Vector3<f32>,
+}
+
+// Handles for moving, turning and scaling one object at a time. Feed it a
+// ray through the cursor every frame, it writes back into the transform.
+pub struct Gizmo {
+	pub mode: Mode,
+	pub snap: Snap,
+	// Height on screen as a fraction of the view's half height, the same
+	// size however far away the object is.
+	pub screen_size: f32,
+	hovered: Option<Handle>,
+	drag: Option<Drag>,
+	// The grab input as of the last `update`, drags start when it goes
+	// down.
+	grabbing: bool,
+}
+
+impl Default for Gizmo {
+	fn default() -> Gizmo {
+		Gizmo {
+			mode: Mode::Translate,
+			snap: Snap::default(),
+			screen_size: 0.25,
+			hovered: None,
+			drag: None,
+			grabbing: false,
+		}
+	}
+}
+
+impl Gizmo {
+	// In world units at a point, for perspective and orthographic
+	// projections alike.
+	pub fn size(
+		&self,
+		center: cgmath::Point3<f32>,
+		view: &cgmath::Matrix4<f32>,
+		projection: &cgmath::Matrix4<f32>,
+	) -> f32 {
+		let clip = projection * view * center.to_homogeneous();
+		self.screen_size * clip.w.abs().max(1e-3) / projection.y.y
+	}
+
+	// World axes for moving and turning, the object's own for scaling
+	// since that's the way its scale stretches.
+	pub fn axes(&self, transform: &Transform) -> [cgmath::Vector3<f32>; 3] {
+		let unit = [
+			cgmath::Vector3::unit_x(),
+			cgmath::Vector3::unit_y(),
+			cgmath::Vector3::unit_z(),
+		];
+		match self.mode {
+			Mode::Scale => {
+				let mut axes = unit;
+				for axis in axes.iter_mut() {
+					*axis = transform.rotation.rotate_vector(*axis);
+				}
+				axes
+			}
+			_ => unit,
+		}
+	}
+
+	// The handle being dragged, or the one under the cursor otherwise.
+	pub fn active(&self) -> Option<Handle> {
+		match &self.drag {
+			Some(drag) => Some(drag.handle),
+			None => self.hovered,
+		}
+	}
+
+	// The closest handle along the ray.
+	pub fn hit(
+		&self,
+		ray: &Ray,
+		transform: &Transform,
+		size: f32,
+	) -> Option<Handle> {
+		let center = transform.center();
+		let axes = self.axes(transform);
+		let radius = GRAB_RADIUS * size;
+		let direction = ray.direction.normalize();
+		let mut closest: Option<(f32, Handle)> = None;
+		let mut consider = |distance: f32, handle: Handle| match closest {
+			Some((best, _)) if best <= distance => {}
+			_ => closest = Some((distance, handle)),
+		};
+
+		for (i, &axis) in axes.iter().enumerate() {
+			let facing = axis.dot(direction).abs();
+			match self.mode {
+				Mode::Translate | Mode::Scale if facing < END_ON => {
+					let (gap, along) = ray.distance_to_segment(
+						center + axis * (AXIS_FROM * size),
+						center + axis * size,
+					);
+					if gap < radius {
+						consider(along, Handle::Axis(i));
+					}
+				}
+				Mode::Rotate if facing > EDGE_ON => {
+					let ring = ring(center, &axes, i, size);
+					for (&a, &b) in ring.iter().zip(ring.iter().skip(1)) {
+						let (gap, along) = ray.distance_to_segment(a, b);
+						if gap < radius {
+							consider(along, Handle::Axis(i));
+						}
+					}
+				}
+				_ => {}
+			}
+
+			if self.mode == Mode::Translate && facing > EDGE_ON {
+				let (u, v) = (axes[(i + 1) % 3], axes[(i + 2) % 3]);
+				if let Some(along) = ray.intersect_plane(center, axis) {
+					let offset = ray.at(along) - center;
+					let range = (PLANE_FROM * size)..=(PLANE_TO * size);
+					if range.contains(&offset.dot(u))
+						&& range.contains(&offset.dot(v))
+					{
+						consider(along, Handle::Plane(i));
+					}
+				}
+			}
+		}
+
+		if self.mode == Mode::Scale {
+			let (gap, along) = ray.distance_to_segment(center, center);
+			if gap < AXIS_FROM * size {
+				consider(along, Handle::Uniform);
+			}
+		}
+		closest.map(|(_, handle)| handle)
+	}
+
+	// Once a frame with the ray through the cursor and whether the grab
+	// input is held. A drag starts when it goes down over a handle and ends
+	// when it comes up. Returns whether the gizmo took the input, so it
+	// isn't also used to pick.
+	pub fn update(
+		&mut self,
+		ray: &Ray,
+		transform: &mut Transform,
+		size: f32,
+		grabbing: bool,
+		snapping: bool,
+	) -> bool {
+		let pressed = grabbing && !self.grabbing;
+		self.grabbing = grabbing;
+		if !grabbing {
+			self.hovered = self.hit(ray, transform, size);
+			return self.drag.take().is_some();
+		}
+		if pressed {
+			self.hovered = self.hit(ray, transform, size);
+			if let Some(handle) = self.hovered {
+				self.begin(ray, transform, size, handle);
+			}
+		}
+		if let Some(drag) = &self.drag {
+			if let Some(to) = Gizmo::grab_point(drag, ray) {
+				*transform = self.dragged(drag, to, snapping);
+			}
+		}
+		self.drag.is_some()
+	}
+
+	fn begin(
+		&mut self,
+		ray: &Ray,
+		transform: &Transform,
+		size: f32,
+		handle: Handle,
+	) {
+		let mut drag = Drag {
+			mode: self.mode,
+			handle,
+			start: *transform,
+			size,
+			axes: self.axes(transform),
+			normal: -ray.direction.normalize(),
+			grabbed: cgmath::vec3(0.0, 0.0, 0.0),
+		};
+		// Nothing to follow when the ray misses the constraint, e.g. a
+		// plane behind the camera.
+		if let Some(grabbed) = Gizmo::grab_point(&drag, ray) {
+			drag.grabbed = grabbed;
+			self.drag = Some(drag);
+		}
+	}
+
+	// Where the ray meets whatever the handle is constrained to, from the
+	// object's centre where the drag started.
+	fn grab_point(drag: &Drag, ray: &Ray) -> Option<cgmath::Vector3<f32>> {
+		let center = drag.start.center();
+		let plane = |normal| {
+			ray.intersect_plane(center, normal)
+				.map(|along| ray.at(along) - center)
+		};
+		match (drag.mode, drag.handle) {
+			(Mode::Translate, Handle::Axis(i))
+			| (Mode::Scale, Handle::Axis(i)) => ray
+				.closest_on_line(center, drag.axes[i])
+				.map(|point| point - center),
+			(_, Handle::Plane(i)) | (Mode::Rotate, Handle::Axis(i)) => {
+				plane(drag.axes[i])
+			}
+			(_, Handle::Uniform) => plane(drag.normal),
+		}
+	}
+
+	// The start transform changed by dragging from where the handle was
+	// grabbed to `to`.
+	fn dragged(
+		&self,
+		drag: &Drag,
+		to: cgmath::Vector3<f32>,
+		snapping: bool,
+	) -> Transform {
+		let snap = |value: f32, step: f32| {
+			if snapping && step > 0.0 {
+				(value / step).round() * step
+			} else {
+				value
+			}
+		};
+		let from = drag.grabbed;
+		let mut transform = drag.start;
+		match (drag.mode, drag.handle) {
+			(Mode::Translate, Handle::Axis(i)) => {
+				let axis = drag.axes[i];
+				let moved = snap((to - from).dot(axis), self.snap.translate);
+				transform.translation += axis * moved;
+			}
+			(Mode::Translate, Handle::Plane(i)) => {
+				let moved = to - from;
+				for j in [(i + 1) % 3, (i + 2) % 3].iter().copied() {
+					let axis = drag.axes[j];
+					transform.translation +=
+						axis * snap(moved.dot(axis), self.snap.translate);
+				}
+			}
+			(Mode::Rotate, Handle::Axis(i)) => {
+				let axis = drag.axes[i];
+				// Signed, turning from the grabbed point to the cursor.
+				let angle = from.cross(to).dot(axis).atan2(from.dot(to));
+				let angle = snap(angle.to_degrees(), self.snap.rotate);
+				transform.rotation = cgmath::Quaternion::from_axis_angle(
+					axis,
+					cgmath::Deg(angle),
+				) * drag.start.rotation;
+			}
+			(Mode::Scale, Handle::Axis(i)) => {
+				let axis = drag.axes[i];
+				let factor = 1.0 + (to - from).dot(axis) / drag.size;
+				let factor = snap(factor, self.snap.scale);
+				transform.scale[i] =
+					(drag.start.scale[i] * factor).max(MIN_SCALE);
+			}
+			(Mode::Scale, Handle::Uniform) => {
+				let from = from.magnitude();
+				if from > 1e-6 {
+					let factor = snap(to.magnitude() / from, self.snap.scale);
+					for i in 0..3 {
+						transform.scale[i] =
+							(drag.start.scale[i] * factor).max(MIN_SCALE);
+					}
+				}
+			}
+			_ => {}
+		}
+		transform
+	}
+
+	// The gizmo as coloured lines, the active handle highlighted.
+	pub fn lines(
+		&self,
+		transform: &Transform,
+		size: f32,
+	) -> std::vec::Vec<LineVertex> {
+		let center = transform.center();
+		let axes = self.axes(transform);
+		let active = self.active();
+		let mut lines = std::vec::Vec::new();
+		let mut line =
+			|a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color| {
+				lines.push(LineVertex {
+					position: a.into(),
+					color,
+				});
+				lines.push(LineVertex {
+					position: b.into(),
+					color,
+				});
+			};
+
+		for (i, &axis) in axes.iter().enumerate() {
+			let (u, v) = (axes[(i + 1) % 3], axes[(i + 2) % 3]);
+			match self.mode {
+				Mode::Translate => {
+					let color = handle_color(active, Handle::Axis(i), i);
+					let tip = center + axis * size;
+					line(center + axis * (AXIS_FROM * size), tip, color);
+					// Arrowhead.
+					let back = tip - axis * (0.15 * size);
+					for &side in [u, -u, v, -v].iter() {
+						line(tip, back + side * (0.05 * size), color);
+					}
+
+					let color = handle_color(active, Handle::Plane(i), i);
+					let corner = |a: f32, b: f32| {
+						center + u * (a * size) + v * (b * size)
+					};
+					let (from, to) = (PLANE_FROM, PLANE_TO);
+					line(corner(from, from), corner(to, from), color);
+					line(corner(to, from), corner(to, to), color);
+					line(corner(to, to), corner(from, to), color);
+					line(corner(from, to), corner(from, from), color);
+				}
+				Mode::Rotate => {
+					let color = handle_color(active, Handle::Axis(i), i);
+					let ring = ring(center, &axes, i, size);
+					for (&a, &b) in ring.iter().zip(ring.iter().skip(1)) {
+						line(a, b, color);
+					}
+				}
+				Mode::Scale => {
+					let color = handle_color(active, Handle::Axis(i), i);
+					let end = center + axis * size;
+					line(center + axis * (AXIS_FROM * size), end, color);
+					for (a, b) in cube_edges(end, &axes, BOX_SIZE * size) {
+						line(a, b, color);
+					}
+				}
+			}
+		}
+
+		if self.mode == Mode::Scale {
+			let color = if active == Some(Handle::Uniform) {
+				[1.0, 0.9, 0.1]
+			} else {
+				[1.0, 1.0, 1.0]
+			};
+			for (a, b) in cube_edges(center, &axes, BOX_SIZE * size) {
+				line(a, b, color);
+			}
+		}
+		lines
+	}
+}
+
+// Red, green and blue by axis, plane handles by the axis they face.
+fn handle_color(active: Option<Handle>, handle: Handle, i: usize) -> [f32; 3] {
+	if active == Some(handle) {
+		[1.0, 0.9, 0.1]
+	} else {
+		let mut color = [0.0; 3];
+		color[i] = 1.0;
+		color
+	}
+}
+
+// Around axis `i`, closed by repeating the first point.
+fn ring(
+	center: cgmath::Point3<f32>,
+	axes: &[cgmath::Vector3<f32>; 3],
+	i: usize,
+	radius: f32,
+) -> std::vec::Vec<cgmath::Point3<f32>> {
+	let (u, v) = (axes[(i + 1) % 3], axes[(i + 2) % 3]);
+	(0..=RING_SEGMENTS)
+		.map(|segment| {
+			let angle =
+				std::f32::consts::TAU * segment as f32 / RING_SEGMENTS as f32;
+			center + (u * angle.cos() + v * angle.sin()) * radius
+		})
+		.collect()
+}
+
+// The 12 edges of a box lined up with `axes`, `size` across.
+fn cube_edges(
+	center: cgmath::Point3<f32>,
+	axes: &[cgmath::Vector3<f32>; 3],
+	size: f32,
+) -> std::vec::Vec<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+	let corner = |x: f32, y: f32, z: f32| {
+		center + (axes[0] * x + axes[1] * y + axes[2] * z) * (size / 2.0)
+	};
+	let mut edges = std::vec::Vec::new();
+	for &a in [-1.0, 1.0].iter() {
+		for &b in [-1.0, 1.0].iter() {
+			edges.push((corner(-1.0, a, b), corner(1.0, a, b)));
+			edges.push((corner(a, -1.0, b), corner(a, 1.0, b)));
+			edges.push((corner(a, b, -1.0), corner(a, b, 1.0)));
+		}
+	}
+	edges
+}
+
+pub struct LineVertex {
+	pub position: [f32; 3],
+	pub color: [f32; 3],
+}
+
+vertex::vertex_layout!(LineVertex {
+	position: 0,
+	color: 1,
+});
+
+// Draws the gizmo's lines over everything else.
+pub struct GizmoRenderer {
+	vao: gpu::VertexArray,
+	vbo: gpu::Buffer,
+	program: u32,
+}
+
+impl GizmoRenderer {
+	pub fn new() -> GizmoRenderer {
+		let vao = gpu::VertexArray::new();
+		let vbo = gpu::Buffer::new();
+		vao.bind();
+		vbo.bind(gl::ARRAY_BUFFER);
+		vertex::set_attributes::<LineVertex>(0, 0);
+		GizmoRenderer {
+			vao,
+			vbo,
+			program: shader::create_shader("gizmo.vert", "gizmo.frag"),
+		}
+	}
+
+	pub fn draw(
+		&self,
+		gizmo: &Gizmo,
+		transform: &Transform,
+		size: f32,
+		view: cgmath::Matrix4<f32>,
+		projection: cgmath::Matrix4<f32>,
+	) {
+		let lines = gizmo.lines(transform, size);
+		self.vao.bind();
+		self.vbo.bind(gl::ARRAY_BUFFER);
+		unsafe {
+			gl::BufferData(
+				gl::ARRAY_BUFFER,
+				(lines.len() * std::mem::size_of::<LineVertex>())
+					as gl::types::GLsizeiptr,
+				lines.as_ptr() as *const std::os::raw::c_void,
+				gl::DYNAMIC_DRAW,
+			);
+			gl::UseProgram(self.program);
+		}
+		shader::set_mat4(self.program, "view", view);
+		shader::set_mat4(self.program, "projection", projection);
+		unsafe {
+			gl::Disable(gl::DEPTH_TEST);
+			gl::DrawArrays(gl::LINES, 0, lines.len() as i32);
+			gl::Enable(gl::DEPTH_TEST);
+		}
+	}
+}
+
+impl Drop for GizmoRenderer {
+	fn drop(&mut self) {
+		unsafe {
+			gl::DeleteProgram(self.program);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+		Ray {
+			origin: cgmath::Point3::from(origin),
+			direction: cgmath::Vector3::from(direction),
+		}
+	}
+
+	fn close(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) -> bool {
+		(a - b).magnitude() < 1e-4
+	}
+
+	fn drag(mode: Mode, handle: Handle, grabbed: [f32; 3]) -> Drag {
+		Drag {
+			mode,
+			handle,
+			start: Transform::from_translation(cgmath::vec3(1.0, 2.0, 3.0)),
+			size: 2.0,
+			axes: [
+				cgmath::Vector3::unit_x(),
+				cgmath::Vector3::unit_y(),
+				cgmath::Vector3::unit_z(),
+			],
+			normal: cgmath::Vector3::unit_z(),
+			grabbed: grabbed.into(),
+		}
+	}
+
+	fn dragged(drag: &Drag, to: [f32; 3], snapping: bool) -> Transform {
+		Gizmo::default().dragged(drag, to.into(), snapping)
+	}
+
+	#[test]
+	fn axis_translate() {
+		let drag = drag(Mode::Translate, Handle::Axis(0), [0.5, 0.0, 0.0]);
+		// Only the part along the axis counts.
+		let moved = dragged(&drag, [1.8, 0.3, 0.0], false);
+		assert!(close(moved.translation, cgmath::vec3(2.3, 2.0, 3.0)));
+		let snapped = dragged(&drag, [1.8, 0.3, 0.0], true);
+		assert!(close(snapped.translation, cgmath::vec3(2.25, 2.0, 3.0)));
+
+		let to =
+			Gizmo::grab_point(&drag, &ray([1.7, 2.0, 10.0], [0.0, 0.0, -1.0]));
+		assert!(close(to.unwrap(), cgmath::vec3(0.7, 0.0, 0.0)));
+	}
+
+	#[test]
+	fn plane_translate() {
+		let drag = drag(Mode::Translate, Handle::Plane(1), [0.0, 0.0, 0.0]);
+		// Not along the plane's normal.
+		let moved = dragged(&drag, [0.6, 5.0, -0.35], false);
+		assert!(close(moved.translation, cgmath::vec3(1.6, 2.0, 2.65)));
+		let snapped = dragged(&drag, [0.6, 5.0, -0.35], true);
+		assert!(close(snapped.translation, cgmath::vec3(1.5, 2.0, 2.75)));
+
+		let to =
+			Gizmo::grab_point(&drag, &ray([1.5, 10.0, 3.0], [0.0, -1.0, 0.0]));
+		assert!(close(to.unwrap(), cgmath::vec3(0.5, 0.0, 0.0)));
+		// Parallel to the plane.
+		let along =
+			Gizmo::grab_point(&drag, &ray([1.5, 10.0, 3.0], [1.0, 0.0, 0.0]));
+		assert_eq!(along, None);
+	}
+
+	#[test]
+	fn rotation_is_signed() {
+		let drag = drag(Mode::Rotate, Handle::Axis(2), [1.0, 0.0, 0.0]);
+		let turned = |to, snapping| {
+			dragged(&drag, to, snapping)
+				.rotation
+				.rotate_vector(cgmath::Vector3::unit_x())
+		};
+		assert!(close(
+			turned([0.0, 1.0, 0.0], false),
+			cgmath::Vector3::unit_y()
+		));
+		assert!(close(
+			turned([0.0, -1.0, 0.0], false),
+			-cgmath::Vector3::unit_y()
+		));
+
+		let (sin, cos) = 40.0f32.to_radians().sin_cos();
+		let (sin_45, cos_45) = 45.0f32.to_radians().sin_cos();
+		assert!(close(
+			turned([cos, sin, 0.0], false),
+			cgmath::vec3(cos, sin, 0.0)
+		));
+		assert!(close(
+			turned([cos, sin, 0.0], true),
+			cgmath::vec3(cos_45, sin_45, 0.0)
+		));
+	}
+
+	#[test]
+	fn scale_stops_at_min_scale() {
+		let axis = drag(Mode::Scale, Handle::Axis(0), [1.0, 0.0, 0.0]);
+		// A drag as long as the gizmo doubles the scale.
+		let stretched = dragged(&axis, [3.0, 0.0, 0.0], false);
+		assert!(close(stretched.scale, cgmath::vec3(2.0, 1.0, 1.0)));
+		let flipped = dragged(&axis, [-5.0, 0.0, 0.0], false);
+		assert_eq!(flipped.scale, cgmath::vec3(MIN_SCALE, 1.0, 1.0));
+
+		let uniform = drag(Mode::Scale, Handle::Uniform, [1.0, 0.0, 0.0]);
+		let grown = dragged(&uniform, [0.0, 3.0, 0.0], false);
+		assert!(close(grown.scale, cgmath::vec3(3.0, 3.0, 3.0)));
+		let shrunk = dragged(&uniform, [0.0, 1e-3, 0.0], false);
+		assert_eq!(shrunk.scale, cgmath::vec3(MIN_SCALE, MIN_SCALE, MIN_SCALE));
+	}
+
+	#[test]
+	fn drags_follow_the_cursor() {
+		let mut gizmo = Gizmo::default();
+		let mut transform =
+			Transform::from_translation(cgmath::vec3(0.0, 0.0, 0.0));
+		let down = ray([0.6, 0.0, 10.0], [0.0, 0.0, -1.0]);
+		assert_eq!(gizmo.hit(&down, &transform, 1.0), Some(Handle::Axis(0)));
+
+		assert!(gizmo.update(&down, &mut transform, 1.0, true, false));
+		assert_eq!(gizmo.active(), Some(Handle::Axis(0)));
+		let moved = ray([1.6, 0.0, 10.0], [0.0, 0.0, -1.0]);
+		assert!(gizmo.update(&moved, &mut transform, 1.0, true, false));
+		assert!(close(transform.translation, cgmath::vec3(1.0, 0.0, 0.0)));
+
+		// Letting go still counts as the gizmo's input, then it's free.
+		assert!(gizmo.update(&moved, &mut transform, 1.0, false, false));
+		assert!(!gizmo.update(&moved, &mut transform, 1.0, false, false));
+		assert!(close(transform.translation, cgmath::vec3(1.0, 0.0, 0.0)));
+	}
+}
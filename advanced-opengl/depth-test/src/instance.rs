@@ -0,0 +1,61 @@
+use crate::gpu;
+use crate::vertex;
+use cgmath::prelude::*;
+
+// Laid out in the buffer exactly as in memory.
+struct Instance {
+	model: cgmath::Matrix4<f32>,
+	color: cgmath::Vector4<f32>,
+}
+
+vertex::vertex_layout!(Instance { model: 0, color: 4 });
+
+// Adds an instance buffer to the currently bound vertex array. The matrix
+// takes the four locations starting at `location`, one column each, and the
+// colour the one after. It starts out holding a single identity, white
+// instance so plain draw calls through the same vertex array stay in bounds.
+pub fn create_instance_buffer(location: u32) -> gpu::Buffer {
+	let buffer = gpu::Buffer::new();
+	buffer.bind(gl::ARRAY_BUFFER);
+	vertex::set_attributes::<Instance>(location, 1);
+
+	upload_instances(&buffer, &[cgmath::Matrix4::identity()], None);
+	buffer
+}
+
+// Replaces the contents of an instance buffer, instances without a colour
+// are white.
+pub fn upload_instances(
+	buffer: &gpu::Buffer,
+	transforms: &[cgmath::Matrix4<f32>],
+	colors: Option<&[cgmath::Vector4<f32>]>,
+) {
+	if let Some(colors) = colors {
+		assert_eq!(
+			colors.len(),
+			transforms.len(),
+			"One colour per instance transform"
+		);
+	}
+
+	let white = cgmath::vec4(1.0, 1.0, 1.0, 1.0);
+	let data: Vec<Instance> = transforms
+		.iter()
+		.enumerate()
+		.map(|(i, &model)| Instance {
+			model,
+			color: colors.map_or(white, |c| c[i]),
+		})
+		.collect();
+
+	buffer.bind(gl::ARRAY_BUFFER);
+	unsafe {
+		gl::BufferData(
+			gl::ARRAY_BUFFER,
+			(data.len() * std::mem::size_of::<Instance>())
+				as gl::types::GLsizeiptr,
+			data.as_ptr() as *const std::os::raw::c_void,
+			gl::DYNAMIC_DRAW,
+		);
+	}
+}
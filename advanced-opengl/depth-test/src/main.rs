@@ -1,12 +1,37 @@
-#![feature(destructuring_assignment)]
-
 extern crate cgmath;
 extern crate gl;
 extern crate glfw;
 extern crate image;
+extern crate png;
 
+mod atlas;
+mod bounds;
+mod cache;
+mod camera;
+mod cubemap;
+mod dds;
+mod export;
+mod flythrough;
+mod gamepad;
+mod gizmo;
+mod gpu;
+mod input;
+mod instance;
+mod ktx2;
+mod record;
 mod shader;
+mod lod;
 mod model;
+mod optimize;
+mod pick;
+mod ply;
+mod primitives;
+mod ray;
+mod skybox;
+mod stl;
+mod terrain;
+mod texture;
+mod vertex;
 use crate::glfw::Context;
 use cgmath::prelude::*;
 
@@ -32,6 +57,7 @@ fn gl_init() -> (
 	window.set_key_polling(true);
 	window.set_cursor_pos_polling(true);
 	window.set_scroll_polling(true);
+	window.set_mouse_button_polling(true);
 	window.set_framebuffer_size_polling(true);
 	window.set_cursor_mode(glfw::CursorMode::Disabled);
 
@@ -46,121 +72,391 @@ fn gl_init() -> (
 	(glfw, window, events)
 }
 
+// The value after `flag` on the command line, if it's there.
+fn arg(flag: &str) -> Option<std::string::String> {
+	let args: std::vec::Vec<std::string::String> = std::env::args().collect();
+	args.iter()
+		.position(|arg| arg == flag)
+		.and_then(|i| args.get(i + 1))
+		.cloned()
+}
+
 fn main() {
 	let (mut glfw, mut window, events) = gl_init();
-	let mut camera_pos = cgmath::point3(0.0, 0.0, 6.0);
-	let mut camera_front = cgmath::vec3(0.0, 0.0, -1.0);
-	let camera_up = cgmath::vec3(0.0, 1.0, 0.0);
-	let camera_speed = 2.5;
+	let mut camera = camera::Camera::new(cgmath::point3(0.0, 0.0, 6.0));
+	let (width, height) = window.get_framebuffer_size();
+	camera.projection.resize(width, height);
+	let bindings = input::Bindings::load_or_default("bindings.toml");
+	let mut gamepad = gamepad::Gamepad::default();
+	let mut session = record::Session::from_args(&window);
+	let mut flythrough =
+		flythrough::Flythrough::load_or_default("flythrough.txt");
+	let mut wireframe = false;
 	let mut last_frame: f32 = 0.0;
-	let mut first_mouse = true;
-	let mut yaw: f32 = -90.0;
-	let mut pitch: f32 = 0.0;
-	let mut last_x: f32 = 400.0;
-	let mut last_y: f32 = 300.0;
+	let mut walking = true;
+	let eye_height = 1.7;
 
 	let object_shader = shader::create_shader("object.vert", "object.frag");
 
-	let model = model::load_model("../../assets/terrain/terrain.obj");
+	let load_options = model::LoadOptions {
+		optimize: true,
+		lods: Some(lod::LodOptions::default()),
+		cache: false,
+		// The terrain is mostly seen at grazing angles.
+		textures: texture::TextureOptions {
+			anisotropy: 16.0,
+			..texture::TextureOptions::default()
+		},
+		atlas: None,
+	};
+	// `--model <file>` shows an OBJ, PLY or STL file instead of the terrain,
+	// `--heightmap <image>` raises the terrain from an image instead of
+	// noise.
+	let (model, terrain) = match arg("--model") {
+		Some(path) => {
+			let options = model::LoadOptions {
+				cache: true,
+				..load_options
+			};
+			match model::load_model_with(&path, &options) {
+				Ok(model) => (model, None),
+				Err(err) => {
+					eprintln!("Failed to load {}: {}", path, err);
+					std::process::exit(1);
+				}
+			}
+		}
+		None => {
+			let options = terrain::TerrainOptions::default();
+			let terrain = match arg("--heightmap") {
+				Some(path) => match terrain::Terrain::from_heightmap(
+					std::path::Path::new(&path),
+					&options,
+				) {
+					Ok(terrain) => terrain,
+					Err(err) => {
+						eprintln!("Failed to load {}: {}", path, err);
+						std::process::exit(1);
+					}
+				},
+				None => terrain::Terrain::from_noise(1, &options),
+			};
+			let model = model::model_from_meshes(
+				vec![terrain.mesh()],
+				"../../assets/terrain",
+				&load_options,
+			);
+			(model, Some(terrain))
+		}
+	};
+	// `--no-cull` draws every mesh at full detail, to compare against.
+	let cull = !std::env::args().any(|arg| arg == "--no-cull");
+	for report in model.optimize_reports.iter() {
+		println!(
+			"mesh: {} -> {} vertices, {} -> {} triangles, ACMR {:.3} -> {:.3}, \
+			 {} -> {} bytes",
+			report.before.vertices,
+			report.after.vertices,
+			report.before.triangles,
+			report.after.triangles,
+			report.before.acmr,
+			report.after.acmr,
+			report.before.vertex_bytes + report.before.index_bytes,
+			report.after.vertex_bytes + report.after.index_bytes,
+		);
+	}
+
+	// `--shape <name>` places another primitive than cubes.
+	let shape = arg("--shape").unwrap_or_else(|| "cube".into());
+	let cube = model::model_from_meshes(
+		vec![primitives::by_name(&shape).expect("Unknown shape")],
+		"",
+		&model::LoadOptions::default(),
+	);
+	// Resting on the terrain, until moved with the gizmo.
+	let mut cubes: std::vec::Vec<gizmo::Transform> = [(-1.0, -1.0), (2.0, 0.0)]
+		.iter()
+		.map(|&(x, z)| {
+			let ground = terrain.as_ref().map_or(0.0, |t| t.height_at(x, z));
+			let y = ground + 0.5;
+			gizmo::Transform::from_translation(cgmath::vec3(x, y, z))
+		})
+		.collect();
+
+	let sky = skybox::Background::Skybox(match arg("--skybox") {
+		Some(path) => skybox::Skybox::load(&path),
+		None => skybox::Skybox::gradient(),
+	});
+	let flat = skybox::Background::default_color();
+	let mut show_sky = true;
+
+	// Ids for picking: the model's meshes from 1, then the cubes.
+	let first_cube = 1 + model.meshes.len() as u32;
+	let mut picked = ray::NOTHING;
+	let mut pick_requested = false;
+	let mut gpu_picking = false;
+	let mut id_buffer: Option<pick::IdBuffer> = None;
+	let mut cursor = (0.0, 0.0);
+	let mut cursor_free = false;
+	let mut gizmo = gizmo::Gizmo::default();
+	let gizmo_renderer = gizmo::GizmoRenderer::new();
 
 	while !window.should_close() {
-		let current_frame = glfw.get_time() as f32;
+		let frame = match session.next_frame(
+			&glfw,
+			&window,
+			&events,
+			&bindings,
+			&mut gamepad,
+		) {
+			Some(frame) => frame,
+			None => break,
+		};
+		let current_frame = frame.time as f32;
 		let delta_time = current_frame - last_frame;
 		last_frame = current_frame;
 
-		for (_, event) in glfw::flush_messages(&events) {
+		let mut actions = gamepad.triggered();
+		for event in frame.events {
+			actions.extend(bindings.triggered(&event));
 			match event {
-				glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
-					gl::Viewport(0, 0, width, height)
-				},
-				glfw::WindowEvent::Key(
-					glfw::Key::Escape | glfw::Key::Q,
-					_,
-					glfw::Action::Release,
-					_,
-				) => {
-					window.set_should_close(true);
+				glfw::WindowEvent::FramebufferSize(width, height) => {
+					unsafe {
+						gl::Viewport(0, 0, width, height);
+					}
+					camera.projection.resize(width, height);
 				}
 				glfw::WindowEvent::CursorPos(xpos, ypos) => {
-					let (xpos, ypos) = (xpos as f32, ypos as f32);
-					if first_mouse {
-						last_x = xpos;
-						last_y = ypos;
-						first_mouse = false;
+					cursor = (xpos, ypos);
+					if !cursor_free {
+						camera.mouse_moved(xpos as f32, ypos as f32);
 					}
+				}
+				glfw::WindowEvent::Scroll(_, yoffset) => {
+					camera.scroll(yoffset as f32);
+				}
+				_ => {}
+			}
+		}
 
-					let mut xoffset = xpos - last_x;
-					let mut yoffset = last_y - ypos;
-					last_x = xpos;
-					last_y = ypos;
-
-					let sensitivity: f32 = 0.1;
-					xoffset *= sensitivity;
-					yoffset *= sensitivity;
-
-					yaw += xoffset;
-					pitch += yoffset;
-
-					if pitch > 89.0 {
-						pitch = 89.0;
+		for action in actions {
+			match action {
+				input::Action::Quit => {
+					window.set_should_close(true);
+				}
+				input::Action::Export => {
+					let obj = std::path::Path::new("export.obj");
+					let gltf = std::path::Path::new("export.gltf");
+					if let Err(err) = export::write_obj(&model, obj) {
+						eprintln!("Failed to export {}: {}", obj.display(), err);
 					}
-					if pitch < -89.0 {
-						pitch = -89.0;
+					if let Err(err) = export::write_gltf(&model, gltf) {
+						eprintln!(
+							"Failed to export {}: {}",
+							gltf.display(),
+							err
+						);
+					}
+				}
+				input::Action::ToggleWalking => {
+					walking = !walking;
+				}
+				input::Action::ToggleSky => {
+					show_sky = !show_sky;
+				}
+				input::Action::NextCameraMode => {
+					camera.next_mode();
+				}
+				input::Action::ToggleProjection => {
+					camera.toggle_projection();
+				}
+				input::Action::AddKeyframe => {
+					flythrough.add_keyframe(&camera);
+				}
+				input::Action::ClearKeyframes => {
+					flythrough.clear();
+				}
+				input::Action::PlayFlythrough => {
+					flythrough.toggle(&mut camera);
+				}
+				input::Action::Pick => {
+					pick_requested = true;
+				}
+				input::Action::TogglePickMode => {
+					gpu_picking = !gpu_picking;
+				}
+				input::Action::ToggleCursor => {
+					cursor_free = !cursor_free;
+					window.set_cursor_mode(if cursor_free {
+						glfw::CursorMode::Normal
+					} else {
+						glfw::CursorMode::Disabled
+					});
+					camera.reset_mouse();
+				}
+				input::Action::NextGizmoMode => {
+					gizmo.mode = gizmo.mode.next();
+				}
+				input::Action::ToggleWireframe => {
+					wireframe = !wireframe;
+					let mode = if wireframe { gl::LINE } else { gl::FILL };
+					unsafe {
+						gl::PolygonMode(gl::FRONT_AND_BACK, mode);
 					}
-
-					let front = cgmath::Vector3 {
-						x: yaw.to_radians().cos() * pitch.to_radians().cos(),
-						y: pitch.to_radians().sin(),
-						z: yaw.to_radians().sin() * pitch.to_radians().cos(),
-					};
-					camera_front = front.normalize();
 				}
 				_ => {}
 			}
 		}
 
-		if window.get_key(glfw::Key::W) == glfw::Action::Press {
-			camera_pos += camera_speed * camera_front * delta_time;
+		if flythrough.playing() {
+			flythrough.update(&mut camera, delta_time);
+		} else {
+			camera.process_input(&bindings, &frame.held, delta_time);
+			camera.process_gamepad(&gamepad, delta_time);
+			camera.update(delta_time);
 		}
-		if window.get_key(glfw::Key::S) == glfw::Action::Press {
-			camera_pos -= camera_speed * camera_front * delta_time;
-		}
-		if window.get_key(glfw::Key::A) == glfw::Action::Press {
-			camera_pos -=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
-		}
-		if window.get_key(glfw::Key::D) == glfw::Action::Press {
-			camera_pos +=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
+		if let (true, Some(terrain)) =
+			(walking && camera.mode == camera::CameraMode::Fps, &terrain)
+		{
+			camera.position.y =
+				terrain.height_at(camera.position.x, camera.position.z)
+					+ eye_height;
 		}
 
 		unsafe {
-			let view = cgmath::Matrix4::look_at_rh(
-				camera_pos,
-				camera_pos + camera_front,
-				camera_up,
-			);
-			let projection: cgmath::Matrix4<f32> = cgmath::perspective(
-				cgmath::Deg(45.0),
-				800 as f32 / 600 as f32,
-				0.1,
-				100.0,
-			);
+			let view = camera.view();
+			let projection = camera.projection();
+			let model_: cgmath::Matrix4<f32> = cgmath::Matrix4::identity();
+
+			// The middle of the screen while the cursor steers.
+			let ndc = if cursor_free {
+				ray::cursor_ndc(cursor, frame.window_size)
+			} else {
+				cgmath::vec2(0.0, 0.0)
+			};
+			let cursor_ray = ray::Ray::from_ndc(ndc, &(projection * view));
+
+			// The picked cube's gizmo gets the mouse first, letting go of
+			// a handle doesn't pick whatever is under it.
+			let picked_cube = picked.checked_sub(first_cube);
+			if let (Some(i), Some(ray)) = (picked_cube, cursor_ray) {
+				let cube = &mut cubes[i as usize];
+				let size = gizmo.size(cube.center(), &view, &projection);
+				let grabbing =
+					bindings.pressed(input::Action::Pick, &frame.held);
+				let snapping =
+					bindings.pressed(input::Action::Snap, &frame.held);
+				if gizmo.update(&ray, cube, size, grabbing, snapping) {
+					pick_requested = false;
+				}
+			}
+			let cube_transforms: std::vec::Vec<cgmath::Matrix4<f32>> =
+				cubes.iter().map(gizmo::Transform::matrix).collect();
+
+			if pick_requested {
+				pick_requested = false;
+				picked = if gpu_picking {
+					let (width, height) =
+						(camera.projection.width, camera.projection.height);
+					let buffer = id_buffer.get_or_insert_with(|| {
+						pick::IdBuffer::new(width as i32, height as i32)
+					});
+					buffer.resize(width as i32, height as i32);
+					buffer.begin(view, projection);
+					buffer.draw_model(&model, model_, 1);
+					buffer.draw_instances(&cube, &cube_transforms, first_cube);
+					buffer.read(ndc)
+				} else {
+					let hit = cursor_ray.and_then(|ray| {
+						ray::nearest(
+							pick::pick_model(&ray, &model, &model_, 1),
+							pick::pick_instances(
+								&ray,
+								&cube,
+								&cube_transforms,
+								first_cube,
+							),
+						)
+					});
+					hit.map_or(ray::NOTHING, |hit| hit.id)
+				};
+			}
 
-			gl::ClearColor(0.22, 0.18, 0.22, 1.0);
-			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+			let background = if show_sky { &sky } else { &flat };
+			background.clear();
 
 			gl::UseProgram(object_shader);
 
 			shader::set_mat4(object_shader, "projection", projection);
 			shader::set_mat4(object_shader, "view", view);
-			let mut model_: cgmath::Matrix4<f32> = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, -1.75, 0.0));
-			model_ = model_ * cgmath::Matrix4::from_scale(10.0);
 			shader::set_mat4(object_shader, "model", model_);
-			model::draw_model(&model, object_shader);
+			let lod_view = lod::LodView {
+				model: model_,
+				camera_pos: camera.position,
+				projection_scale: lod::projection_scale(
+					&projection,
+					camera.projection.height as f32,
+				),
+				orthographic: camera.projection.kind
+					== camera::ProjectionKind::Orthographic,
+				pixel_error: 1.0,
+			};
+			let frustum =
+				bounds::Frustum::from_matrix(&(projection * view * model_));
+			let stats = if cull {
+				model::draw_model_with(
+					&model,
+					object_shader,
+					Some(&frustum),
+					Some(&lod_view),
+				)
+			} else {
+				model::draw_model(&model, object_shader)
+			};
+
+			shader::set_mat4(
+				object_shader,
+				"model",
+				cgmath::Matrix4::identity(),
+			);
+			// The picked cube is highlighted.
+			let cube_colors: std::vec::Vec<cgmath::Vector4<f32>> = (0
+				..cube_transforms.len())
+				.map(|i| {
+					if first_cube + i as u32 == picked {
+						cgmath::vec4(1.0, 0.8, 0.2, 1.0)
+					} else {
+						cgmath::vec4(1.0, 1.0, 1.0, 1.0)
+					}
+				})
+				.collect();
+			model::draw_model_instanced(
+				&cube,
+				object_shader,
+				&cube_transforms,
+				Some(&cube_colors),
+			);
+			background.draw(view, projection);
+			if let Some(i) = picked.checked_sub(first_cube) {
+				let cube = &cubes[i as usize];
+				let size = gizmo.size(cube.center(), &view, &projection);
+				gizmo_renderer.draw(&gizmo, cube, size, view, projection);
+			}
+
+			window.set_title(&format!(
+				"Learn OpenGL - {} drawn, {} culled",
+				stats.drawn, stats.culled
+			));
 		}
 
 		glfw.poll_events();
 		window.swap_buffers();
 	}
+
+	drop(model);
+	drop(cube);
+	drop((sky, flat));
+	drop(id_buffer);
+	drop(gizmo_renderer);
+	gpu::report_leaks();
 }
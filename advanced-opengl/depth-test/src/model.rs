@@ -1,57 +1,111 @@
-#![allow(deref_nullptr)]
-
 extern crate cgmath;
 extern crate gl;
 extern crate tobj;
 
 use crate::shader::*;
-use crate::image::GenericImage;
+use cgmath::prelude::*;
+use crate::atlas;
+use crate::bounds;
+use crate::cache;
+use crate::gpu;
+use crate::instance;
+use crate::lod;
+use crate::optimize;
+use crate::ply;
+use crate::stl;
+use crate::texture;
+use crate::vertex;
 use std::path::Path;
-
-macro_rules! offset_of {
-    ($ty:ty, $field:ident) => {
-        &(*(std::ptr::null() as *const $ty)).$field as *const _ as usize
-    }
-}
+use std::rc::Rc;
 
 #[derive(Clone)]
-struct Vertex {
-	position: cgmath::Vector3<f32>,
-	normal: cgmath::Vector3<f32>,
-	tex_coords: cgmath::Vector2<f32>,
+pub struct Vertex {
+	pub position: cgmath::Vector3<f32>,
+	pub normal: cgmath::Vector3<f32>,
+	pub tex_coords: cgmath::Vector2<f32>,
+	pub color: cgmath::Vector4<f32>,
+	// Points along increasing u, w is the handedness of the bitangent.
+	pub tangent: cgmath::Vector4<f32>,
 }
 
+vertex::vertex_layout!(Vertex { position: 0, normal: 1, tex_coords: 2, color: 3, tangent: 4 });
+
 #[derive(Clone)]
 struct Texture {
-	id: u32,
+	texture: Rc<gpu::Texture2D>,
 	type_: std::string::String,
 	path: std::string::String,
 }
 
 #[derive(Default, Clone)]
-struct Mesh {
-	vertices: std::vec::Vec<Vertex>,
-	indices: std::vec::Vec<u32>,
+pub struct Material {
+	pub name: std::string::String,
+	pub ambient: [f32; 3],
+	pub diffuse: [f32; 3],
+	pub specular: [f32; 3],
+	pub shininess: f32,
+	pub dissolve: f32,
+	pub diffuse_texture: std::string::String,
+	pub specular_texture: std::string::String,
+	pub normal_texture: std::string::String,
+}
+
+#[derive(Clone)]
+pub struct Lod {
+	pub indices: std::vec::Vec<u32>,
+	pub error: f32,
+	pub offset: usize,
+}
+
+// The GL side of a mesh, created by `setup_mesh`.
+struct MeshBuffers {
+	vao: gpu::VertexArray,
+	// Kept alive for as long as the vertex array refers to them.
+	_vbo: gpu::Buffer,
+	_ebo: gpu::Buffer,
+	instances: gpu::Buffer,
+}
+
+#[derive(Default)]
+pub struct Mesh {
+	pub vertices: std::vec::Vec<Vertex>,
+	pub indices: std::vec::Vec<u32>,
+	pub index_type: u32,
+	pub lods: std::vec::Vec<Lod>,
+	pub aabb: bounds::Aabb,
+	pub sphere: bounds::Sphere,
+	pub material: Option<Material>,
 	textures: std::vec::Vec<Texture>,
-	vao: u32,
-	vbo: u32,
-	ebo: u32,
+	buffers: Option<MeshBuffers>,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default)]
 pub struct Model {
-	meshes: std::vec::Vec<Mesh>,
+	pub meshes: std::vec::Vec<Mesh>,
 	textures_loaded: std::vec::Vec<Texture>,
-	directory: std::string::String,
+	pub directory: std::string::String,
+	pub optimize_reports: std::vec::Vec<optimize::OptimizeReport>,
+}
+
+#[derive(Default, Clone)]
+pub struct LoadOptions {
+	pub optimize: bool,
+	pub lods: Option<lod::LodOptions>,
+	pub cache: bool,
+	// Adjusted per texture role, see `TextureOptions::for_role`.
+	pub textures: texture::TextureOptions,
+	// Packs the diffuse-only materials' images into one texture so their
+	// meshes draw without rebinding, see `atlas_diffuse_textures`.
+	pub atlas: Option<atlas::AtlasOptions>,
 }
 
-fn draw_mesh(mesh: &Mesh, shader: u32) {
+fn bind_mesh_textures(mesh: &Mesh, shader: u32) {
 	let mut diffuse_nr: u32 = 1;
 	let mut specular_nr: u32 = 1;
 
 	for (i, texture) in mesh.textures.iter().enumerate() {
 		unsafe {
-			gl::ActiveTexture(gl::TEXTURE0 as u32 + i as u32);
+			gl::ActiveTexture(gl::TEXTURE0 + i as u32);
 			let number;
 			if texture.type_ == "diffuse" {
 				number = diffuse_nr;
@@ -64,68 +118,261 @@ fn draw_mesh(mesh: &Mesh, shader: u32) {
 				number = 0;
 			}
 			set_int(shader, &format!("material.texture_{}{}", texture.type_, number), i as i32);
-			gl::BindTexture(gl::TEXTURE_2D, texture.id);
+			texture.texture.bind();
 		}
 	}
 	unsafe {
 		gl::ActiveTexture(gl::TEXTURE0);
-		gl::BindVertexArray(mesh.vao);
-		gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, gl::UNSIGNED_INT, std::ptr::null());
+	}
+}
+
+// Meshes that share an atlas, or just the same material, can skip
+// rebinding when drawn one after the other.
+fn same_textures(a: &Mesh, b: &Mesh) -> bool {
+	a.textures.len() == b.textures.len() && a.textures.iter().zip(b.textures.iter()).all(|(a, b)| Rc::ptr_eq(&a.texture, &b.texture) && a.type_ == b.type_)
+}
+
+fn mesh_buffers(mesh: &Mesh) -> &MeshBuffers {
+	mesh.buffers.as_ref().expect("Mesh was drawn before setup_mesh")
+}
+
+pub fn draw_mesh(mesh: &Mesh, shader: u32, level: Option<usize>, bind_textures: bool) {
+	if bind_textures {
+		bind_mesh_textures(mesh, shader);
+	}
+	mesh_buffers(mesh).vao.bind();
+	unsafe {
+		match level {
+			Some(level) => {
+				let lod = &mesh.lods[level];
+				gl::DrawElements(gl::TRIANGLES, lod.indices.len() as i32, mesh.index_type, lod.offset as *const std::os::raw::c_void);
+			}
+			None => gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, mesh.index_type, std::ptr::null()),
+		}
+	}
+}
+
+fn draw_mesh_instanced(mesh: &Mesh, shader: u32, count: usize) {
+	bind_mesh_textures(mesh, shader);
+	mesh_buffers(mesh).vao.bind();
+	unsafe {
+		gl::DrawElementsInstanced(gl::TRIANGLES, mesh.indices.len() as i32, mesh.index_type, std::ptr::null(), count as i32);
 	}
 }
 
 	
 	
 	
-fn setup_mesh(vertices: std::vec::Vec<Vertex>, indices: std::vec::Vec<u32>, textures: std::vec::Vec<Texture>) -> Mesh {
-	let mut mesh: Mesh = Mesh::default();
-	mesh.vertices = vertices;
-	mesh.indices = indices;
-	mesh.textures = textures;
-	unsafe {
-		gl::GenVertexArrays(1, &mut mesh.vao);
-		gl::GenBuffers(1, &mut mesh.vbo);
-		gl::GenBuffers(1, &mut mesh.ebo);
+pub fn setup_mesh(mut mesh: Mesh) -> Mesh {
+	// Every level of detail shares the vertex buffer, their index lists are
+	// appended to the full-detail ones in a single element buffer.
+	let mut all_indices = mesh.indices.clone();
+	let index_size = optimize::index_size(mesh.index_type);
+	for lod in mesh.lods.iter_mut() {
+		lod.offset = all_indices.len() * index_size;
+		all_indices.extend_from_slice(&lod.indices);
+	}
 
-		gl::BindVertexArray(mesh.vao);
+	let vao = gpu::VertexArray::new();
+	let vbo = gpu::Buffer::new();
+	let ebo = gpu::Buffer::new();
 
-		gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
+	vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	ebo.bind(gl::ELEMENT_ARRAY_BUFFER);
+	unsafe {
 		let size = (mesh.vertices.len() *  std::mem::size_of::<Vertex>()) as isize;
 		let data = &mesh.vertices[0] as *const Vertex as *const std::os::raw::c_void;
 		gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
 
-		gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
-		let size = (mesh.indices.len() * std::mem::size_of::<u32>()) as isize;
-		let data = &mesh.indices[0] as *const u32 as *const std::os::raw::c_void;
-		gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+		if mesh.index_type == gl::UNSIGNED_SHORT {
+			let short_indices: std::vec::Vec<u16> = all_indices.iter().map(|&i| i as u16).collect();
+			let size = (short_indices.len() * std::mem::size_of::<u16>()) as isize;
+			let data = &short_indices[0] as *const u16 as *const std::os::raw::c_void;
+			gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+		} else {
+			let size = (all_indices.len() * std::mem::size_of::<u32>()) as isize;
+			let data = &all_indices[0] as *const u32 as *const std::os::raw::c_void;
+			gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+		}
+	}
+	vertex::set_attributes::<Vertex>(0, 0);
 
-		let size = std::mem::size_of::<Vertex>() as i32;
-		gl::EnableVertexAttribArray(0);
-		gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, position) as *const std::os::raw::c_void);
+	let instances = instance::create_instance_buffer(5);
+	unsafe {
+		gl::BindVertexArray(0);
+	}
 
-		gl::EnableVertexAttribArray(1);
-		gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, normal) as *const std::os::raw::c_void);
+	mesh.buffers = Some(MeshBuffers { vao, _vbo: vbo, _ebo: ebo, instances });
+	mesh
+}
 
-		gl::EnableVertexAttribArray(2);
-		gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coords) as *const std::os::raw::c_void);
+pub fn draw_model(model: &Model, shader: u32) -> bounds::CullStats {
+	draw_model_with(model, shader, None, None)
+}
 
-		gl::BindVertexArray(0);
+// Draws every mesh once per transform in a single call, each instance is
+// placed by `model * transforms[i]` and tinted by `colors[i]` when given.
+pub fn draw_model_instanced(model: &Model, shader: u32, transforms: &[cgmath::Matrix4<f32>], colors: Option<&[cgmath::Vector4<f32>]>) {
+	if transforms.is_empty() {
+		return;
 	}
-	mesh
+	set_int(shader, "instanced", 1);
+	for mesh in model.meshes.iter() {
+		instance::upload_instances(&mesh_buffers(mesh).instances, transforms, colors);
+		draw_mesh_instanced(mesh, shader, transforms.len());
+	}
+	set_int(shader, "instanced", 0);
 }
 
-pub fn draw_model(model: &Model, shader: u32) {
+// `frustum` has to be extracted from projection * view * model so the planes
+// are in the same space as the mesh bounds.
+pub fn draw_model_with(model: &Model, shader: u32, frustum: Option<&bounds::Frustum>, lod_view: Option<&lod::LodView>) -> bounds::CullStats {
+	let mut stats = bounds::CullStats::default();
+	let mut previous: Option<&Mesh> = None;
 	for mesh in model.meshes.iter() {
-		draw_mesh(mesh, shader);
+		if let Some(frustum) = frustum {
+			if !frustum.intersects_sphere(&mesh.sphere) || !frustum.intersects_aabb(&mesh.aabb) {
+				stats.culled += 1;
+				continue;
+			}
+		}
+
+		let level = match lod_view {
+			Some(view) if !mesh.lods.is_empty() => {
+				let errors: std::vec::Vec<f32> = mesh.lods.iter().map(|lod| lod.error).collect();
+				lod::select_level(&errors, mesh.sphere.center, view)
+			}
+			_ => None,
+		};
+		draw_mesh(mesh, shader, level, previous.filter(|previous| same_textures(previous, mesh)).is_none());
+		previous = Some(mesh);
+		stats.drawn += 1;
+	}
+	stats
+}
+
+// Wraps meshes built in code, e.g. by `primitives` or `terrain`, in a
+// drawable model. Material textures are looked up in `directory` and the
+// options apply as they would to a file, except that nothing is cached.
+pub fn model_from_meshes(meshes: std::vec::Vec<Mesh>, directory: &str, options: &LoadOptions) -> Model {
+	let mut model = Model { directory: directory.into(), ..Model::default() };
+	let meshes = meshes.into_iter().filter(|mesh| !is_empty(mesh)).map(|mesh| process_mesh(mesh, options, &mut model.optimize_reports)).collect();
+	add_meshes(&mut model, meshes, options);
+	model
+}
+
+pub fn load_model_with(path: &str, options: &LoadOptions) -> std::io::Result<Model> {
+	let mut loaded_model = Model::default();
+	let obj_path = Path::new(path);
+	loaded_model.directory = obj_path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+
+	let meshes = load_meshes(path, options, &mut loaded_model.optimize_reports)?;
+	add_meshes(&mut loaded_model, meshes, options);
+	Ok(loaded_model)
+}
+
+// The meshes of a file, ready for `setup_mesh`, from the cache when it's
+// up to date and otherwise parsed and processed.
+pub(crate) fn load_meshes(path: &str, options: &LoadOptions, reports: &mut std::vec::Vec<optimize::OptimizeReport>) -> std::io::Result<std::vec::Vec<Mesh>> {
+	let obj_path = Path::new(path);
+	if options.cache {
+		if let Some(meshes) = cache::read(obj_path, options) {
+			println!("{}: {} meshes loaded from the cache", path, meshes.len());
+			return Ok(meshes);
+		}
+	}
+
+	let extension = obj_path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+	let raw_meshes = match extension.as_str() {
+		"ply" => ply::load_ply(obj_path)?,
+		"stl" => stl::load_stl(obj_path)?,
+		_ => parse_obj(path),
+	};
+	let meshes: std::vec::Vec<Mesh> = raw_meshes.into_iter().filter(|mesh| !is_empty(mesh)).map(|mesh| process_mesh(mesh, options, reports)).collect();
+	if options.cache {
+		if let Err(err) = cache::write(obj_path, options, &meshes) {
+			eprintln!("Failed to write mesh cache for {}: {}", path, err);
+		}
+	}
+	Ok(meshes)
+}
+
+// A file without faces, or an object without any in one, leaves nothing
+// to draw and nothing `setup_mesh` could upload.
+fn is_empty(mesh: &Mesh) -> bool {
+	mesh.vertices.is_empty() || mesh.indices.is_empty()
+}
+
+fn add_meshes(model: &mut Model, mut meshes: std::vec::Vec<Mesh>, options: &LoadOptions) {
+	let atlased = match &options.atlas {
+		Some(atlas_options) => atlas_diffuse_textures(model, &mut meshes, &options.textures, atlas_options),
+		None => vec![false; meshes.len()],
+	};
+	for (mut mesh, atlased) in meshes.into_iter().zip(atlased) {
+		if let (Some(material), false) = (mesh.material.clone(), atlased) {
+			mesh.textures = load_mesh_textures(model, &material, &options.textures);
+		}
+		model.meshes.push(setup_mesh(mesh));
 	}
 }
 
-pub fn load_model(path: &str) -> Model {
+// Meshes whose material has nothing but a diffuse image, and whose texture
+// coordinates stay within it, get their coordinates remapped into a shared
+// atlas. Returns which meshes were, the rest load their textures as usual.
+fn atlas_diffuse_textures(model: &mut Model, meshes: &mut [Mesh], options: &texture::TextureOptions, atlas_options: &atlas::AtlasOptions) -> std::vec::Vec<bool> {
+	let mut paths: std::vec::Vec<std::string::String> = std::vec::Vec::new();
+	let mut image_of = vec![None; meshes.len()];
+	for (i, mesh) in meshes.iter().enumerate() {
+		let path = match &mesh.material {
+			Some(material) if material.specular_texture.is_empty() && material.normal_texture.is_empty() => &material.diffuse_texture,
+			_ => continue,
+		};
+		// Compressed files keep their own blocks and mip levels.
+		let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+		if path.is_empty() || extension == "dds" || extension == "ktx2" || !atlas::can_remap(mesh) {
+			continue;
+		}
+		image_of[i] = Some(match paths.iter().position(|p| p == path) {
+			Some(index) => index,
+			None => {
+				paths.push(path.clone());
+				paths.len() - 1
+			}
+		});
+	}
+	// A single image gains nothing from an atlas.
+	if paths.len() < 2 {
+		return vec![false; meshes.len()];
+	}
+
+	let images: std::vec::Vec<texture::Image> = paths.iter().map(|path| texture::Image::open(Path::new(&format!("{}/{}", model.directory, path)))).collect();
+	let atlas = match atlas::build_atlas(&images, atlas_options, &options.for_role("diffuse")) {
+		Some(atlas) => atlas,
+		None => {
+			eprintln!("{} diffuse textures don't fit in a {} texel atlas", paths.len(), atlas_options.max_size);
+			return vec![false; meshes.len()];
+		}
+	};
+	let texture = Texture {
+		texture: Rc::new(atlas.texture),
+		type_: "diffuse".into(),
+		path: "atlas".into(),
+	};
+
+	for (mesh, image) in meshes.iter_mut().zip(image_of.iter()) {
+		if let Some(image) = image {
+			atlas::remap_tex_coords(mesh, &atlas.regions[*image]);
+			mesh.textures = vec![texture.clone()];
+		}
+	}
+	image_of.iter().map(|image| image.is_some()).collect()
+}
+
+fn parse_obj(path: &str) -> std::vec::Vec<Mesh> {
 	let obj = tobj::load_obj(path, true);
 	let (models, materials) = obj.unwrap();
-	let mut loaded_model = Model::default();
-	let path = Path::new(path);
-	loaded_model.directory = path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+	let mut meshes = std::vec::Vec::with_capacity(models.len());
 
 	for model in models {
 		let mesh = &model.mesh;
@@ -140,42 +387,151 @@ pub fn load_model(path: &str) -> Model {
 				position: cgmath::vec3(p[i*3], p[i*3+1], p[i*3+2]),
 				normal: cgmath::vec3(n[i*3], n[i*3+1], n[i*3+2]),
 				tex_coords: cgmath::vec2(t[i*2], t[i*2+1]),
+				color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+				tangent: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
 			})
 		}
 
-		let mut textures: std::vec::Vec<Texture> = std::vec::Vec::new();
-		if let Some(material_id) = mesh.material_id {
+		let material = mesh.material_id.map(|material_id| {
 			let material = &materials[material_id];
-
-			if !material.diffuse_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.diffuse_texture, "diffuse");
-				textures.push(texture);
+			Material {
+				name: material.name.clone(),
+				ambient: material.ambient,
+				diffuse: material.diffuse,
+				specular: material.specular,
+				shininess: material.shininess,
+				dissolve: material.dissolve,
+				diffuse_texture: material.diffuse_texture.clone(),
+				specular_texture: material.specular_texture.clone(),
+				normal_texture: material.normal_texture.clone(),
 			}
+		});
 
-			if !material.specular_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.specular_texture, "specular");
-				textures.push(texture);
-			}
+		meshes.push(Mesh { vertices, indices, index_type: gl::UNSIGNED_INT, material, ..Mesh::default() });
+	}
 
-			if !material.normal_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.normal_texture, "normal");
-				textures.push(texture);
-			}
+	meshes
+}
+
+// Optimisation, levels of detail and bounds, shared by every file format.
+fn process_mesh(mut mesh: Mesh, options: &LoadOptions, reports: &mut std::vec::Vec<optimize::OptimizeReport>) -> Mesh {
+	if options.optimize {
+		let optimized = optimize::optimize(std::mem::take(&mut mesh.vertices), std::mem::take(&mut mesh.indices));
+		reports.push(optimized.report);
+		mesh.vertices = optimized.vertices;
+		mesh.indices = optimized.indices;
+		mesh.index_type = optimized.index_type;
+	}
+	// Computed tangents always have a handedness, so w = 0 means none yet.
+	if mesh.vertices.iter().all(|v| v.tangent.w == 0.0) {
+		compute_tangents(&mut mesh.vertices, &mesh.indices);
+	}
+
+	if let Some(lod_options) = &options.lods {
+		let levels = lod::generate_lods(&mesh.vertices, &mesh.indices, lod_options);
+		for level in levels {
+			let indices = if options.optimize {
+				optimize::optimize_vertex_cache(&level.indices, mesh.vertices.len())
+			} else {
+				level.indices
+			};
+			mesh.lods.push(Lod { indices, error: level.error, offset: 0 });
 		}
+	}
 
-		loaded_model.meshes.push(setup_mesh(vertices, indices, textures));
+	compute_bounds(&mut mesh);
+	mesh
+}
+
+pub fn compute_bounds(mesh: &mut Mesh) {
+	let positions = mesh.vertices.iter().map(|v| cgmath::Point3::from_vec(v.position));
+	mesh.aabb = bounds::Aabb::from_points(positions.clone());
+	mesh.sphere = bounds::Sphere::from_points(positions);
+}
+
+// Area weighted smooth normals, for formats that don't store any.
+pub fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+	for vertex in vertices.iter_mut() {
+		vertex.normal = cgmath::vec3(0.0, 0.0, 0.0);
+	}
+	for tri in indices.chunks(3) {
+		let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+		let normal = (vertices[b].position - vertices[a].position).cross(vertices[c].position - vertices[a].position);
+		vertices[a].normal += normal;
+		vertices[b].normal += normal;
+		vertices[c].normal += normal;
+	}
+	for vertex in vertices.iter_mut() {
+		if vertex.normal.magnitude2() > 0.0 {
+			vertex.normal = vertex.normal.normalize();
+		}
+	}
+}
+
+// Per-vertex tangents from the texture coordinates (Lengyel 2001), summed
+// over the triangles around each vertex and made orthogonal to its normal.
+// Vertices without usable texture coordinates get any perpendicular.
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+	let zero = cgmath::vec3(0.0, 0.0, 0.0);
+	let mut tangents = vec![zero; vertices.len()];
+	let mut bitangents = vec![zero; vertices.len()];
+	for tri in indices.chunks(3) {
+		let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+		let (e1, e2) = (vertices[b].position - vertices[a].position, vertices[c].position - vertices[a].position);
+		let (d1, d2) = (vertices[b].tex_coords - vertices[a].tex_coords, vertices[c].tex_coords - vertices[a].tex_coords);
+		let det = d1.x * d2.y - d2.x * d1.y;
+		if det.abs() < 1e-12 {
+			continue;
+		}
+		let tangent = (e1 * d2.y - e2 * d1.y) / det;
+		let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+		for &i in [a, b, c].iter() {
+			tangents[i] += tangent;
+			bitangents[i] += bitangent;
+		}
+	}
+
+	for (i, vertex) in vertices.iter_mut().enumerate() {
+		let n = vertex.normal;
+		let mut t = tangents[i] - n * n.dot(tangents[i]);
+		if t.magnitude2() < 1e-12 {
+			let axis = if n.x.abs() < 0.9 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+			t = axis - n * n.dot(axis);
+		}
+		let t = t.normalize();
+		let w = if n.cross(t).dot(bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+		vertex.tangent = t.extend(w);
+	}
+}
+
+fn load_mesh_textures(model: &mut Model, material: &Material, options: &texture::TextureOptions) -> std::vec::Vec<Texture> {
+	let mut textures: std::vec::Vec<Texture> = std::vec::Vec::new();
+
+	if !material.diffuse_texture.is_empty() {
+		let texture = load_material_texture(model, &material.diffuse_texture, "diffuse", options);
+		textures.push(texture);
+	}
+
+	if !material.specular_texture.is_empty() {
+		let texture = load_material_texture(model, &material.specular_texture, "specular", options);
+		textures.push(texture);
 	}
 
-	loaded_model
+	if !material.normal_texture.is_empty() {
+		let texture = load_material_texture(model, &material.normal_texture, "normal", options);
+		textures.push(texture);
+	}
+
+	textures
 }
 
-fn load_material_texture(model: &mut Model, path: &str, type_name: &str) -> Texture {
+fn load_material_texture(model: &mut Model, path: &str, type_name: &str, options: &texture::TextureOptions) -> Texture {
 	let texture = model.textures_loaded.iter().find(|t| t.path == path);
 	if let Some(texture) = texture {
 		return texture.clone();
 	}
 	let texture = Texture {
-		id: texture_from_file(path, &model.directory),
+		texture: Rc::new(texture::load_texture(&format!("{}/{}", model.directory, path), &options.for_role(type_name))),
 		type_: type_name.into(),
 		path: path.into(),
 	};
@@ -183,34 +539,4 @@ fn load_material_texture(model: &mut Model, path: &str, type_name: &str) -> Text
 	model.textures_loaded.push(texture.clone());
 
 	texture
-}
-
-fn texture_from_file(path: &str, _pp: &str) -> u32 {
-	let mut texture_id = 0;
-	let path = format!("{}/{}", _pp, path);
-	let img = image::open(path).expect("Texture failed to load");
-	// let img = img.flipv();
-	let format = match img {
-		image::ImageLuma8(_) => gl::RED,
-		image::ImageLumaA8(_) => gl::RG,
-		image::ImageRgb8(_) => gl::RGB,
-		image::ImageRgba8(_) => gl::RGBA,
-	};
-
-	let data = img.raw_pixels();
-
-	unsafe {
-		gl::GenTextures(1, &mut texture_id);
-
-		gl::BindTexture(gl::TEXTURE_2D, texture_id);
-		gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, img.width() as i32, img.height() as i32, 0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const std::os::raw::c_void);
-		gl::GenerateMipmap(gl::TEXTURE_2D);
-
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-	}
-
-	texture_id
 }
\ No newline at end of file
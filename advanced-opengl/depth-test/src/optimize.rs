@@ -0,0 +1,455 @@
+use crate::model::Vertex;
+use gl::types::*;
+
+// Cache model used by the Forsyth reordering and the size of the FIFO cache
+// the ACMR statistic is measured against.
+const FORSYTH_CACHE_SIZE: usize = 32;
+const STATS_CACHE_SIZE: usize = 16;
+
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRI_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeshStats {
+	pub vertices: usize,
+	pub triangles: usize,
+	pub acmr: f32,
+	pub vertex_bytes: usize,
+	pub index_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OptimizeReport {
+	pub before: MeshStats,
+	pub after: MeshStats,
+}
+
+pub struct OptimizedMesh {
+	pub vertices: std::vec::Vec<Vertex>,
+	pub indices: std::vec::Vec<u32>,
+	pub index_type: GLenum,
+	pub report: OptimizeReport,
+}
+
+pub fn optimize(
+	vertices: std::vec::Vec<Vertex>,
+	indices: std::vec::Vec<u32>,
+) -> OptimizedMesh {
+	let before = mesh_stats(&vertices, &indices, gl::UNSIGNED_INT);
+
+	let (vertices, indices) = weld_vertices(&vertices, &indices);
+	let indices = optimize_vertex_cache(&indices, vertices.len());
+	let (vertices, indices) = optimize_vertex_fetch(&vertices, &indices);
+	let index_type = index_type_for(vertices.len());
+
+	let after = mesh_stats(&vertices, &indices, index_type);
+
+	OptimizedMesh {
+		vertices,
+		indices,
+		index_type,
+		report: OptimizeReport { before, after },
+	}
+}
+
+pub fn index_type_for(vertex_count: usize) -> GLenum {
+	if vertex_count <= u16::MAX as usize {
+		gl::UNSIGNED_SHORT
+	} else {
+		gl::UNSIGNED_INT
+	}
+}
+
+pub fn index_size(index_type: GLenum) -> usize {
+	match index_type {
+		gl::UNSIGNED_SHORT => std::mem::size_of::<u16>(),
+		_ => std::mem::size_of::<u32>(),
+	}
+}
+
+pub fn mesh_stats(
+	vertices: &[Vertex],
+	indices: &[u32],
+	index_type: GLenum,
+) -> MeshStats {
+	MeshStats {
+		vertices: vertices.len(),
+		triangles: indices.len() / 3,
+		acmr: acmr(indices, STATS_CACHE_SIZE),
+		vertex_bytes: std::mem::size_of_val(vertices),
+		index_bytes: indices.len() * index_size(index_type),
+	}
+}
+
+// Average cache miss ratio: vertex shader invocations per triangle for a FIFO
+// post-transform cache of the given size. 3.0 is the worst case, 0.5 the
+// ideal for large regular grids.
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+	let triangles = indices.len() / 3;
+	if triangles == 0 {
+		return 0.0;
+	}
+
+	let mut cache = std::collections::VecDeque::with_capacity(cache_size + 1);
+	let mut misses = 0;
+	for &index in indices {
+		if !cache.contains(&index) {
+			misses += 1;
+			cache.push_back(index);
+			if cache.len() > cache_size {
+				cache.pop_front();
+			}
+		}
+	}
+
+	misses as f32 / triangles as f32
+}
+
+fn vertex_key(vertex: &Vertex) -> [u32; 16] {
+	// Adding 0.0 folds -0.0 into 0.0 so they hash the same.
+	let bits = |x: f32| (x + 0.0).to_bits();
+	[
+		bits(vertex.position.x),
+		bits(vertex.position.y),
+		bits(vertex.position.z),
+		bits(vertex.normal.x),
+		bits(vertex.normal.y),
+		bits(vertex.normal.z),
+		bits(vertex.tex_coords.x),
+		bits(vertex.tex_coords.y),
+		bits(vertex.color.x),
+		bits(vertex.color.y),
+		bits(vertex.color.z),
+		bits(vertex.color.w),
+		bits(vertex.tangent.x),
+		bits(vertex.tangent.y),
+		bits(vertex.tangent.z),
+		bits(vertex.tangent.w),
+	]
+}
+
+pub fn weld_vertices(
+	vertices: &[Vertex],
+	indices: &[u32],
+) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+	let mut unique: std::collections::HashMap<[u32; 16], u32> =
+		std::collections::HashMap::with_capacity(vertices.len());
+	let mut welded: std::vec::Vec<Vertex> =
+		std::vec::Vec::with_capacity(vertices.len());
+
+	let remap: std::vec::Vec<u32> = vertices
+		.iter()
+		.map(|vertex| {
+			*unique.entry(vertex_key(vertex)).or_insert_with(|| {
+				welded.push(vertex.clone());
+				(welded.len() - 1) as u32
+			})
+		})
+		.collect();
+
+	let indices = indices.iter().map(|&i| remap[i as usize]).collect();
+
+	(welded, indices)
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+	if remaining == 0 {
+		return -1.0;
+	}
+
+	let mut score = match cache_position {
+		Some(position) if position < 3 => LAST_TRI_SCORE,
+		Some(position) => {
+			let scaler = 1.0 / (FORSYTH_CACHE_SIZE - 3) as f32;
+			(1.0 - (position - 3) as f32 * scaler).powf(CACHE_DECAY_POWER)
+		}
+		None => 0.0,
+	};
+	score +=
+		VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER);
+
+	score
+}
+
+// Tom Forsyth's "Linear-Speed Vertex Cache Optimisation": greedily emits the
+// triangle whose vertices score highest against a simulated LRU cache.
+pub fn optimize_vertex_cache(
+	indices: &[u32],
+	vertex_count: usize,
+) -> std::vec::Vec<u32> {
+	let triangle_count = indices.len() / 3;
+
+	let mut remaining = vec![0u32; vertex_count];
+	for &index in indices {
+		remaining[index as usize] += 1;
+	}
+
+	// Per-vertex lists of triangles that still have to be emitted, packed in
+	// one array. The first `remaining[v]` entries of each list are live.
+	let mut offsets = vec![0usize; vertex_count + 1];
+	for v in 0..vertex_count {
+		offsets[v + 1] = offsets[v] + remaining[v] as usize;
+	}
+	let mut adjacency = vec![0usize; indices.len()];
+	let mut fill = offsets.clone();
+	for (triangle, corners) in indices.chunks(3).enumerate() {
+		for &v in corners {
+			adjacency[fill[v as usize]] = triangle;
+			fill[v as usize] += 1;
+		}
+	}
+
+	let mut cache_position: std::vec::Vec<Option<usize>> =
+		vec![None; vertex_count];
+	let mut vertex_scores: std::vec::Vec<f32> = (0..vertex_count)
+		.map(|v| vertex_score(None, remaining[v]))
+		.collect();
+	let mut triangle_scores: std::vec::Vec<f32> = indices
+		.chunks(3)
+		.map(|corners| corners.iter().map(|&v| vertex_scores[v as usize]).sum())
+		.collect();
+	let mut emitted = vec![false; triangle_count];
+
+	let mut cache: std::vec::Vec<u32> =
+		std::vec::Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+	let mut output = std::vec::Vec::with_capacity(triangle_count * 3);
+	let mut best: Option<usize> = None;
+	let mut next_unemitted = 0;
+
+	for _ in 0..triangle_count {
+		let triangle = match best {
+			Some(triangle) => triangle,
+			None => {
+				while emitted[next_unemitted] {
+					next_unemitted += 1;
+				}
+				next_unemitted
+			}
+		};
+
+		emitted[triangle] = true;
+		let corners = &indices[triangle * 3..triangle * 3 + 3];
+		output.extend_from_slice(corners);
+
+		for &v in corners {
+			let v = v as usize;
+			let start = offsets[v];
+			let end = start + remaining[v] as usize;
+			let position = adjacency[start..end]
+				.iter()
+				.position(|&t| t == triangle)
+				.unwrap();
+			adjacency.swap(start + position, end - 1);
+			remaining[v] -= 1;
+		}
+
+		let mut new_cache: std::vec::Vec<u32> = corners.to_vec();
+		for &v in cache.iter() {
+			if !corners.contains(&v) {
+				new_cache.push(v);
+			}
+		}
+		for &v in new_cache.iter().skip(FORSYTH_CACHE_SIZE) {
+			cache_position[v as usize] = None;
+		}
+		for (position, &v) in
+			new_cache.iter().take(FORSYTH_CACHE_SIZE).enumerate()
+		{
+			cache_position[v as usize] = Some(position);
+		}
+
+		// Rescore everything that moved in or out of the cache and push the
+		// change onto the triangles still using those vertices.
+		for &v in new_cache.iter() {
+			let v = v as usize;
+			let score = vertex_score(cache_position[v], remaining[v]);
+			let delta = score - vertex_scores[v];
+			vertex_scores[v] = score;
+			let start = offsets[v];
+			for &t in &adjacency[start..start + remaining[v] as usize] {
+				triangle_scores[t] += delta;
+			}
+		}
+
+		new_cache.truncate(FORSYTH_CACHE_SIZE);
+		cache = new_cache;
+
+		best = None;
+		let mut best_score = f32::MIN;
+		for &v in cache.iter() {
+			let v = v as usize;
+			let start = offsets[v];
+			for &t in &adjacency[start..start + remaining[v] as usize] {
+				if triangle_scores[t] > best_score {
+					best_score = triangle_scores[t];
+					best = Some(t);
+				}
+			}
+		}
+	}
+
+	output
+}
+
+// Renumbers vertices in order of first use so the vertex fetch walks the
+// buffer linearly. Vertices no index refers to are dropped.
+pub fn optimize_vertex_fetch(
+	vertices: &[Vertex],
+	indices: &[u32],
+) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+	let mut remap = vec![u32::MAX; vertices.len()];
+	let mut reordered: std::vec::Vec<Vertex> =
+		std::vec::Vec::with_capacity(vertices.len());
+
+	let indices = indices
+		.iter()
+		.map(|&i| {
+			let new_index = &mut remap[i as usize];
+			if *new_index == u32::MAX {
+				*new_index = reordered.len() as u32;
+				reordered.push(vertices[i as usize].clone());
+			}
+			*new_index
+		})
+		.collect();
+
+	(reordered, indices)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn vertex(x: f32, y: f32) -> Vertex {
+		Vertex {
+			position: cgmath::vec3(x, y, 0.0),
+			normal: cgmath::vec3(0.0, 0.0, 1.0),
+			tex_coords: cgmath::vec2(x, y),
+			color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+			tangent: cgmath::vec4(1.0, 0.0, 0.0, 1.0),
+		}
+	}
+
+	// A size x size grid of quads as an unindexed triangle soup, the
+	// triangles shuffled so the original order is cache-hostile.
+	fn shuffled_grid(
+		size: usize,
+	) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+		let mut triangles = std::vec::Vec::new();
+		for y in 0..size {
+			for x in 0..size {
+				let (x0, y0) = (x as f32, y as f32);
+				let (x1, y1) = (x0 + 1.0, y0 + 1.0);
+				triangles.push([(x0, y0), (x1, y0), (x1, y1)]);
+				triangles.push([(x0, y0), (x1, y1), (x0, y1)]);
+			}
+		}
+		let mut seed = 12345u32;
+		for i in (1..triangles.len()).rev() {
+			seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+			triangles.swap(i, (seed >> 8) as usize % (i + 1));
+		}
+		let vertices: std::vec::Vec<Vertex> = triangles
+			.iter()
+			.flatten()
+			.map(|&(x, y)| vertex(x, y))
+			.collect();
+		let indices = (0..vertices.len() as u32).collect();
+		(vertices, indices)
+	}
+
+	fn positions(
+		vertices: &[Vertex],
+		indices: &[u32],
+	) -> std::vec::Vec<[(i32, i32); 3]> {
+		let mut triangles: std::vec::Vec<[(i32, i32); 3]> = indices
+			.chunks(3)
+			.map(|corners| {
+				let mut triangle = [(0, 0); 3];
+				for (corner, &i) in triangle.iter_mut().zip(corners) {
+					let p = vertices[i as usize].position;
+					*corner = (p.x as i32, p.y as i32);
+				}
+				triangle.sort_unstable();
+				triangle
+			})
+			.collect();
+		triangles.sort_unstable();
+		triangles
+	}
+
+	#[test]
+	fn welding_shares_equal_vertices() {
+		let (vertices, indices) = shuffled_grid(8);
+		let (welded, welded_indices) = weld_vertices(&vertices, &indices);
+		assert_eq!(welded.len(), 9 * 9);
+		assert_eq!(welded_indices.len(), indices.len());
+		assert!(welded_indices.iter().all(|&i| (i as usize) < welded.len()));
+		assert_eq!(
+			positions(&welded, &welded_indices),
+			positions(&vertices, &indices)
+		);
+	}
+
+	#[test]
+	fn vertex_cache_order_does_not_raise_acmr() {
+		let (vertices, indices) = shuffled_grid(32);
+		let (welded, indices) = weld_vertices(&vertices, &indices);
+		let ordered = optimize_vertex_cache(&indices, welded.len());
+		assert_eq!(ordered.len(), indices.len());
+		assert_eq!(positions(&welded, &ordered), positions(&welded, &indices));
+		let before = acmr(&indices, STATS_CACHE_SIZE);
+		let after = acmr(&ordered, STATS_CACHE_SIZE);
+		assert!(after <= before, "{} > {}", after, before);
+		assert!(after < 1.0, "{}", after);
+	}
+
+	#[test]
+	fn fetch_remap_keeps_indices_valid() {
+		let (vertices, indices) = shuffled_grid(8);
+		let mut extra = vertices.clone();
+		// Unreferenced vertices are dropped by the remap.
+		extra.push(vertex(-1.0, -1.0));
+		let (reordered, remapped) = optimize_vertex_fetch(&extra, &indices);
+		assert_eq!(reordered.len(), vertices.len());
+		assert!(remapped.iter().all(|&i| (i as usize) < reordered.len()));
+		assert_eq!(
+			positions(&reordered, &remapped),
+			positions(&vertices, &indices)
+		);
+		// First use order: every index is at most one past the largest so
+		// far.
+		let mut next = 0;
+		for &i in &remapped {
+			assert!(i <= next);
+			next = next.max(i + 1);
+		}
+	}
+
+	#[test]
+	fn optimize_keeps_the_mesh() {
+		let (vertices, indices) = shuffled_grid(16);
+		let expected = positions(&vertices, &indices);
+		let mesh = optimize(vertices, indices);
+		assert!(mesh
+			.indices
+			.iter()
+			.all(|&i| (i as usize) < mesh.vertices.len()));
+		assert_eq!(positions(&mesh.vertices, &mesh.indices), expected);
+		assert!(mesh.report.after.acmr <= mesh.report.before.acmr);
+		assert_eq!(mesh.index_type, gl::UNSIGNED_SHORT);
+		assert!(mesh.report.after.index_bytes < mesh.report.before.index_bytes);
+	}
+
+	#[test]
+	fn short_indices_only_up_to_65535_vertices() {
+		assert_eq!(index_type_for(0), gl::UNSIGNED_SHORT);
+		assert_eq!(index_type_for(65535), gl::UNSIGNED_SHORT);
+		assert_eq!(index_type_for(65536), gl::UNSIGNED_INT);
+		assert_eq!(index_type_for(1 << 20), gl::UNSIGNED_INT);
+		assert_eq!(index_size(gl::UNSIGNED_SHORT), 2);
+		assert_eq!(index_size(gl::UNSIGNED_INT), 4);
+	}
+}
@@ -0,0 +1,222 @@
+use crate::gpu;
+use crate::model::{self, Model};
+use crate::ray::{self, Hit, Ray, NOTHING};
+use crate::shader;
+use cgmath::prelude::*;
+
+// The closest triangle of one mesh, in the mesh's own space. Boxes are
+// checked first so most meshes cost a single test.
+fn pick_mesh(ray: &Ray, mesh: &model::Mesh, id: u32) -> Option<Hit> {
+	ray.intersect_aabb(&mesh.aabb)?;
+	let position =
+		|i: u32| cgmath::Point3::from_vec(mesh.vertices[i as usize].position);
+	let triangles = mesh.indices.chunks_exact(3).map(|corners| {
+		[
+			position(corners[0]),
+			position(corners[1]),
+			position(corners[2]),
+		]
+	});
+	ray::pick_triangles(ray, triangles, id)
+}
+
+// Meshes are `first_id`, `first_id + 1` and so on, in the model's order.
+pub fn pick_model(
+	ray: &Ray,
+	model: &Model,
+	transform: &cgmath::Matrix4<f32>,
+	first_id: u32,
+) -> Option<Hit> {
+	let local = ray.transform(&transform.invert()?);
+	let mut closest = None;
+	for (i, mesh) in model.meshes.iter().enumerate() {
+		closest =
+			ray::nearest(closest, pick_mesh(&local, mesh, first_id + i as u32));
+	}
+	// Back in world space, the distance already is.
+	closest.map(|hit| Hit {
+		point: ray.at(hit.distance),
+		..hit
+	})
+}
+
+// Each instance is `first_id` plus its index in `transforms`, whichever of
+// the model's meshes was hit.
+pub fn pick_instances(
+	ray: &Ray,
+	model: &Model,
+	transforms: &[cgmath::Matrix4<f32>],
+	first_id: u32,
+) -> Option<Hit> {
+	let mut closest = None;
+	for (i, transform) in transforms.iter().enumerate() {
+		let id = first_id + i as u32;
+		let hit =
+			pick_model(ray, model, transform, 0).map(|hit| Hit { id, ..hit });
+		closest = ray::nearest(closest, hit);
+	}
+	closest
+}
+
+// Draws ids instead of colours into an offscreen buffer and reads back the
+// one under the cursor, exact to the pixel where the CPU tests only know
+// the triangles.
+pub struct IdBuffer {
+	framebuffer: gpu::Framebuffer,
+	ids: gpu::Texture2D,
+	depth: gpu::Renderbuffer,
+	program: u32,
+	width: i32,
+	height: i32,
+}
+
+impl IdBuffer {
+	pub fn new(width: i32, height: i32) -> IdBuffer {
+		let mut buffer = IdBuffer {
+			framebuffer: gpu::Framebuffer::new(),
+			ids: gpu::Texture2D::new(),
+			depth: gpu::Renderbuffer::new(),
+			program: shader::create_shader("id.vert", "id.frag"),
+			width: 0,
+			height: 0,
+		};
+		buffer.framebuffer.bind();
+		unsafe {
+			gl::FramebufferTexture2D(
+				gl::FRAMEBUFFER,
+				gl::COLOR_ATTACHMENT0,
+				gl::TEXTURE_2D,
+				buffer.ids.id(),
+				0,
+			);
+			gl::FramebufferRenderbuffer(
+				gl::FRAMEBUFFER,
+				gl::DEPTH_ATTACHMENT,
+				gl::RENDERBUFFER,
+				buffer.depth.id(),
+			);
+			gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+		}
+		buffer.resize(width, height);
+		buffer
+	}
+
+	// To the framebuffer size, it's cheap when nothing changed.
+	pub fn resize(&mut self, width: i32, height: i32) {
+		if (width, height) == (self.width, self.height)
+			|| width <= 0
+			|| height <= 0
+		{
+			return;
+		}
+		self.width = width;
+		self.height = height;
+		self.ids.bind();
+		self.depth.bind();
+		unsafe {
+			gl::TexImage2D(
+				gl::TEXTURE_2D,
+				0,
+				gl::R32UI as i32,
+				width,
+				height,
+				0,
+				gl::RED_INTEGER,
+				gl::UNSIGNED_INT,
+				std::ptr::null(),
+			);
+			// Integer textures can't be filtered.
+			gl::TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_MIN_FILTER,
+				gl::NEAREST as i32,
+			);
+			gl::TexParameteri(
+				gl::TEXTURE_2D,
+				gl::TEXTURE_MAG_FILTER,
+				gl::NEAREST as i32,
+			);
+			gl::RenderbufferStorage(
+				gl::RENDERBUFFER,
+				gl::DEPTH_COMPONENT24,
+				width,
+				height,
+			);
+		}
+	}
+
+	// Binds and clears the buffer for the `draw_*` calls, `read` puts the
+	// window's framebuffer back.
+	pub fn begin(
+		&self,
+		view: cgmath::Matrix4<f32>,
+		projection: cgmath::Matrix4<f32>,
+	) {
+		self.framebuffer.bind();
+		unsafe {
+			gl::Viewport(0, 0, self.width, self.height);
+			gl::ClearBufferuiv(gl::COLOR, 0, [NOTHING; 4].as_ptr());
+			gl::Clear(gl::DEPTH_BUFFER_BIT);
+			gl::UseProgram(self.program);
+		}
+		shader::set_mat4(self.program, "view", view);
+		shader::set_mat4(self.program, "projection", projection);
+	}
+
+	pub fn draw_model(
+		&self,
+		model: &Model,
+		transform: cgmath::Matrix4<f32>,
+		first_id: u32,
+	) {
+		shader::set_mat4(self.program, "model", transform);
+		for (i, mesh) in model.meshes.iter().enumerate() {
+			shader::set_uint(self.program, "id", first_id + i as u32);
+			model::draw_mesh(mesh, self.program, None, false);
+		}
+	}
+
+	pub fn draw_instances(
+		&self,
+		model: &Model,
+		transforms: &[cgmath::Matrix4<f32>],
+		first_id: u32,
+	) {
+		shader::set_mat4(self.program, "model", cgmath::Matrix4::identity());
+		shader::set_uint(self.program, "id", first_id);
+		model::draw_model_instanced(model, self.program, transforms, None);
+	}
+
+	// The id drawn under a point on screen, then back to drawing to the
+	// window.
+	pub fn read(&self, ndc: cgmath::Vector2<f32>) -> u32 {
+		let x = ((ndc.x + 1.0) / 2.0 * self.width as f32) as i32;
+		let y = ((ndc.y + 1.0) / 2.0 * self.height as f32) as i32;
+		let mut id = NOTHING;
+		unsafe {
+			if x >= 0 && x < self.width && y >= 0 && y < self.height {
+				gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+				gl::ReadPixels(
+					x,
+					y,
+					1,
+					1,
+					gl::RED_INTEGER,
+					gl::UNSIGNED_INT,
+					&mut id as *mut u32 as *mut std::os::raw::c_void,
+				);
+			}
+			gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+			gl::Viewport(0, 0, self.width, self.height);
+		}
+		id
+	}
+}
+
+impl Drop for IdBuffer {
+	fn drop(&mut self) {
+		unsafe {
+			gl::DeleteProgram(self.program);
+		}
+	}
+}
@@ -0,0 +1,520 @@
+use crate::model::{compute_normals, Mesh, Vertex};
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+	Ascii,
+	BinaryLittleEndian,
+	BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+	I8,
+	U8,
+	I16,
+	U16,
+	I32,
+	U32,
+	F32,
+	F64,
+}
+
+enum Property {
+	Scalar(std::string::String, Scalar),
+	List(std::string::String, Scalar, Scalar),
+}
+
+struct Element {
+	name: std::string::String,
+	count: usize,
+	properties: std::vec::Vec<Property>,
+}
+
+fn invalid(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn scalar(name: &str) -> std::io::Result<Scalar> {
+	Ok(match name {
+		"char" | "int8" => Scalar::I8,
+		"uchar" | "uint8" => Scalar::U8,
+		"short" | "int16" => Scalar::I16,
+		"ushort" | "uint16" => Scalar::U16,
+		"int" | "int32" => Scalar::I32,
+		"uint" | "uint32" => Scalar::U32,
+		"float" | "float32" => Scalar::F32,
+		"double" | "float64" => Scalar::F64,
+		_ => return Err(invalid("unknown PLY property type")),
+	})
+}
+
+impl Scalar {
+	fn size(self) -> usize {
+		match self {
+			Scalar::I8 | Scalar::U8 => 1,
+			Scalar::I16 | Scalar::U16 => 2,
+			Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+			Scalar::F64 => 8,
+		}
+	}
+
+	// Integer colour channels are normalised to 0..1 like OpenGL would.
+	fn normalized(self, value: f64) -> f64 {
+		match self {
+			Scalar::U8 => value / 255.0,
+			Scalar::U16 => value / 65535.0,
+			_ => value,
+		}
+	}
+}
+
+// Reads values one at a time from either the whitespace separated body of an
+// ASCII file or the packed body of a binary one.
+struct Body<'a> {
+	format: Format,
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Body<'a> {
+	fn read(&mut self, kind: Scalar) -> std::io::Result<f64> {
+		if self.format == Format::Ascii {
+			return self.read_word();
+		}
+
+		let size = kind.size();
+		if self.position + size > self.bytes.len() {
+			return Err(invalid("PLY body is truncated"));
+		}
+		let mut raw = [0u8; 8];
+		raw[..size]
+			.copy_from_slice(&self.bytes[self.position..self.position + size]);
+		self.position += size;
+		if self.format == Format::BinaryBigEndian {
+			raw[..size].reverse();
+		}
+
+		Ok(match kind {
+			Scalar::I8 => raw[0] as i8 as f64,
+			Scalar::U8 => raw[0] as f64,
+			Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+			Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+			Scalar::I32 => {
+				i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64
+			}
+			Scalar::U32 => {
+				u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64
+			}
+			Scalar::F32 => {
+				f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64
+			}
+			Scalar::F64 => f64::from_le_bytes(raw),
+		})
+	}
+
+	fn read_word(&mut self) -> std::io::Result<f64> {
+		while self.position < self.bytes.len()
+			&& self.bytes[self.position].is_ascii_whitespace()
+		{
+			self.position += 1;
+		}
+		let start = self.position;
+		while self.position < self.bytes.len()
+			&& !self.bytes[self.position].is_ascii_whitespace()
+		{
+			self.position += 1;
+		}
+		std::str::from_utf8(&self.bytes[start..self.position])
+			.ok()
+			.and_then(|word| word.parse::<f64>().ok())
+			.ok_or_else(|| invalid("PLY body has a malformed value"))
+	}
+}
+
+// Loads the `vertex` and `face` elements of a PLY file as a single mesh.
+// Positions are required, normals are generated when missing, texture
+// coordinates (s/t, u/v or texture_u/texture_v) and vertex colours
+// (red/green/blue/alpha) are kept when present. Polygons are fanned into
+// triangles and any other elements are skipped.
+pub fn load_ply(path: &Path) -> std::io::Result<std::vec::Vec<Mesh>> {
+	let mut bytes = std::vec::Vec::new();
+	std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+	parse_ply(&bytes)
+}
+
+pub fn parse_ply(bytes: &[u8]) -> std::io::Result<std::vec::Vec<Mesh>> {
+	let header_end = bytes
+		.windows(10)
+		.position(|window| window == b"end_header")
+		.ok_or_else(|| invalid("PLY header has no end_header"))?;
+	let mut body_start = header_end + 10;
+	// The header line ends in "\n" or "\r\n".
+	while body_start < bytes.len() && bytes[body_start] != b'\n' {
+		body_start += 1;
+	}
+	body_start += 1;
+
+	let header = std::str::from_utf8(&bytes[..header_end])
+		.map_err(|_| invalid("PLY header is not text"))?;
+	let mut lines = header.lines();
+	if lines.next().map(str::trim) != Some("ply") {
+		return Err(invalid("not a PLY file"));
+	}
+
+	let mut format = None;
+	let mut elements: std::vec::Vec<Element> = std::vec::Vec::new();
+	for line in lines {
+		let words: std::vec::Vec<&str> = line.split_whitespace().collect();
+		match words.as_slice() {
+			["format", "ascii", _] => format = Some(Format::Ascii),
+			["format", "binary_little_endian", _] => {
+				format = Some(Format::BinaryLittleEndian)
+			}
+			["format", "binary_big_endian", _] => {
+				format = Some(Format::BinaryBigEndian)
+			}
+			["element", name, count] => elements.push(Element {
+				name: name.to_string(),
+				count: count.parse().map_err(|_| {
+					invalid("PLY element count is not a number")
+				})?,
+				properties: std::vec::Vec::new(),
+			}),
+			["property", "list", count_type, item_type, name] => elements
+				.last_mut()
+				.ok_or_else(|| invalid("PLY property outside an element"))?
+				.properties
+				.push(Property::List(
+					name.to_string(),
+					scalar(count_type)?,
+					scalar(item_type)?,
+				)),
+			["property", kind, name] => elements
+				.last_mut()
+				.ok_or_else(|| invalid("PLY property outside an element"))?
+				.properties
+				.push(Property::Scalar(name.to_string(), scalar(kind)?)),
+			_ => {}
+		}
+	}
+
+	let mut body = Body {
+		format: format.ok_or_else(|| invalid("PLY header has no format"))?,
+		bytes: &bytes[body_start.min(bytes.len())..],
+		position: 0,
+	};
+
+	let mut vertices: std::vec::Vec<Vertex> = std::vec::Vec::new();
+	let mut indices: std::vec::Vec<u32> = std::vec::Vec::new();
+	let mut has_normals = false;
+
+	for element in elements.iter() {
+		for _ in 0..element.count {
+			let mut vertex = Vertex {
+				position: cgmath::vec3(0.0, 0.0, 0.0),
+				normal: cgmath::vec3(0.0, 0.0, 0.0),
+				tex_coords: cgmath::vec2(0.0, 0.0),
+				color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+				tangent: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+			};
+
+			for property in element.properties.iter() {
+				match property {
+					Property::Scalar(name, kind) => {
+						let value = body.read(*kind)?;
+						if element.name != "vertex" {
+							continue;
+						}
+						let v = value as f32;
+						match name.as_str() {
+							"x" => vertex.position.x = v,
+							"y" => vertex.position.y = v,
+							"z" => vertex.position.z = v,
+							"nx" => vertex.normal.x = v,
+							"ny" => vertex.normal.y = v,
+							"nz" => vertex.normal.z = v,
+							"s" | "u" | "texture_u" => vertex.tex_coords.x = v,
+							"t" | "v" | "texture_v" => vertex.tex_coords.y = v,
+							"red" => {
+								vertex.color.x = kind.normalized(value) as f32
+							}
+							"green" => {
+								vertex.color.y = kind.normalized(value) as f32
+							}
+							"blue" => {
+								vertex.color.z = kind.normalized(value) as f32
+							}
+							"alpha" => {
+								vertex.color.w = kind.normalized(value) as f32
+							}
+							_ => {}
+						}
+						if name == "nx" || name == "ny" || name == "nz" {
+							has_normals = true;
+						}
+					}
+					Property::List(name, count_type, item_type) => {
+						let count = body.read(*count_type)? as usize;
+						// The count comes from the file, don't trust it with
+						// the allocation.
+						let mut items = std::vec::Vec::with_capacity(
+							count.min(body.bytes.len()),
+						);
+						for _ in 0..count {
+							items.push(body.read(*item_type)? as u32);
+						}
+						let is_face_indices =
+							name == "vertex_indices" || name == "vertex_index";
+						if element.name == "face" && is_face_indices {
+							for i in 1..count.saturating_sub(1) {
+								indices.extend_from_slice(&[
+									items[0],
+									items[i],
+									items[i + 1],
+								]);
+							}
+						}
+					}
+				}
+			}
+
+			if element.name == "vertex" {
+				vertices.push(vertex);
+			}
+		}
+	}
+
+	if indices.iter().any(|&i| i as usize >= vertices.len()) {
+		return Err(invalid("PLY face refers to a missing vertex"));
+	}
+	if !has_normals {
+		compute_normals(&mut vertices, &indices);
+	}
+
+	let mut mesh = Mesh::default();
+	mesh.vertices = vertices;
+	mesh.indices = indices;
+	mesh.index_type = gl::UNSIGNED_INT;
+
+	Ok(vec![mesh])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::prelude::*;
+
+	const ASCII: &str = "ply\nformat ascii 1.0\ncomment a unit quad\n\
+		element vertex 4\nproperty float x\nproperty float y\n\
+		property float z\nproperty float s\nproperty float t\n\
+		property uchar red\nproperty uchar green\nproperty uchar blue\n\
+		element face 1\nproperty list uchar int vertex_indices\n\
+		end_header\n\
+		0 0 0 0 0 255 0 0\n1 0 0 1 0 0 255 0\n\
+		1 1 0 1 1 0 0 255\n0 1 0 0 1 255 255 255\n\
+		4 0 1 2 3\n";
+
+	// The same quad as ASCII, packed in the given byte order.
+	fn binary(big_endian: bool) -> std::vec::Vec<u8> {
+		let format = if big_endian {
+			"binary_big_endian"
+		} else {
+			"binary_little_endian"
+		};
+		let mut bytes = format!(
+			"ply\r\nformat {} 1.0\r\nelement vertex 4\r\n\
+			property float x\r\nproperty float y\r\nproperty float z\r\n\
+			property double u\r\nproperty double v\r\n\
+			element face 1\r\nproperty list uchar uint vertex_index\r\n\
+			end_header\r\n",
+			format
+		)
+		.into_bytes();
+		let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+		for &[x, y] in corners.iter() {
+			for &value in [x, y, 0.0f32].iter() {
+				if big_endian {
+					bytes.extend_from_slice(&value.to_be_bytes());
+				} else {
+					bytes.extend_from_slice(&value.to_le_bytes());
+				}
+			}
+			for &value in [x as f64, y as f64].iter() {
+				if big_endian {
+					bytes.extend_from_slice(&value.to_be_bytes());
+				} else {
+					bytes.extend_from_slice(&value.to_le_bytes());
+				}
+			}
+		}
+		bytes.push(4);
+		for i in 0..4u32 {
+			if big_endian {
+				bytes.extend_from_slice(&i.to_be_bytes());
+			} else {
+				bytes.extend_from_slice(&i.to_le_bytes());
+			}
+		}
+		bytes
+	}
+
+	fn check_quad(meshes: &[Mesh]) {
+		assert_eq!(meshes.len(), 1);
+		let mesh = &meshes[0];
+		let positions: std::vec::Vec<[f32; 3]> = mesh
+			.vertices
+			.iter()
+			.map(|v| [v.position.x, v.position.y, v.position.z])
+			.collect();
+		assert_eq!(
+			positions,
+			[
+				[0.0, 0.0, 0.0],
+				[1.0, 0.0, 0.0],
+				[1.0, 1.0, 0.0],
+				[0.0, 1.0, 0.0]
+			]
+		);
+		// The quad is fanned from its first corner.
+		assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+		for v in mesh.vertices.iter() {
+			assert_eq!(v.tex_coords, cgmath::vec2(v.position.x, v.position.y));
+			// Generated, as the file has none.
+			let up = cgmath::vec3(0.0, 0.0, 1.0);
+			assert!((v.normal - up).magnitude() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn ascii() {
+		let meshes = parse_ply(ASCII.as_bytes()).unwrap();
+		check_quad(&meshes);
+		let colors: std::vec::Vec<[f32; 4]> = meshes[0]
+			.vertices
+			.iter()
+			.map(|v| [v.color.x, v.color.y, v.color.z, v.color.w])
+			.collect();
+		assert_eq!(
+			colors,
+			[
+				[1.0, 0.0, 0.0, 1.0],
+				[0.0, 1.0, 0.0, 1.0],
+				[0.0, 0.0, 1.0, 1.0],
+				[1.0, 1.0, 1.0, 1.0]
+			]
+		);
+	}
+
+	#[test]
+	fn binary_little_and_big_endian() {
+		check_quad(&parse_ply(&binary(false)).unwrap());
+		check_quad(&parse_ply(&binary(true)).unwrap());
+	}
+
+	fn error(bytes: &[u8]) -> std::string::String {
+		match parse_ply(bytes) {
+			Ok(_) => panic!("parsed a malformed PLY"),
+			Err(err) => {
+				assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+				err.to_string()
+			}
+		}
+	}
+
+	#[test]
+	fn malformed_headers() {
+		assert_eq!(
+			error(b"plx\nformat ascii 1.0\nend_header\n"),
+			"not a PLY file"
+		);
+		assert_eq!(
+			error(b"ply\nformat ascii 1.0\nelement vertex 1\n"),
+			"PLY header has no end_header"
+		);
+		assert_eq!(
+			error(b"ply\nelement vertex 0\nend_header\n"),
+			"PLY header has no format"
+		);
+		assert_eq!(
+			error(b"ply\nformat ascii 1.0\nelement vertex x\nend_header\n"),
+			"PLY element count is not a number"
+		);
+		assert_eq!(
+			error(b"ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+			"PLY property outside an element"
+		);
+		assert_eq!(
+			error(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty quad x\nend_header\n"),
+			"unknown PLY property type"
+		);
+		assert_eq!(
+			error(&[
+				0xff, 0xfe, b'\n', b'e', b'n', b'd', b'_', b'h', b'e', b'a',
+				b'd', b'e', b'r'
+			]),
+			"PLY header is not text"
+		);
+	}
+
+	#[test]
+	fn malformed_bodies() {
+		let ascii = ASCII.replace("1 1 0 1 1", "1 one 0 1 1");
+		assert_eq!(error(ascii.as_bytes()), "PLY body has a malformed value");
+		let ascii = ASCII.replace("4 0 1 2 3", "4 0 1 2 9");
+		assert_eq!(
+			error(ascii.as_bytes()),
+			"PLY face refers to a missing vertex"
+		);
+
+		let bytes = binary(false);
+		for length in (bytes.len() - 60)..bytes.len() {
+			assert_eq!(error(&bytes[..length]), "PLY body is truncated");
+		}
+		// A list claiming billions of items fails instead of allocating.
+		let mut huge = b"ply\nformat binary_little_endian 1.0\n\
+			element face 1\nproperty list uint uint vertex_indices\n\
+			end_header\n"
+			.to_vec();
+		huge.extend_from_slice(&u32::MAX.to_le_bytes());
+		huge.extend_from_slice(&[0; 8]);
+		assert_eq!(error(&huge), "PLY body is truncated");
+	}
+
+	// Loads `bytes` saved as `name` the way the viewer loads `--model`.
+	fn load(name: &str, bytes: &[u8]) -> std::io::Result<std::vec::Vec<Mesh>> {
+		let path = std::env::temp_dir().join(format!(
+			"ply-{}-{}",
+			std::process::id(),
+			name
+		));
+		std::fs::write(&path, bytes).unwrap();
+		let meshes = crate::model::load_meshes(
+			path.to_str().unwrap(),
+			&crate::model::LoadOptions::default(),
+			&mut std::vec::Vec::new(),
+		);
+		let _ = std::fs::remove_file(&path);
+		meshes
+	}
+
+	#[test]
+	fn files_without_faces_load_nothing() {
+		let ply = "ply\nformat ascii 1.0\nelement vertex 1\n\
+			property float x\nproperty float y\nproperty float z\n\
+			element face 0\nproperty list uchar int vertex_indices\n\
+			end_header\n0 0 0\n";
+		let meshes = parse_ply(ply.as_bytes()).unwrap();
+		assert!(meshes.iter().all(|mesh| mesh.indices.is_empty()));
+		assert!(load("no-faces.ply", ply.as_bytes()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn load_errors_reach_the_caller() {
+		let err = load("truncated.ply", b"ply\nformat ascii 1.0\n")
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+	}
+}
@@ -0,0 +1,732 @@
+use crate::model::{compute_bounds, Mesh, Vertex};
+use crate::optimize;
+use cgmath::prelude::*;
+
+// Every generator returns a mesh centred on the origin with counter-clockwise
+// front faces, normals pointing outwards, texture coordinates in 0..1 and
+// tangents along increasing u. Pass it to `model::setup_mesh` or
+// `model::model_from_meshes` to draw it.
+
+const TAU: f32 = std::f32::consts::PI * 2.0;
+
+fn vertex(
+	position: cgmath::Vector3<f32>,
+	normal: cgmath::Vector3<f32>,
+	tex_coords: cgmath::Vector2<f32>,
+	tangent: cgmath::Vector3<f32>,
+	bitangent: cgmath::Vector3<f32>,
+) -> Vertex {
+	let w = if normal.cross(tangent).dot(bitangent) < 0.0 {
+		-1.0
+	} else {
+		1.0
+	};
+	Vertex {
+		position,
+		normal,
+		tex_coords,
+		color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+		tangent: tangent.extend(w),
+	}
+}
+
+fn finish(
+	vertices: std::vec::Vec<Vertex>,
+	indices: std::vec::Vec<u32>,
+) -> Mesh {
+	let mut mesh = Mesh::default();
+	mesh.index_type = optimize::index_type_for(vertices.len());
+	mesh.vertices = vertices;
+	mesh.indices = indices;
+	compute_bounds(&mut mesh);
+	mesh
+}
+
+fn append(
+	vertices: &mut std::vec::Vec<Vertex>,
+	indices: &mut std::vec::Vec<u32>,
+	part: (std::vec::Vec<Vertex>, std::vec::Vec<u32>),
+) {
+	let offset = vertices.len() as u32;
+	vertices.extend(part.0);
+	indices.extend(part.1.iter().map(|&i| i + offset));
+}
+
+// 24 vertices, each face has its own so the normals stay flat.
+pub fn cube(size: f32) -> Mesh {
+	let faces = [
+		(cgmath::Vector3::unit_x(), -cgmath::Vector3::unit_z()),
+		(-cgmath::Vector3::unit_x(), cgmath::Vector3::unit_z()),
+		(cgmath::Vector3::unit_y(), cgmath::Vector3::unit_x()),
+		(-cgmath::Vector3::unit_y(), cgmath::Vector3::unit_x()),
+		(cgmath::Vector3::unit_z(), cgmath::Vector3::unit_x()),
+		(-cgmath::Vector3::unit_z(), -cgmath::Vector3::unit_x()),
+	];
+
+	let half = size * 0.5;
+	let mut vertices = std::vec::Vec::with_capacity(24);
+	let mut indices = std::vec::Vec::with_capacity(36);
+	for &(normal, u) in faces.iter() {
+		let v = normal.cross(u);
+		let first = vertices.len() as u32;
+		for &(s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() {
+			let position =
+				(normal + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0)) * half;
+			vertices.push(vertex(position, normal, cgmath::vec2(s, t), u, v));
+		}
+		indices.extend_from_slice(&[
+			first,
+			first + 1,
+			first + 2,
+			first,
+			first + 2,
+			first + 3,
+		]);
+	}
+
+	finish(vertices, indices)
+}
+
+// A grid in the XZ plane facing +Y, u runs along +X and v along -Z.
+pub fn plane(
+	width: f32,
+	depth: f32,
+	subdivisions_x: usize,
+	subdivisions_z: usize,
+) -> Mesh {
+	let (columns, rows) = (subdivisions_x.max(1), subdivisions_z.max(1));
+	let normal = cgmath::Vector3::unit_y();
+	let tangent = cgmath::Vector3::unit_x();
+	let bitangent = -cgmath::Vector3::unit_z();
+
+	let mut vertices = std::vec::Vec::with_capacity((columns + 1) * (rows + 1));
+	for k in 0..=rows {
+		let t = k as f32 / rows as f32;
+		for j in 0..=columns {
+			let s = j as f32 / columns as f32;
+			let position =
+				cgmath::vec3((s - 0.5) * width, 0.0, (0.5 - t) * depth);
+			vertices.push(vertex(
+				position,
+				normal,
+				cgmath::vec2(s, t),
+				tangent,
+				bitangent,
+			));
+		}
+	}
+
+	let mut indices = std::vec::Vec::with_capacity(columns * rows * 6);
+	let stride = columns as u32 + 1;
+	for k in 0..rows as u32 {
+		for j in 0..columns as u32 {
+			let a = k * stride + j;
+			let b = a + stride;
+			indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+		}
+	}
+
+	finish(vertices, indices)
+}
+
+// A point of the outline a surface of revolution is swept from, as a
+// distance from the Y axis, a height and the outward normal in that plane.
+struct ProfilePoint {
+	radius: f32,
+	y: f32,
+	normal: cgmath::Vector2<f32>,
+}
+
+// Sweeps the profile, listed top to bottom along the outside, around the Y
+// axis. u follows the sweep and v the profile's arc length, rows on the axis
+// only keep the triangles that aren't degenerate.
+fn revolve(
+	profile: &[ProfilePoint],
+	segments: usize,
+) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+	let segments = segments.max(3);
+
+	let mut arc = vec![0.0; profile.len()];
+	for k in 1..profile.len() {
+		let dr = profile[k].radius - profile[k - 1].radius;
+		let dy = profile[k].y - profile[k - 1].y;
+		arc[k] = arc[k - 1] + (dr * dr + dy * dy).sqrt();
+	}
+	let length = arc[profile.len() - 1].max(f32::EPSILON);
+
+	let mut vertices =
+		std::vec::Vec::with_capacity(profile.len() * (segments + 1));
+	for (k, point) in profile.iter().enumerate() {
+		// Towards the previous point, i.e. up the profile where v grows.
+		let (above, below) = (
+			&profile[k.saturating_sub(1)],
+			&profile[(k + 1).min(profile.len() - 1)],
+		);
+		let up = cgmath::vec2(above.radius - below.radius, above.y - below.y);
+		for j in 0..=segments {
+			let theta = TAU * j as f32 / segments as f32;
+			let (sin, cos) = theta.sin_cos();
+			let position =
+				cgmath::vec3(point.radius * sin, point.y, point.radius * cos);
+			let normal = cgmath::vec3(
+				point.normal.x * sin,
+				point.normal.y,
+				point.normal.x * cos,
+			)
+			.normalize();
+			let tangent = cgmath::vec3(cos, 0.0, -sin);
+			let bitangent = cgmath::vec3(up.x * sin, up.y, up.x * cos);
+			vertices.push(vertex(
+				position,
+				normal,
+				cgmath::vec2(j as f32 / segments as f32, 1.0 - arc[k] / length),
+				tangent,
+				bitangent,
+			));
+		}
+	}
+
+	// sin(PI) isn't quite zero, so rows are on the axis when they're tiny
+	// compared to the widest one.
+	let widest = profile.iter().map(|p| p.radius.abs()).fold(0.0, f32::max);
+	let on_axis = |point: &ProfilePoint| point.radius.abs() <= widest * 1e-5;
+
+	let mut indices = std::vec::Vec::new();
+	let stride = segments as u32 + 1;
+	for k in 0..profile.len() - 1 {
+		let top_on_axis = on_axis(&profile[k]);
+		let bottom_on_axis = on_axis(&profile[k + 1]);
+		for j in 0..segments as u32 {
+			let a = k as u32 * stride + j;
+			let b = a + stride;
+			if !bottom_on_axis {
+				indices.extend_from_slice(&[a, b, b + 1]);
+			}
+			if !top_on_axis {
+				indices.extend_from_slice(&[a, b + 1, a + 1]);
+			}
+		}
+	}
+
+	(vertices, indices)
+}
+
+// A flat cap at height `y` facing up or down, textured as if projected from
+// the side it faces.
+fn disc(
+	radius: f32,
+	y: f32,
+	up: bool,
+	segments: usize,
+) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+	let segments = segments.max(3);
+	let (normal, flip) = if up {
+		(cgmath::Vector3::unit_y(), -1.0)
+	} else {
+		(-cgmath::Vector3::unit_y(), 1.0)
+	};
+	let tangent = cgmath::Vector3::unit_x();
+	let bitangent = cgmath::vec3(0.0, 0.0, flip);
+
+	let mut vertices = std::vec::Vec::with_capacity(segments + 1);
+	vertices.push(vertex(
+		cgmath::vec3(0.0, y, 0.0),
+		normal,
+		cgmath::vec2(0.5, 0.5),
+		tangent,
+		bitangent,
+	));
+	for j in 0..segments {
+		let (sin, cos) = (TAU * j as f32 / segments as f32).sin_cos();
+		vertices.push(vertex(
+			cgmath::vec3(radius * sin, y, radius * cos),
+			normal,
+			cgmath::vec2(0.5 + sin * 0.5, 0.5 + cos * 0.5 * flip),
+			tangent,
+			bitangent,
+		));
+	}
+
+	let mut indices = std::vec::Vec::with_capacity(segments * 3);
+	for j in 0..segments as u32 {
+		let (current, next) = (1 + j, 1 + (j + 1) % segments as u32);
+		if up {
+			indices.extend_from_slice(&[0, current, next]);
+		} else {
+			indices.extend_from_slice(&[0, next, current]);
+		}
+	}
+
+	(vertices, indices)
+}
+
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Mesh {
+	let rings = rings.max(2);
+	let profile: std::vec::Vec<ProfilePoint> = (0..=rings)
+		.map(|i| {
+			let (sin, cos) =
+				(std::f32::consts::PI * i as f32 / rings as f32).sin_cos();
+			ProfilePoint {
+				radius: radius * sin,
+				y: radius * cos,
+				normal: cgmath::vec2(sin, cos),
+			}
+		})
+		.collect();
+	let (vertices, indices) = revolve(&profile, segments);
+	finish(vertices, indices)
+}
+
+// Subdivided icosahedron, the triangles are much more even than a UV
+// sphere's. Vertices along the u = 0 seam are duplicated so no triangle
+// wraps its texture coordinates around the whole sphere, and u can go a
+// little past 1 there.
+pub fn icosphere(radius: f32, subdivisions: usize) -> Mesh {
+	let t = (1.0 + 5f32.sqrt()) * 0.5;
+	let mut positions: std::vec::Vec<cgmath::Vector3<f32>> = [
+		(-1.0, t, 0.0),
+		(1.0, t, 0.0),
+		(-1.0, -t, 0.0),
+		(1.0, -t, 0.0),
+		(0.0, -1.0, t),
+		(0.0, 1.0, t),
+		(0.0, -1.0, -t),
+		(0.0, 1.0, -t),
+		(t, 0.0, -1.0),
+		(t, 0.0, 1.0),
+		(-t, 0.0, -1.0),
+		(-t, 0.0, 1.0),
+	]
+	.iter()
+	.map(|&(x, y, z)| cgmath::vec3(x, y, z).normalize())
+	.collect();
+	let mut faces: std::vec::Vec<[u32; 3]> = vec![
+		[0, 11, 5],
+		[0, 5, 1],
+		[0, 1, 7],
+		[0, 7, 10],
+		[0, 10, 11],
+		[1, 5, 9],
+		[5, 11, 4],
+		[11, 10, 2],
+		[10, 7, 6],
+		[7, 1, 8],
+		[3, 9, 4],
+		[3, 4, 2],
+		[3, 2, 6],
+		[3, 6, 8],
+		[3, 8, 9],
+		[4, 9, 5],
+		[2, 4, 11],
+		[6, 2, 10],
+		[8, 6, 7],
+		[9, 8, 1],
+	];
+
+	for _ in 0..subdivisions {
+		let mut midpoints: std::collections::HashMap<(u32, u32), u32> =
+			std::collections::HashMap::new();
+		let mut midpoint = |a: u32, b: u32| {
+			let key = (a.min(b), a.max(b));
+			*midpoints.entry(key).or_insert_with(|| {
+				let p =
+					(positions[a as usize] + positions[b as usize]).normalize();
+				positions.push(p);
+				positions.len() as u32 - 1
+			})
+		};
+		faces = faces
+			.iter()
+			.flat_map(|&[a, b, c]| {
+				let (ab, bc, ca) =
+					(midpoint(a, b), midpoint(b, c), midpoint(c, a));
+				vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+			})
+			.collect();
+	}
+
+	let mut vertices: std::vec::Vec<Vertex> = positions
+		.iter()
+		.map(|&n| {
+			let theta = n.x.atan2(n.z);
+			let (sin, cos) = theta.sin_cos();
+			vertex(
+				n * radius,
+				n,
+				cgmath::vec2(
+					0.5 + theta / TAU,
+					0.5 + n.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI,
+				),
+				cgmath::vec3(cos, 0.0, -sin),
+				cgmath::Vector3::unit_y() - n * n.y,
+			)
+		})
+		.collect();
+
+	// The poles have no meaningful u, every triangle touching one gets its
+	// own copy in line with the other two corners.
+	let is_pole = |v: &Vertex| v.normal.y.abs() > 1.0 - 1e-6;
+	let mut seam: std::collections::HashMap<u32, u32> =
+		std::collections::HashMap::new();
+	let mut indices = std::vec::Vec::with_capacity(faces.len() * 3);
+	for face in faces.iter() {
+		let u: std::vec::Vec<f32> = face
+			.iter()
+			.map(|&i| &vertices[i as usize])
+			.filter(|v| !is_pole(v))
+			.map(|v| v.tex_coords.x)
+			.collect();
+		let wraps = u.iter().cloned().fold(f32::MIN, f32::max)
+			- u.iter().cloned().fold(f32::MAX, f32::min)
+			> 0.5;
+
+		let mut corners = [0; 3];
+		for (corner, &i) in corners.iter_mut().zip(face.iter()) {
+			let v = &vertices[i as usize];
+			*corner = if wraps && !is_pole(v) && v.tex_coords.x < 0.5 {
+				*seam.entry(i).or_insert_with(|| {
+					let mut v = vertices[i as usize].clone();
+					v.tex_coords.x += 1.0;
+					vertices.push(v);
+					vertices.len() as u32 - 1
+				})
+			} else {
+				i
+			};
+		}
+
+		for k in 0..3 {
+			if !is_pole(&vertices[corners[k] as usize]) {
+				continue;
+			}
+			let others = [corners[(k + 1) % 3], corners[(k + 2) % 3]];
+			let u = others
+				.iter()
+				.map(|&i| vertices[i as usize].tex_coords.x)
+				.sum::<f32>()
+				* 0.5;
+			let (sin, cos) = ((u - 0.5) * TAU).sin_cos();
+			let mut pole = vertices[corners[k] as usize].clone();
+			pole.tex_coords.x = u;
+			pole.tangent = cgmath::vec4(cos, 0.0, -sin, 1.0);
+			vertices.push(pole);
+			corners[k] = vertices.len() as u32 - 1;
+		}
+		indices.extend_from_slice(&corners);
+	}
+
+	finish(vertices, indices)
+}
+
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> Mesh {
+	let half = height * 0.5;
+	let side = [
+		ProfilePoint {
+			radius,
+			y: half,
+			normal: cgmath::vec2(1.0, 0.0),
+		},
+		ProfilePoint {
+			radius,
+			y: -half,
+			normal: cgmath::vec2(1.0, 0.0),
+		},
+	];
+
+	let (mut vertices, mut indices) = revolve(&side, segments);
+	append(
+		&mut vertices,
+		&mut indices,
+		disc(radius, half, true, segments),
+	);
+	append(
+		&mut vertices,
+		&mut indices,
+		disc(radius, -half, false, segments),
+	);
+	finish(vertices, indices)
+}
+
+// The apex gets one vertex per segment so every side keeps its own normal.
+pub fn cone(radius: f32, height: f32, segments: usize) -> Mesh {
+	let half = height * 0.5;
+	let normal = cgmath::vec2(height, radius).normalize();
+	let side = [
+		ProfilePoint {
+			radius: 0.0,
+			y: half,
+			normal,
+		},
+		ProfilePoint {
+			radius,
+			y: -half,
+			normal,
+		},
+	];
+
+	let (mut vertices, mut indices) = revolve(&side, segments);
+	append(
+		&mut vertices,
+		&mut indices,
+		disc(radius, -half, false, segments),
+	);
+	finish(vertices, indices)
+}
+
+// Lies in the XZ plane, `major_radius` is measured to the centre of the
+// tube.
+pub fn torus(
+	major_radius: f32,
+	minor_radius: f32,
+	major_segments: usize,
+	minor_segments: usize,
+) -> Mesh {
+	let minor_segments = minor_segments.max(3);
+	// Starts on the outer equator and heads down, so the outside of the tube
+	// is swept top to bottom like every other profile.
+	let profile: std::vec::Vec<ProfilePoint> = (0..=minor_segments)
+		.map(|k| {
+			let (sin, cos) = (TAU * k as f32 / minor_segments as f32).sin_cos();
+			ProfilePoint {
+				radius: major_radius + minor_radius * cos,
+				y: -minor_radius * sin,
+				normal: cgmath::vec2(cos, -sin),
+			}
+		})
+		.collect();
+	let (vertices, indices) = revolve(&profile, major_segments);
+	finish(vertices, indices)
+}
+
+// `height` is the length of the straight part, the whole capsule is
+// `height + 2 * radius` tall.
+pub fn capsule(
+	radius: f32,
+	height: f32,
+	segments: usize,
+	rings: usize,
+) -> Mesh {
+	let rings = rings.max(1);
+	let half = height * 0.5;
+	let quarter = std::f32::consts::FRAC_PI_2;
+	let mut profile = std::vec::Vec::with_capacity((rings + 1) * 2);
+	for &(start, y) in [(0.0, half), (quarter, -half)].iter() {
+		for i in 0..=rings {
+			let phi = start + quarter * i as f32 / rings as f32;
+			let (sin, cos) = phi.sin_cos();
+			profile.push(ProfilePoint {
+				radius: radius * sin,
+				y: y + radius * cos,
+				normal: cgmath::vec2(sin, cos),
+			});
+		}
+	}
+	let (vertices, indices) = revolve(&profile, segments);
+	finish(vertices, indices)
+}
+
+// The shapes `--shape` can place, each about as big as `cube(1.0)`.
+pub fn by_name(name: &str) -> Option<Mesh> {
+	Some(match name {
+		"cube" => cube(1.0),
+		"plane" => plane(1.0, 1.0, 1, 1),
+		"sphere" => uv_sphere(0.5, 32, 16),
+		"icosphere" => icosphere(0.5, 2),
+		"cylinder" => cylinder(0.5, 1.0, 32),
+		"cone" => cone(0.5, 1.0, 32),
+		"torus" => torus(0.35, 0.15, 32, 16),
+		"capsule" => capsule(0.25, 0.5, 16, 8),
+		_ => return None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Checks what every generator promises, with normals pointing away from
+	// `inside(position)`, the nearest point of whatever the surface wraps.
+	fn check<F>(mesh: &Mesh, inside: F)
+	where
+		F: Fn(cgmath::Vector3<f32>) -> cgmath::Vector3<f32>,
+	{
+		assert_eq!(mesh.indices.len() % 3, 0);
+		assert!(mesh
+			.indices
+			.iter()
+			.all(|&i| (i as usize) < mesh.vertices.len()));
+		assert_eq!(
+			mesh.index_type,
+			optimize::index_type_for(mesh.vertices.len())
+		);
+
+		for v in mesh.vertices.iter() {
+			assert!(
+				(v.normal.magnitude() - 1.0).abs() < 1e-5,
+				"{:?}",
+				v.normal
+			);
+			let outward = v.position - inside(v.position);
+			assert!(
+				v.normal.dot(outward) > 0.0,
+				"{:?} at {:?}",
+				v.normal,
+				v.position
+			);
+
+			let tangent = v.tangent.truncate();
+			assert!((tangent.magnitude() - 1.0).abs() < 1e-5, "{:?}", tangent);
+			assert!(tangent.dot(v.normal).abs() < 1e-5, "{:?}", v.tangent);
+			assert_eq!(v.tangent.w.abs(), 1.0);
+		}
+
+		for tri in mesh.indices.chunks(3) {
+			let [a, b, c] =
+				[tri[0], tri[1], tri[2]].map(|i| &mesh.vertices[i as usize]);
+			let face = (b.position - a.position).cross(c.position - a.position);
+			let normal = a.normal + b.normal + c.normal;
+			assert!(
+				face.dot(normal) > 0.0,
+				"{:?} winds against its normals",
+				tri
+			);
+		}
+	}
+
+	fn origin(_: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+		cgmath::vec3(0.0, 0.0, 0.0)
+	}
+
+	fn tex_coords_in_unit_square(mesh: &Mesh) -> bool {
+		mesh.vertices.iter().all(|v| {
+			(0.0..=1.0).contains(&v.tex_coords.x)
+				&& (0.0..=1.0).contains(&v.tex_coords.y)
+		})
+	}
+
+	#[test]
+	fn cube_faces() {
+		let mesh = cube(2.0);
+		assert_eq!((mesh.vertices.len(), mesh.indices.len()), (24, 36));
+		check(&mesh, origin);
+		assert!(tex_coords_in_unit_square(&mesh));
+		for v in mesh.vertices.iter() {
+			// Every corner is one unit along each axis, and on the face its
+			// normal points out of.
+			assert_eq!(v.position.map(f32::abs), cgmath::vec3(1.0, 1.0, 1.0));
+			assert_eq!(v.position.dot(v.normal), 1.0);
+		}
+	}
+
+	#[test]
+	fn plane_grid() {
+		let mesh = plane(3.0, 2.0, 3, 2);
+		assert_eq!((mesh.vertices.len(), mesh.indices.len()), (12, 36));
+		check(&mesh, |p| p - cgmath::Vector3::unit_y());
+		assert!(tex_coords_in_unit_square(&mesh));
+		assert!(mesh.vertices.iter().all(|v| v.position.y == 0.0));
+		assert_eq!(mesh.aabb.min, cgmath::point3(-1.5, 0.0, -1.0));
+		assert_eq!(mesh.aabb.max, cgmath::point3(1.5, 0.0, 1.0));
+	}
+
+	#[test]
+	fn uv_sphere_rings() {
+		let mesh = uv_sphere(2.0, 8, 4);
+		// 5 rows of 9, the rows next to the poles only keep one triangle
+		// per segment.
+		assert_eq!(mesh.vertices.len(), 45);
+		assert_eq!(mesh.indices.len(), 8 * (2 * 4 - 2) * 3);
+		check(&mesh, origin);
+		assert!(tex_coords_in_unit_square(&mesh));
+		for v in mesh.vertices.iter() {
+			assert!((v.position.magnitude() - 2.0).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn icosphere_subdivisions() {
+		let mesh = icosphere(2.0, 1);
+		assert_eq!(mesh.indices.len(), 80 * 3);
+		check(&mesh, origin);
+		// Seam and pole copies share the 42 positions of a once
+		// subdivided icosahedron.
+		let mut positions: std::vec::Vec<[u32; 3]> = mesh
+			.vertices
+			.iter()
+			.map(|v| {
+				[
+					v.position.x.to_bits(),
+					v.position.y.to_bits(),
+					v.position.z.to_bits(),
+				]
+			})
+			.collect();
+		positions.sort();
+		positions.dedup();
+		assert_eq!(positions.len(), 42);
+		for v in mesh.vertices.iter() {
+			assert!((v.position.magnitude() - 2.0).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn cylinder_sides_and_caps() {
+		let mesh = cylinder(1.0, 2.0, 8);
+		// Two rows of 9 for the side, a centre and 8 rim vertices per cap.
+		assert_eq!(mesh.vertices.len(), 18 + 9 * 2);
+		assert_eq!(mesh.indices.len(), (16 + 8 * 2) * 3);
+		check(&mesh, origin);
+		assert!(tex_coords_in_unit_square(&mesh));
+	}
+
+	#[test]
+	fn cone_sides_and_base() {
+		let mesh = cone(1.0, 2.0, 8);
+		assert_eq!(mesh.vertices.len(), 18 + 9);
+		assert_eq!(mesh.indices.len(), (8 + 8) * 3);
+		check(&mesh, origin);
+		assert!(tex_coords_in_unit_square(&mesh));
+	}
+
+	#[test]
+	fn torus_tube() {
+		let mesh = torus(2.0, 0.5, 8, 4);
+		assert_eq!(mesh.vertices.len(), 5 * 9);
+		assert_eq!(mesh.indices.len(), 8 * 4 * 2 * 3);
+		// Away from the circle through the middle of the tube.
+		check(&mesh, |p| cgmath::vec3(p.x, 0.0, p.z).normalize() * 2.0);
+		assert!(tex_coords_in_unit_square(&mesh));
+	}
+
+	#[test]
+	fn capsule_caps_and_side() {
+		let mesh = capsule(0.5, 1.0, 8, 2);
+		// Each half sphere has 3 rows, the side is between the two equators.
+		assert_eq!(mesh.vertices.len(), 6 * 9);
+		assert_eq!(mesh.indices.len(), (8 + 16 * 3 + 8) * 3);
+		// Away from the segment the half spheres are centred on.
+		check(&mesh, |p| cgmath::vec3(0.0, p.y.clamp(-0.5, 0.5), 0.0));
+		assert!(tex_coords_in_unit_square(&mesh));
+		assert!((mesh.aabb.max.y - 1.0).abs() < 1e-6);
+		assert!((mesh.aabb.min.y + 1.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn named_shapes() {
+		for name in [
+			"cube",
+			"plane",
+			"sphere",
+			"icosphere",
+			"cylinder",
+			"cone",
+			"torus",
+			"capsule",
+		]
+		.iter()
+		{
+			assert!(by_name(name).is_some(), "{}", name);
+		}
+		assert!(by_name("teapot").is_none());
+	}
+}
@@ -0,0 +1,461 @@
+use crate::bounds::Aabb;
+use cgmath::prelude::*;
+
+// Objects are told apart by ids the caller hands out, the same for the
+// CPU tests and the ID buffer. 0 is the background.
+pub const NOTHING: u32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+	pub origin: cgmath::Point3<f32>,
+	pub direction: cgmath::Vector3<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+	pub id: u32,
+	// Along the ray, from the near plane for rays through the cursor.
+	pub distance: f32,
+	pub point: cgmath::Point3<f32>,
+	// Which triangle of the mesh, and where in it as weights for its
+	// three corners.
+	pub triangle: usize,
+	pub barycentric: cgmath::Vector3<f32>,
+}
+
+// The cursor in normalised device coordinates, from window coordinates
+// with y growing downwards.
+pub fn cursor_ndc(
+	cursor: (f64, f64),
+	window_size: (i32, i32),
+) -> cgmath::Vector2<f32> {
+	let (width, height) = window_size;
+	cgmath::vec2(
+		(2.0 * cursor.0 / width.max(1) as f64 - 1.0) as f32,
+		(1.0 - 2.0 * cursor.1 / height.max(1) as f64) as f32,
+	)
+}
+
+impl Ray {
+	// From the near plane to the far plane through a point on screen, for
+	// perspective and orthographic projections alike.
+	pub fn from_ndc(
+		ndc: cgmath::Vector2<f32>,
+		view_projection: &cgmath::Matrix4<f32>,
+	) -> Option<Ray> {
+		let inverse = view_projection.invert()?;
+		let unproject = |z| {
+			let p = inverse * cgmath::vec4(ndc.x, ndc.y, z, 1.0);
+			cgmath::Point3::from_homogeneous(p)
+		};
+		let near = unproject(-1.0);
+		let far = unproject(1.0);
+		Some(Ray {
+			origin: near,
+			direction: (far - near).normalize(),
+		})
+	}
+
+	// Into another space. The direction keeps its scale so distances
+	// along the result are still the original's.
+	pub fn transform(&self, matrix: &cgmath::Matrix4<f32>) -> Ray {
+		Ray {
+			origin: matrix.transform_point(self.origin),
+			direction: matrix.transform_vector(self.direction),
+		}
+	}
+
+	pub fn at(&self, distance: f32) -> cgmath::Point3<f32> {
+		self.origin + self.direction * distance
+	}
+
+	// Where the ray enters the box, 0 when it starts inside (slab test).
+	pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+		if aabb.is_empty() {
+			return None;
+		}
+		let mut near = 0.0f32;
+		let mut far = f32::INFINITY;
+		for axis in 0..3 {
+			let origin = self.origin[axis];
+			let direction = self.direction[axis];
+			if direction == 0.0 {
+				if origin < aabb.min[axis] || origin > aabb.max[axis] {
+					return None;
+				}
+				continue;
+			}
+			let a = (aabb.min[axis] - origin) / direction;
+			let b = (aabb.max[axis] - origin) / direction;
+			near = near.max(a.min(b));
+			far = far.min(a.max(b));
+			if near > far {
+				return None;
+			}
+		}
+		Some(near)
+	}
+
+	// Either side of the triangle (Möller-Trumbore, 1997). Returns the
+	// distance and the weights of `a`, `b` and `c` at the hit.
+	pub fn intersect_triangle(
+		&self,
+		a: cgmath::Point3<f32>,
+		b: cgmath::Point3<f32>,
+		c: cgmath::Point3<f32>,
+	) -> Option<(f32, cgmath::Vector3<f32>)> {
+		let ab = b - a;
+		let ac = c - a;
+		let p = self.direction.cross(ac);
+		let determinant = ab.dot(p);
+		// Parallel to the triangle, or too close to it to tell.
+		if determinant.abs() < 1e-12 {
+			return None;
+		}
+		let inverse = 1.0 / determinant;
+		let to_origin = self.origin - a;
+		let u = to_origin.dot(p) * inverse;
+		if !(0.0..=1.0).contains(&u) {
+			return None;
+		}
+		let q = to_origin.cross(ab);
+		let v = self.direction.dot(q) * inverse;
+		if v < 0.0 || u + v > 1.0 {
+			return None;
+		}
+		let distance = ac.dot(q) * inverse;
+		if distance < 0.0 {
+			return None;
+		}
+		Some((distance, cgmath::vec3(1.0 - u - v, u, v)))
+	}
+
+	// The plane through `point` facing either way, `None` when the ray
+	// runs along it or away from it.
+	pub fn intersect_plane(
+		&self,
+		point: cgmath::Point3<f32>,
+		normal: cgmath::Vector3<f32>,
+	) -> Option<f32> {
+		let along = self.direction.dot(normal);
+		if along.abs() < 1e-6 {
+			return None;
+		}
+		let distance = (point - self.origin).dot(normal) / along;
+		if distance < 0.0 {
+			return None;
+		}
+		Some(distance)
+	}
+
+	// The point of the infinite line through `point` that comes closest to
+	// the ray, `None` when they're parallel.
+	pub fn closest_on_line(
+		&self,
+		point: cgmath::Point3<f32>,
+		direction: cgmath::Vector3<f32>,
+	) -> Option<cgmath::Point3<f32>> {
+		let a = self.direction.dot(self.direction);
+		let b = self.direction.dot(direction);
+		let c = direction.dot(direction);
+		let denominator = a * c - b * b;
+		if denominator <= 1e-9 * a * c {
+			return None;
+		}
+		let w = self.origin - point;
+		let s =
+			(a * direction.dot(w) - b * self.direction.dot(w)) / denominator;
+		Some(point + direction * s)
+	}
+
+	// How close the ray comes to the segment from `a` to `b`, and how far
+	// along the ray that is.
+	pub fn distance_to_segment(
+		&self,
+		a: cgmath::Point3<f32>,
+		b: cgmath::Point3<f32>,
+	) -> (f32, f32) {
+		let ab = b - a;
+		let on_line = self
+			.closest_on_line(a, ab)
+			.map_or(0.0, |point| (point - a).dot(ab) / ab.magnitude2());
+		let point = a + ab * on_line.clamp(0.0, 1.0);
+		let along = ((point - self.origin).dot(self.direction)
+			/ self.direction.magnitude2())
+		.max(0.0);
+		(self.at(along).distance(point), along)
+	}
+}
+
+// Picks between two hits, either of which may be a miss.
+pub fn nearest(a: Option<Hit>, b: Option<Hit>) -> Option<Hit> {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(if b.distance < a.distance { b } else { a }),
+		(a, None) => a,
+		(None, b) => b,
+	}
+}
+
+// The closest of a list of triangles, all in the ray's space.
+pub fn pick_triangles<I>(ray: &Ray, triangles: I, id: u32) -> Option<Hit>
+where
+	I: Iterator<Item = [cgmath::Point3<f32>; 3]>,
+{
+	let mut closest: Option<Hit> = None;
+	for (triangle, [a, b, c]) in triangles.enumerate() {
+		if let Some((distance, barycentric)) = ray.intersect_triangle(a, b, c) {
+			let hit = Hit {
+				id,
+				distance,
+				point: ray.at(distance),
+				triangle,
+				barycentric,
+			};
+			closest = nearest(closest, Some(hit));
+		}
+	}
+	closest
+}
+
+// Copies of one shape placed by `transforms`, each `first_id` plus its
+// index, for scenes drawn without a model. Chapters with a model pick
+// through `pick` instead.
+#[allow(dead_code)]
+pub fn pick_placed(
+	ray: &Ray,
+	triangles: &[[cgmath::Point3<f32>; 3]],
+	transforms: &[cgmath::Matrix4<f32>],
+	first_id: u32,
+) -> Option<Hit> {
+	let aabb = Aabb::from_points(triangles.iter().flatten().copied());
+	let mut closest = None;
+	for (i, transform) in transforms.iter().enumerate() {
+		let local = match transform.invert() {
+			Some(inverse) => ray.transform(&inverse),
+			None => continue,
+		};
+		if local.intersect_aabb(&aabb).is_none() {
+			continue;
+		}
+		let id = first_id + i as u32;
+		let hit =
+			pick_triangles(&local, triangles.iter().copied(), id).map(|hit| {
+				Hit {
+					point: ray.at(hit.distance),
+					..hit
+				}
+			});
+		closest = nearest(closest, hit);
+	}
+	closest
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+		Ray {
+			origin: cgmath::Point3::from(origin),
+			direction: cgmath::Vector3::from(direction),
+		}
+	}
+
+	fn unit_box() -> Aabb {
+		Aabb {
+			min: cgmath::point3(-1.0, -1.0, -1.0),
+			max: cgmath::point3(1.0, 1.0, 1.0),
+		}
+	}
+
+	fn close(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) -> bool {
+		(a - b).magnitude() < 1e-4
+	}
+
+	#[test]
+	fn boxes() {
+		let aabb = unit_box();
+		assert_eq!(
+			ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb),
+			Some(4.0)
+		);
+		// Starting inside counts as a hit right away.
+		assert_eq!(
+			ray([0.5, 0.0, 0.0], [1.0, 0.0, 0.0]).intersect_aabb(&aabb),
+			Some(0.0)
+		);
+		// Along an axis, outside the box's slab on another.
+		assert_eq!(
+			ray([0.0, 2.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb),
+			None
+		);
+		assert_eq!(
+			ray([0.0, 1.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb),
+			Some(4.0)
+		);
+		// Behind the origin.
+		assert_eq!(
+			ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0]).intersect_aabb(&aabb),
+			None
+		);
+		// Diagonal past a corner.
+		assert_eq!(
+			ray([3.0, 0.0, 0.0], [-1.0, 3.0, 0.0]).intersect_aabb(&aabb),
+			None
+		);
+		assert_eq!(
+			ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0])
+				.intersect_aabb(&Aabb::default()),
+			None
+		);
+	}
+
+	#[test]
+	fn triangles() {
+		let (a, b, c) = (
+			cgmath::point3(0.0, 0.0, 0.0),
+			cgmath::point3(3.0, 0.0, 0.0),
+			cgmath::point3(0.0, 3.0, 0.0),
+		);
+		let (distance, weights) = ray([1.0, 1.0, 2.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.unwrap();
+		assert_eq!(distance, 2.0);
+		assert!(close(
+			weights,
+			cgmath::vec3(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0)
+		));
+
+		let (_, weights) = ray([2.0, 0.5, 1.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.unwrap();
+		assert!(close(
+			weights,
+			cgmath::vec3(1.0 / 6.0, 2.0 / 3.0, 1.0 / 6.0)
+		));
+		// The weights put the hit back together.
+		let point = a.to_vec() * weights.x
+			+ b.to_vec() * weights.y
+			+ c.to_vec() * weights.z;
+		assert!(close(point, cgmath::vec3(2.0, 0.5, 0.0)));
+
+		// Either side, and on the edges.
+		assert!(ray([1.0, 1.0, -2.0], [0.0, 0.0, 1.0])
+			.intersect_triangle(a, b, c)
+			.is_some());
+		let (_, weights) = ray([1.5, 1.5, 1.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.unwrap();
+		assert!(close(weights, cgmath::vec3(0.0, 0.5, 0.5)));
+		assert!(ray([1.0, 0.0, 1.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.is_some());
+
+		assert!(ray([1.6, 1.6, 1.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.is_none());
+		assert!(ray([-0.1, 1.0, 1.0], [0.0, 0.0, -1.0])
+			.intersect_triangle(a, b, c)
+			.is_none());
+		// Parallel, in the plane and above it, and pointing away.
+		assert!(ray([-1.0, 1.0, 0.0], [1.0, 0.0, 0.0])
+			.intersect_triangle(a, b, c)
+			.is_none());
+		assert!(ray([-1.0, 1.0, 1.0], [1.0, 0.0, 0.0])
+			.intersect_triangle(a, b, c)
+			.is_none());
+		assert!(ray([1.0, 1.0, 1.0], [0.0, 0.0, 1.0])
+			.intersect_triangle(a, b, c)
+			.is_none());
+	}
+
+	#[test]
+	fn rays_through_the_screen() {
+		let view = cgmath::Matrix4::look_at_rh(
+			cgmath::point3(0.0, 0.0, 5.0),
+			cgmath::point3(0.0, 0.0, 0.0),
+			cgmath::Vector3::unit_y(),
+		);
+		let perspective =
+			cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, 100.0) * view;
+		let centre =
+			Ray::from_ndc(cgmath::vec2(0.0, 0.0), &perspective).unwrap();
+		assert!(close(centre.origin.to_vec(), cgmath::vec3(0.0, 0.0, 4.9)));
+		assert!(close(centre.direction, cgmath::vec3(0.0, 0.0, -1.0)));
+		// 45 degrees out along both axes in the corner.
+		let corner =
+			Ray::from_ndc(cgmath::vec2(1.0, 1.0), &perspective).unwrap();
+		assert!(close(corner.origin.to_vec(), cgmath::vec3(0.1, 0.1, 4.9)));
+		assert!(close(
+			corner.direction,
+			cgmath::vec3(1.0, 1.0, -1.0).normalize()
+		));
+
+		// Orthographic rays all run parallel from the near plane.
+		let orthographic =
+			cgmath::ortho(-2.0, 2.0, -1.0, 1.0, 0.1, 100.0) * view;
+		let side =
+			Ray::from_ndc(cgmath::vec2(0.5, -0.5), &orthographic).unwrap();
+		assert!(close(side.origin.to_vec(), cgmath::vec3(1.0, -0.5, 4.9)));
+		assert!(close(side.direction, cgmath::vec3(0.0, 0.0, -1.0)));
+
+		assert!(Ray::from_ndc(
+			cgmath::vec2(0.0, 0.0),
+			&cgmath::Matrix4::from_scale(0.0)
+		)
+		.is_none());
+		assert_eq!(
+			cursor_ndc((400.0, 0.0), (800, 600)),
+			cgmath::vec2(0.0, 1.0)
+		);
+	}
+
+	// A 2x2 square facing +Z.
+	fn square() -> [[cgmath::Point3<f32>; 3]; 2] {
+		let corners = [
+			cgmath::point3(-1.0, -1.0, 0.0),
+			cgmath::point3(1.0, -1.0, 0.0),
+			cgmath::point3(1.0, 1.0, 0.0),
+			cgmath::point3(-1.0, 1.0, 0.0),
+		];
+		[
+			[corners[0], corners[1], corners[2]],
+			[corners[0], corners[2], corners[3]],
+		]
+	}
+
+	#[test]
+	fn placed_copies() {
+		let transforms = [
+			cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, -5.0)),
+			cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, -2.0))
+				* cgmath::Matrix4::from_scale(2.0),
+			// Edge on to rays along Z.
+			cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 0.0, -1.0))
+				* cgmath::Matrix4::from_angle_y(cgmath::Deg(90.0)),
+			cgmath::Matrix4::from_scale(0.0),
+		];
+		let forward = |x: f32| ray([x, 0.0, 0.0], [0.0, 0.0, -1.0]);
+
+		// The nearest copy wins, distances and points stay in world space.
+		let hit =
+			pick_placed(&forward(0.5), &square(), &transforms, 10).unwrap();
+		assert_eq!(hit.id, 11);
+		assert!((hit.distance - 2.0).abs() < 1e-5);
+		assert!(close(hit.point.to_vec(), cgmath::vec3(0.5, 0.0, -2.0)));
+
+		// Only the scaled copy reaches this far out.
+		let hit =
+			pick_placed(&forward(1.5), &square(), &transforms, 10).unwrap();
+		assert_eq!(hit.id, 11);
+		assert!(
+			pick_placed(&forward(2.5), &square(), &transforms, 10).is_none()
+		);
+
+		// Without the scaled copy the one behind it shows.
+		let hit = pick_placed(&forward(0.5), &square(), &transforms[..1], 10)
+			.unwrap();
+		assert_eq!(hit.id, 10);
+		assert!((hit.distance - 5.0).abs() < 1e-5);
+	}
+}
@@ -0,0 +1,461 @@
+use crate::gamepad::{Gamepad, GamepadState};
+use crate::input::{self, Bindings, Held};
+use std::io::Write;
+
+// Everything the main loop reads in a frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+	// Seconds, from `glfw::Glfw::get_time`.
+	pub time: f64,
+	pub held: Held,
+	pub gamepad: GamepadState,
+	// In screen coordinates like the cursor, which can differ from the
+	// framebuffer's pixels. Picking needs it to place the cursor on screen.
+	pub window_size: (i32, i32),
+	pub events: std::vec::Vec<glfw::WindowEvent>,
+}
+
+// Where a frame's input comes from.
+pub enum Session {
+	Live,
+	Recording(Recorder),
+	Replay(std::vec::IntoIter<Frame>),
+}
+
+impl Session {
+	// `--record <file>` saves the input as it's used, `--replay <file>`
+	// uses a saved session instead of the window's. Anything else runs
+	// live.
+	pub fn from_args(window: &glfw::Window) -> Session {
+		let args: std::vec::Vec<std::string::String> =
+			std::env::args().collect();
+		let path = |flag| {
+			args.iter()
+				.position(|arg| arg == flag)
+				.and_then(|i| args.get(i + 1))
+				.map(std::path::Path::new)
+		};
+		if let Some(path) = path("--replay") {
+			let frames = load(path).expect("Failed to load recording");
+			Session::Replay(frames.into_iter())
+		} else if let Some(path) = path("--record") {
+			let (width, height) = window.get_framebuffer_size();
+			let recorder = Recorder::create(path, width, height)
+				.expect("Failed to start recording");
+			Session::Recording(recorder)
+		} else {
+			Session::Live
+		}
+	}
+
+	// The next frame's input, `None` once a replay has run out. The
+	// gamepad is updated either way.
+	pub fn next_frame(
+		&mut self,
+		glfw: &glfw::Glfw,
+		window: &glfw::Window,
+		events: &std::sync::mpsc::Receiver<(f64, glfw::WindowEvent)>,
+		bindings: &Bindings,
+		gamepad: &mut Gamepad,
+	) -> Option<Frame> {
+		if let Session::Replay(frames) = self {
+			// The real events still have to be taken off the queue.
+			glfw::flush_messages(events).for_each(drop);
+			let frame = frames.next()?;
+			gamepad.inject(Some(frame.gamepad));
+			gamepad.update(glfw);
+			return Some(frame);
+		}
+
+		gamepad.update(glfw);
+		let frame = Frame {
+			time: glfw.get_time(),
+			held: Held::poll(window, bindings),
+			gamepad: *gamepad.state(),
+			window_size: window.get_size(),
+			events: glfw::flush_messages(events)
+				.map(|(_, event)| event)
+				.collect(),
+		};
+		if let Session::Recording(recorder) = self {
+			recorder.write(&frame).expect("Failed to record input");
+		}
+		Some(frame)
+	}
+}
+
+// Writes frames to a text file as they happen, a line for the frame and
+// one for each event:
+//
+//     size 800 600
+//     frame 0.016 held W LeftShift
+//     window 800 600
+//     gamepad 0.0 0.0 0.0 0.0 -1.0 -1.0 000000000000000
+//     cursor 400.0 300.5
+//     key W 17 release 0
+//
+// The window line only comes when the size changes. Floats are written so
+// they read back exactly.
+pub struct Recorder {
+	file: std::io::BufWriter<std::fs::File>,
+	window_size: Option<(i32, i32)>,
+}
+
+impl Recorder {
+	// The framebuffer size the replay starts at.
+	pub fn create(
+		path: &std::path::Path,
+		width: i32,
+		height: i32,
+	) -> std::io::Result<Recorder> {
+		let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+		writeln!(file, "size {} {}", width, height)?;
+		Ok(Recorder {
+			file,
+			window_size: None,
+		})
+	}
+
+	pub fn write(&mut self, frame: &Frame) -> std::io::Result<()> {
+		write!(self.file, "frame {:?} held", frame.time)?;
+		for &input in frame.held.inputs.iter() {
+			if let Some(name) = input::input_name(input) {
+				write!(self.file, " {}", name)?;
+			}
+		}
+		writeln!(self.file)?;
+
+		if self.window_size != Some(frame.window_size) {
+			let (width, height) = frame.window_size;
+			writeln!(self.file, "window {} {}", width, height)?;
+			self.window_size = Some(frame.window_size);
+		}
+
+		if frame.gamepad != GamepadState::idle() {
+			write!(self.file, "gamepad")?;
+			for axis in frame.gamepad.axes.iter() {
+				write!(self.file, " {:?}", axis)?;
+			}
+			let buttons: std::string::String = frame
+				.gamepad
+				.buttons
+				.iter()
+				.map(|&pressed| if pressed { '1' } else { '0' })
+				.collect();
+			writeln!(self.file, " {}", buttons)?;
+		}
+
+		for event in frame.events.iter() {
+			if let Some(line) = event_line(event) {
+				writeln!(self.file, "{}", line)?;
+			}
+		}
+		// A crash shouldn't lose the frames leading up to it.
+		self.file.flush()
+	}
+}
+
+fn action_name(action: glfw::Action) -> &'static str {
+	match action {
+		glfw::Action::Press => "press",
+		glfw::Action::Release => "release",
+		glfw::Action::Repeat => "repeat",
+	}
+}
+
+// `None` for events that can't be written: dropped files, and keys and
+// mouse buttons without a name, which nothing can be bound to.
+fn event_line(event: &glfw::WindowEvent) -> Option<std::string::String> {
+	use glfw::WindowEvent::*;
+	Some(match *event {
+		Pos(x, y) => format!("pos {} {}", x, y),
+		Size(width, height) => format!("window_size {} {}", width, height),
+		Close => "close".to_string(),
+		Refresh => "refresh".to_string(),
+		Focus(focused) => format!("focus {}", focused),
+		Iconify(iconified) => format!("iconify {}", iconified),
+		FramebufferSize(width, height) => {
+			format!("framebuffer_size {} {}", width, height)
+		}
+		MouseButton(button, action, modifiers) => format!(
+			"mouse {} {} {}",
+			input::input_name(input::Input::Mouse(button))?,
+			action_name(action),
+			modifiers.bits()
+		),
+		CursorPos(x, y) => format!("cursor {:?} {:?}", x, y),
+		CursorEnter(entered) => format!("cursor_enter {}", entered),
+		Scroll(x, y) => format!("scroll {:?} {:?}", x, y),
+		Key(key, scancode, action, modifiers) => format!(
+			"key {} {} {} {}",
+			input::input_name(input::Input::Key(key))?,
+			scancode,
+			action_name(action),
+			modifiers.bits()
+		),
+		Char(c) => format!("char {}", c as u32),
+		CharModifiers(c, modifiers) => {
+			format!("char_modifiers {} {}", c as u32, modifiers.bits())
+		}
+		Maximize(maximized) => format!("maximize {}", maximized),
+		ContentScale(x, y) => format!("content_scale {:?} {:?}", x, y),
+		FileDrop(_) => return None,
+	})
+}
+
+fn invalid(line: usize, message: &str) -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::InvalidData,
+		format!("line {}: {}", line, message),
+	)
+}
+
+pub fn load(path: &std::path::Path) -> std::io::Result<std::vec::Vec<Frame>> {
+	parse_recording(&std::fs::read_to_string(path)?)
+}
+
+// The size line becomes a framebuffer resize at the start of the first
+// frame, so the replay starts out the size the recording did. Frames keep
+// the window size of the one before until a window line changes it,
+// recordings from before there were any start out at the framebuffer's.
+pub fn parse_recording(text: &str) -> std::io::Result<std::vec::Vec<Frame>> {
+	let mut frames: std::vec::Vec<Frame> = std::vec::Vec::new();
+	let mut size = None;
+	for (number, line) in text.lines().enumerate() {
+		let number = number + 1;
+		let mut words = line.split_whitespace();
+		let kind = match words.next() {
+			Some(kind) => kind,
+			None => continue,
+		};
+		let words: std::vec::Vec<&str> = words.collect();
+		let bad = || invalid(number, &format!("bad `{}` line", kind));
+
+		match kind {
+			"size" => {
+				if words.len() != 2 {
+					return Err(bad());
+				}
+				size = Some((
+					words[0].parse().map_err(|_| bad())?,
+					words[1].parse().map_err(|_| bad())?,
+				));
+			}
+			"frame" => {
+				if words.get(1) != Some(&"held") {
+					return Err(bad());
+				}
+				let mut frame = Frame {
+					time: words[0].parse().map_err(|_| bad())?,
+					gamepad: GamepadState::idle(),
+					window_size: frames
+						.last()
+						.map(|frame| frame.window_size)
+						.or(size)
+						.unwrap_or_default(),
+					..Frame::default()
+				};
+				for name in words[2..].iter() {
+					let input = input::input_from_name(name)
+						.ok_or_else(|| invalid(number, "unknown key"))?;
+					frame.held.inputs.push(input);
+				}
+				if frames.is_empty() {
+					if let Some((width, height)) = size {
+						frame.events.push(glfw::WindowEvent::FramebufferSize(
+							width, height,
+						));
+					}
+				}
+				frames.push(frame);
+			}
+			_ => {
+				let frame = frames
+					.last_mut()
+					.ok_or_else(|| invalid(number, "expected a frame first"))?;
+				if kind == "gamepad" {
+					frame.gamepad = parse_gamepad(&words).ok_or_else(bad)?;
+				} else if kind == "window" {
+					if words.len() != 2 {
+						return Err(bad());
+					}
+					frame.window_size = (
+						words[0].parse().map_err(|_| bad())?,
+						words[1].parse().map_err(|_| bad())?,
+					);
+				} else {
+					let event = parse_event(kind, &words).ok_or_else(bad)?;
+					frame.events.push(event);
+				}
+			}
+		}
+	}
+	Ok(frames)
+}
+
+fn parse_gamepad(words: &[&str]) -> Option<GamepadState> {
+	let mut state = GamepadState::default();
+	if words.len() != state.axes.len() + 1
+		|| words[state.axes.len()].len() != state.buttons.len()
+	{
+		return None;
+	}
+	for (axis, word) in state.axes.iter_mut().zip(words.iter()) {
+		*axis = word.parse().ok()?;
+	}
+	for (button, c) in state
+		.buttons
+		.iter_mut()
+		.zip(words[state.axes.len()].chars())
+	{
+		*button = match c {
+			'0' => false,
+			'1' => true,
+			_ => return None,
+		};
+	}
+	Some(state)
+}
+
+fn parse_event(kind: &str, words: &[&str]) -> Option<glfw::WindowEvent> {
+	use glfw::WindowEvent::*;
+	fn arg<T: std::str::FromStr>(words: &[&str], i: usize) -> Option<T> {
+		words.get(i)?.parse().ok()
+	}
+	let action = |i| match *words.get(i)? {
+		"press" => Some(glfw::Action::Press),
+		"release" => Some(glfw::Action::Release),
+		"repeat" => Some(glfw::Action::Repeat),
+		_ => None,
+	};
+	let modifiers = |i| arg(words, i).map(glfw::Modifiers::from_bits_truncate);
+	let character = |i| std::char::from_u32(arg(words, i)?);
+
+	Some(match kind {
+		"pos" => Pos(arg(words, 0)?, arg(words, 1)?),
+		"window_size" => Size(arg(words, 0)?, arg(words, 1)?),
+		"close" => Close,
+		"refresh" => Refresh,
+		"focus" => Focus(arg(words, 0)?),
+		"iconify" => Iconify(arg(words, 0)?),
+		"framebuffer_size" => FramebufferSize(arg(words, 0)?, arg(words, 1)?),
+		"mouse" => match input::input_from_name(words.first()?)? {
+			input::Input::Mouse(button) => {
+				MouseButton(button, action(1)?, modifiers(2)?)
+			}
+			input::Input::Key(_) => return None,
+		},
+		"cursor" => CursorPos(arg(words, 0)?, arg(words, 1)?),
+		"cursor_enter" => CursorEnter(arg(words, 0)?),
+		"scroll" => Scroll(arg(words, 0)?, arg(words, 1)?),
+		"key" => match input::input_from_name(words.first()?)? {
+			input::Input::Key(key) => {
+				Key(key, arg(words, 1)?, action(2)?, modifiers(3)?)
+			}
+			input::Input::Mouse(_) => return None,
+		},
+		"char" => Char(character(0)?),
+		"char_modifiers" => CharModifiers(character(0)?, modifiers(1)?),
+		"maximize" => Maximize(arg(words, 0)?),
+		"content_scale" => ContentScale(arg(words, 0)?, arg(words, 1)?),
+		_ => return None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn error(text: &str) -> std::string::String {
+		parse_recording(text).err().unwrap().to_string()
+	}
+
+	#[test]
+	fn recordings_read_back_exactly() {
+		let mut gamepad = GamepadState::idle();
+		gamepad.set_axis(glfw::GamepadAxis::AxisLeftX, 0.1 + 0.2);
+		gamepad.buttons[glfw::GamepadButton::ButtonY as usize] = true;
+		let held = Held {
+			inputs: vec![
+				input::Input::Key(glfw::Key::W),
+				input::Input::Mouse(glfw::MouseButton::Button1),
+			],
+		};
+		// A high DPI window, half the framebuffer's size, made bigger
+		// halfway through.
+		let frames = vec![
+			Frame {
+				time: 0.1,
+				held: held.clone(),
+				gamepad: GamepadState::idle(),
+				window_size: (800, 600),
+				events: vec![glfw::WindowEvent::CursorPos(400.25, 1.0 / 3.0)],
+			},
+			Frame {
+				time: 0.1 + 1.0 / 60.0,
+				held,
+				gamepad,
+				window_size: (1024, 768),
+				events: vec![
+					glfw::WindowEvent::Size(1024, 768),
+					glfw::WindowEvent::FramebufferSize(2048, 1536),
+					glfw::WindowEvent::Key(
+						glfw::Key::Q,
+						24,
+						glfw::Action::Release,
+						glfw::Modifiers::Control,
+					),
+					glfw::WindowEvent::Scroll(0.0, -1.5),
+				],
+			},
+			Frame {
+				time: 0.2,
+				window_size: (1024, 768),
+				gamepad: GamepadState::idle(),
+				..Frame::default()
+			},
+		];
+
+		let path = std::env::temp_dir()
+			.join(format!("record-test-{}.txt", std::process::id()));
+		let mut recorder = Recorder::create(&path, 1600, 1200).unwrap();
+		for frame in frames.iter() {
+			recorder.write(frame).unwrap();
+		}
+		drop(recorder);
+		let text = std::fs::read_to_string(&path).unwrap();
+		let loaded = load(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(text.matches("window ").count(), 2);
+		let mut expected = frames;
+		expected[0]
+			.events
+			.insert(0, glfw::WindowEvent::FramebufferSize(1600, 1200));
+		assert_eq!(loaded, expected);
+	}
+
+	#[test]
+	fn older_recordings_use_the_framebuffer_size() {
+		let frames = parse_recording(
+			"size 800 600\nframe 0.0 held\nframe 0.5 held W\nwindow 400 300\n",
+		)
+		.unwrap();
+		assert_eq!(frames[0].window_size, (800, 600));
+		assert_eq!(frames[1].window_size, (400, 300));
+	}
+
+	#[test]
+	fn bad_recordings_say_which_line() {
+		assert_eq!(
+			error("size 800 600\nframe 0.0 held\nwindow 800\n"),
+			"line 3: bad `window` line"
+		);
+		assert_eq!(error("window 800 600\n"), "line 1: expected a frame first");
+		assert_eq!(error("frame 0.0 held Hyper\n"), "line 1: unknown key");
+		assert_eq!(
+			error("frame 0.0 held\ngamepad 0 0 0 0 -1 -1 01\n"),
+			"line 2: bad `gamepad` line"
+		);
+	}
+}
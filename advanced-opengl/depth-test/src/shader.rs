@@ -42,9 +42,7 @@ pub fn create_shader(vert_path: &str, frag_path: &str) -> u32 {
 
 	let vertex_shader = load_shader(vs_src, gl::VERTEX_SHADER);
 	let fragment_shader = load_shader(fs_src, gl::FRAGMENT_SHADER);
-	let shader_program = compile_program(vertex_shader, fragment_shader);
-
-	shader_program
+	compile_program(vertex_shader, fragment_shader)
 }
 
 pub fn set_mat4(program: u32, name: &str, data: cgmath::Matrix4<f32>) {
@@ -95,4 +93,14 @@ pub fn set_int(program: u32, name: &str, data: i32) {
 
 		gl::Uniform1i(location, data);
 	}
+}
+
+pub fn set_uint(program: u32, name: &str, data: u32) {
+	let c_str = std::ffi::CString::new(name.as_bytes()).unwrap();
+
+	unsafe {
+		let location = gl::GetUniformLocation(program, c_str.as_ptr());
+
+		gl::Uniform1ui(location, data);
+	}
 }
\ No newline at end of file
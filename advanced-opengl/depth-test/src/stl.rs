@@ -0,0 +1,249 @@
+use crate::model::{Mesh, Vertex};
+use cgmath::prelude::*;
+use std::io::Read;
+use std::path::Path;
+
+fn invalid(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Loads a binary or ASCII STL file as a single mesh. The facet normals
+// stored in the file are frequently zero or stale, so every triangle gets
+// its own three vertices with a normal computed from its winding.
+pub fn load_stl(path: &Path) -> std::io::Result<std::vec::Vec<Mesh>> {
+	let mut bytes = std::vec::Vec::new();
+	std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+	parse_stl(&bytes)
+}
+
+pub fn parse_stl(bytes: &[u8]) -> std::io::Result<std::vec::Vec<Mesh>> {
+	let positions = if is_binary(bytes) {
+		parse_binary(bytes)?
+	} else {
+		parse_ascii(bytes)?
+	};
+
+	let mut mesh = Mesh::default();
+	for tri in positions.chunks(3) {
+		let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]);
+		let normal = if normal.magnitude2() > 0.0 {
+			normal.normalize()
+		} else {
+			normal
+		};
+		for &position in tri.iter() {
+			mesh.indices.push(mesh.vertices.len() as u32);
+			mesh.vertices.push(Vertex {
+				position,
+				normal,
+				tex_coords: cgmath::vec2(0.0, 0.0),
+				color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+				tangent: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+			});
+		}
+	}
+	mesh.index_type = gl::UNSIGNED_INT;
+
+	Ok(vec![mesh])
+}
+
+// Binary files may also start with "solid", the size implied by the
+// triangle count is the reliable tell.
+fn is_binary(bytes: &[u8]) -> bool {
+	if bytes.len() < 84 {
+		return false;
+	}
+	let count =
+		u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
+	bytes.len() as u64 == 84 + 50 * count as u64
+}
+
+fn parse_binary(
+	bytes: &[u8],
+) -> std::io::Result<std::vec::Vec<cgmath::Vector3<f32>>> {
+	let count =
+		u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
+	let float = |offset: usize| {
+		f32::from_le_bytes([
+			bytes[offset],
+			bytes[offset + 1],
+			bytes[offset + 2],
+			bytes[offset + 3],
+		])
+	};
+
+	let mut positions = std::vec::Vec::with_capacity(count as usize * 3);
+	for i in 0..count as usize {
+		// 12 bytes of normal, three 12 byte vertices, 2 bytes of attributes.
+		let facet = 84 + i * 50;
+		for v in 0..3 {
+			let offset = facet + 12 + v * 12;
+			positions.push(cgmath::vec3(
+				float(offset),
+				float(offset + 4),
+				float(offset + 8),
+			));
+		}
+	}
+
+	Ok(positions)
+}
+
+fn parse_ascii(
+	bytes: &[u8],
+) -> std::io::Result<std::vec::Vec<cgmath::Vector3<f32>>> {
+	let text =
+		std::str::from_utf8(bytes).map_err(|_| invalid("STL is not text"))?;
+	let mut words = text.split_whitespace();
+	if words.next() != Some("solid") {
+		return Err(invalid("not an STL file"));
+	}
+
+	let mut positions = std::vec::Vec::new();
+	while let Some(word) = words.next() {
+		if word != "vertex" {
+			continue;
+		}
+		let mut coordinate = || -> std::io::Result<f32> {
+			words
+				.next()
+				.and_then(|w| w.parse::<f32>().ok())
+				.ok_or_else(|| invalid("STL vertex has a malformed coordinate"))
+		};
+		let (x, y, z) = (coordinate()?, coordinate()?, coordinate()?);
+		positions.push(cgmath::vec3(x, y, z));
+	}
+
+	if positions.len() % 3 != 0 {
+		return Err(invalid("STL facet does not have three vertices"));
+	}
+
+	Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TRIANGLE: [[f32; 3]; 3] =
+		[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+	// Binary files with `header` in their first 80 bytes and a stale facet
+	// normal.
+	fn binary(header: &[u8], triangles: usize) -> std::vec::Vec<u8> {
+		let mut bytes = header.to_vec();
+		bytes.resize(80, 0);
+		bytes.extend_from_slice(&(triangles as u32).to_le_bytes());
+		for _ in 0..triangles {
+			for &value in [0.0f32, 0.0, -1.0].iter() {
+				bytes.extend_from_slice(&value.to_le_bytes());
+			}
+			for corner in TRIANGLE.iter() {
+				for value in corner.iter() {
+					bytes.extend_from_slice(&value.to_le_bytes());
+				}
+			}
+			bytes.extend_from_slice(&[0, 0]);
+		}
+		bytes
+	}
+
+	fn check_triangles(meshes: &[Mesh], count: usize) {
+		assert_eq!(meshes.len(), 1);
+		let mesh = &meshes[0];
+		assert_eq!(mesh.vertices.len(), count * 3);
+		assert_eq!(
+			mesh.indices,
+			(0..count as u32 * 3).collect::<std::vec::Vec<_>>()
+		);
+		for (i, v) in mesh.vertices.iter().enumerate() {
+			assert_eq!(v.position, cgmath::Vector3::from(TRIANGLE[i % 3]));
+			// From the winding, not the file.
+			assert_eq!(v.normal, cgmath::vec3(0.0, 0.0, 1.0));
+		}
+	}
+
+	#[test]
+	fn binary_files() {
+		check_triangles(&parse_stl(&binary(b"exported", 2)).unwrap(), 2);
+		// Plenty of exporters start binary headers with "solid" too.
+		check_triangles(&parse_stl(&binary(b"solid part", 1)).unwrap(), 1);
+		check_triangles(&parse_stl(&binary(b"", 0)).unwrap(), 0);
+	}
+
+	#[test]
+	fn ascii_files() {
+		let text = "solid part\n\
+			facet normal 0 0 -1\n outer loop\n\
+			vertex 0 0 0\n vertex 1 0 0\n vertex 0 1 0\n\
+			endloop\nendfacet\nendsolid part\n";
+		check_triangles(&parse_stl(text.as_bytes()).unwrap(), 1);
+	}
+
+	fn error(bytes: &[u8]) -> std::string::String {
+		match parse_stl(bytes) {
+			Ok(_) => panic!("parsed a malformed STL"),
+			Err(err) => {
+				assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+				err.to_string()
+			}
+		}
+	}
+
+	#[test]
+	fn malformed_files() {
+		assert_eq!(error(b"facet normal 0 0 1\n"), "not an STL file");
+		assert_eq!(error(b""), "not an STL file");
+		assert_eq!(
+			error(b"solid\nvertex 0 0 zero\n"),
+			"STL vertex has a malformed coordinate"
+		);
+		assert_eq!(
+			error(b"solid\nvertex 0 0"),
+			"STL vertex has a malformed coordinate"
+		);
+		assert_eq!(
+			error(b"solid\nvertex 0 0 0\nvertex 1 0 0\n"),
+			"STL facet does not have three vertices"
+		);
+		// A binary file cut short no longer matches its triangle count and
+		// is then rejected as text.
+		let bytes = binary(b"exported", 2);
+		for length in 84..bytes.len() {
+			assert!(parse_stl(&bytes[..length]).is_err(), "{}", length);
+		}
+	}
+
+	// Loads `bytes` saved as `name` the way the viewer loads `--model`.
+	fn load(name: &str, bytes: &[u8]) -> std::io::Result<std::vec::Vec<Mesh>> {
+		let path = std::env::temp_dir().join(format!(
+			"stl-{}-{}",
+			std::process::id(),
+			name
+		));
+		std::fs::write(&path, bytes).unwrap();
+		let meshes = crate::model::load_meshes(
+			path.to_str().unwrap(),
+			&crate::model::LoadOptions::default(),
+			&mut std::vec::Vec::new(),
+		);
+		let _ = std::fs::remove_file(&path);
+		meshes
+	}
+
+	#[test]
+	fn files_without_facets_load_nothing() {
+		assert!(load("binary.stl", &binary(b"", 0)).unwrap().is_empty());
+		let ascii = b"solid empty\nendsolid empty\n";
+		check_triangles(&parse_stl(ascii).unwrap(), 0);
+		assert!(load("ascii.stl", ascii).unwrap().is_empty());
+	}
+
+	#[test]
+	fn load_errors_reach_the_caller() {
+		let err = load("broken.stl", b"solid\nvertex 0 0 zero\n")
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+	}
+}
@@ -0,0 +1,356 @@
+use crate::model::{compute_bounds, compute_tangents, Material, Mesh, Vertex};
+use crate::optimize;
+use crate::texture;
+use cgmath::prelude::*;
+use std::path::Path;
+
+#[derive(Clone)]
+pub struct TerrainOptions {
+	// Width and depth in world units, centred on the origin.
+	pub size: f32,
+	// Vertices along each side.
+	pub resolution: usize,
+	// Height of a white heightmap pixel or a noise peak.
+	pub height_scale: f32,
+	// How many times the texture repeats along each side.
+	pub texture_repeat: f32,
+	// Diffuse texture, relative to the directory the model is built with.
+	pub texture: std::string::String,
+}
+
+impl Default for TerrainOptions {
+	fn default() -> Self {
+		TerrainOptions {
+			size: 20.0,
+			resolution: 129,
+			height_scale: 2.0,
+			texture_repeat: 10.0,
+			texture: "grass.jpg".into(),
+		}
+	}
+}
+
+// A square grid of heights, row by row from -Z to +Z, each row from -X to +X.
+pub struct Terrain {
+	heights: std::vec::Vec<f32>,
+	options: TerrainOptions,
+}
+
+impl Terrain {
+	// Samples the image's luminance, stretched over the whole terrain with
+	// the top of the image at -Z. 16-bit PNGs keep their full precision.
+	pub fn from_heightmap(
+		path: &Path,
+		options: &TerrainOptions,
+	) -> std::io::Result<Terrain> {
+		Terrain::from_image(&texture::Image::read(path)?, options)
+	}
+
+	fn from_image(
+		img: &texture::Image,
+		options: &TerrainOptions,
+	) -> std::io::Result<Terrain> {
+		let (width, height) = (img.width, img.height);
+		if width == 0 || height == 0 {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"heightmap has no pixels",
+			));
+		}
+		let pixel =
+			|x: u32, y: u32| img.gray(x.min(width - 1), y.min(height - 1));
+
+		Ok(Terrain::from_fn(options, |s, t| {
+			let (x, y) = (s * (width - 1) as f32, t * (height - 1) as f32);
+			let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+			let (fx, fy) = (x.fract(), y.fract());
+			let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1, y0) * fx;
+			let bottom =
+				pixel(x0, y0 + 1) * (1.0 - fx) + pixel(x0 + 1, y0 + 1) * fx;
+			top * (1.0 - fy) + bottom * fy
+		}))
+	}
+
+	// Fractal value noise, the same seed always gives the same terrain.
+	pub fn from_noise(seed: u32, options: &TerrainOptions) -> Terrain {
+		Terrain::from_fn(options, |s, t| {
+			let (mut amplitude, mut frequency) = (0.5, 4.0);
+			let (mut sum, mut total) = (0.0, 0.0);
+			for octave in 0..5u32 {
+				let seed = seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+				sum +=
+					value_noise(seed, s * frequency, t * frequency) * amplitude;
+				total += amplitude;
+				amplitude *= 0.5;
+				frequency *= 2.0;
+			}
+			sum / total
+		})
+	}
+
+	// `height` takes the position on the terrain as 0..1 along X and Z and
+	// returns 0..1, which is scaled by `height_scale`.
+	fn from_fn<F>(options: &TerrainOptions, height: F) -> Terrain
+	where
+		F: Fn(f32, f32) -> f32,
+	{
+		let resolution = options.resolution.max(2);
+		let last = (resolution - 1) as f32;
+		let mut heights = std::vec::Vec::with_capacity(resolution * resolution);
+		for k in 0..resolution {
+			for j in 0..resolution {
+				heights.push(
+					height(j as f32 / last, k as f32 / last)
+						* options.height_scale,
+				);
+			}
+		}
+
+		Terrain {
+			heights,
+			options: TerrainOptions {
+				resolution,
+				..options.clone()
+			},
+		}
+	}
+
+	fn resolution(&self) -> usize {
+		self.options.resolution
+	}
+
+	fn spacing(&self) -> f32 {
+		self.options.size / (self.resolution() - 1) as f32
+	}
+
+	fn sample(&self, j: usize, k: usize) -> f32 {
+		let last = self.resolution() - 1;
+		self.heights[k.min(last) * self.resolution() + j.min(last)]
+	}
+
+	// Height of the surface under a world position, following the same two
+	// triangles per cell the mesh is drawn with. Positions off the terrain
+	// get the height of its nearest edge.
+	pub fn height_at(&self, x: f32, z: f32) -> f32 {
+		let half = self.options.size * 0.5;
+		let last = (self.resolution() - 1) as f32;
+		let gx = ((x + half) / self.spacing()).max(0.0).min(last);
+		let gz = ((z + half) / self.spacing()).max(0.0).min(last);
+		let (j, k) = (gx.floor() as usize, gz.floor() as usize);
+		let (fx, fz) = (gx - j as f32, gz - k as f32);
+
+		let h00 = self.sample(j, k);
+		let h10 = self.sample(j + 1, k);
+		let h01 = self.sample(j, k + 1);
+		let h11 = self.sample(j + 1, k + 1);
+		if fz >= fx {
+			h00 + (h11 - h01) * fx + (h01 - h00) * fz
+		} else {
+			h00 + (h10 - h00) * fx + (h11 - h10) * fz
+		}
+	}
+
+	pub fn mesh(&self) -> Mesh {
+		let resolution = self.resolution();
+		let (half, spacing) = (self.options.size * 0.5, self.spacing());
+		let last = (resolution - 1) as f32;
+		let repeat = self.options.texture_repeat;
+
+		let mut vertices =
+			std::vec::Vec::with_capacity(resolution * resolution);
+		for k in 0..resolution {
+			for j in 0..resolution {
+				// Central differences, one-sided along the edges.
+				let (left, right) = (j.saturating_sub(1), j + 1);
+				let (back, front) = (k.saturating_sub(1), k + 1);
+				let dx = (self.sample(right, k) - self.sample(left, k))
+					/ ((right.min(resolution - 1) - left) as f32 * spacing);
+				let dz = (self.sample(j, front) - self.sample(j, back))
+					/ ((front.min(resolution - 1) - back) as f32 * spacing);
+
+				vertices.push(Vertex {
+					position: cgmath::vec3(
+						j as f32 * spacing - half,
+						self.sample(j, k),
+						k as f32 * spacing - half,
+					),
+					normal: cgmath::vec3(-dx, 1.0, -dz).normalize(),
+					tex_coords: cgmath::vec2(
+						j as f32 / last * repeat,
+						(1.0 - k as f32 / last) * repeat,
+					),
+					color: cgmath::vec4(1.0, 1.0, 1.0, 1.0),
+					tangent: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+				});
+			}
+		}
+
+		let mut indices =
+			std::vec::Vec::with_capacity((resolution - 1).pow(2) * 6);
+		let stride = resolution as u32;
+		for k in 0..stride - 1 {
+			for j in 0..stride - 1 {
+				let a = k * stride + j;
+				let b = a + stride;
+				indices.extend_from_slice(&[a, b, b + 1, a, b + 1, a + 1]);
+			}
+		}
+		compute_tangents(&mut vertices, &indices);
+
+		let mut mesh = Mesh::default();
+		mesh.index_type = optimize::index_type_for(vertices.len());
+		mesh.vertices = vertices;
+		mesh.indices = indices;
+		mesh.material = Some(Material {
+			name: "terrain".into(),
+			ambient: [1.0, 1.0, 1.0],
+			diffuse: [0.8, 0.8, 0.8],
+			specular: [0.1, 0.1, 0.1],
+			shininess: 8.0,
+			dissolve: 1.0,
+			diffuse_texture: self.options.texture.clone(),
+			..Material::default()
+		});
+		compute_bounds(&mut mesh);
+		mesh
+	}
+}
+
+fn hash(seed: u32, x: i32, y: i32) -> f32 {
+	let mut h = seed
+		^ (x as u32).wrapping_mul(0x8da6_b343)
+		^ (y as u32).wrapping_mul(0xd816_3841);
+	h ^= h >> 13;
+	h = h.wrapping_mul(0x5bd1_e995);
+	h ^= h >> 15;
+	(h & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+}
+
+// Random values on the integer lattice, blended with a smoothstep.
+fn value_noise(seed: u32, x: f32, y: f32) -> f32 {
+	let (x0, y0) = (x.floor(), y.floor());
+	let (fx, fy) = (x - x0, y - y0);
+	let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+	let (ix, iy) = (x0 as i32, y0 as i32);
+
+	let top = hash(seed, ix, iy) * (1.0 - sx) + hash(seed, ix + 1, iy) * sx;
+	let bottom =
+		hash(seed, ix, iy + 1) * (1.0 - sx) + hash(seed, ix + 1, iy + 1) * sx;
+	top * (1.0 - sy) + bottom * sy
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn options() -> TerrainOptions {
+		TerrainOptions {
+			size: 8.0,
+			resolution: 9,
+			height_scale: 3.0,
+			..TerrainOptions::default()
+		}
+	}
+
+	// Bumpy enough that the two triangles of a cell never share a plane.
+	fn bumpy() -> Terrain {
+		Terrain::from_fn(&options(), |s, t| {
+			(s * 7.0).sin() * (t * 5.0).cos() + s * t
+		})
+	}
+
+	// Interpolates the mesh's own triangle under (x, z).
+	fn triangle_height(mesh: &Mesh, tri: &[u32], x: f32, z: f32) -> f32 {
+		let [a, b, c] = [tri[0], tri[1], tri[2]]
+			.map(|i| mesh.vertices[i as usize].position);
+		let area = |p: cgmath::Vector3<f32>, q: cgmath::Vector3<f32>| {
+			(q.x - p.x) * (z - p.z) - (x - p.x) * (q.z - p.z)
+		};
+		let total = (b.x - a.x) * (c.z - a.z) - (c.x - a.x) * (b.z - a.z);
+		let (wa, wb, wc) =
+			(area(b, c) / total, area(c, a) / total, area(a, b) / total);
+		assert!(wa >= 0.0 && wb >= 0.0 && wc >= 0.0, "outside {:?}", tri);
+		wa * a.y + wb * b.y + wc * c.y
+	}
+
+	#[test]
+	fn heights_match_the_mesh_vertices() {
+		let terrain = bumpy();
+		let mesh = terrain.mesh();
+		assert_eq!(mesh.vertices.len(), 81);
+		for v in mesh.vertices.iter() {
+			let height = terrain.height_at(v.position.x, v.position.z);
+			assert!((height - v.position.y).abs() < 1e-5, "{:?}", v.position);
+		}
+	}
+
+	#[test]
+	fn heights_follow_both_triangles_of_a_cell() {
+		let terrain = bumpy();
+		let mesh = terrain.mesh();
+		let spacing = terrain.spacing();
+		// Cells are indexed like the vertices, two triangles each.
+		for (cell, quad) in mesh.indices.chunks(6).enumerate() {
+			let (j, k) = ((cell % 8) as f32, (cell / 8) as f32);
+			let corner = |fx: f32, fz: f32| {
+				((j + fx) * spacing - 4.0, (k + fz) * spacing - 4.0)
+			};
+			// The first triangle is the one towards +Z, the second
+			// towards +X.
+			for (tri, (fx, fz)) in
+				[(&quad[..3], (0.25, 0.7)), (&quad[3..], (0.7, 0.25))].iter()
+			{
+				let (x, z) = corner(*fx, *fz);
+				let expected = triangle_height(&mesh, tri, x, z);
+				assert!(
+					(terrain.height_at(x, z) - expected).abs() < 1e-4,
+					"cell {} at {:?}",
+					cell,
+					(fx, fz)
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn positions_off_the_terrain_clamp_to_the_edge() {
+		let terrain = bumpy();
+		assert_eq!(terrain.height_at(-100.0, -100.0), terrain.sample(0, 0));
+		assert_eq!(terrain.height_at(100.0, 100.0), terrain.sample(8, 8));
+		assert_eq!(
+			terrain.height_at(-100.0, 1.0),
+			terrain.height_at(-4.0, 1.0)
+		);
+		assert_eq!(terrain.height_at(1.5, 100.0), terrain.height_at(1.5, 4.0));
+	}
+
+	#[test]
+	fn heightmaps_scale_luminance() {
+		let img = texture::Image {
+			width: 2,
+			height: 2,
+			channels: 1,
+			pixels: texture::Pixels::U8(vec![0, 255, 255, 0]),
+		};
+		let terrain = Terrain::from_image(&img, &options()).unwrap();
+		// The top of the image is at -Z.
+		assert_eq!(terrain.height_at(-4.0, -4.0), 0.0);
+		assert_eq!(terrain.height_at(4.0, -4.0), 3.0);
+		assert_eq!(terrain.height_at(-4.0, 4.0), 3.0);
+		assert_eq!(terrain.height_at(0.0, 0.0), 1.5);
+	}
+
+	#[test]
+	fn bad_heightmaps_are_errors() {
+		let empty = texture::Image {
+			width: 0,
+			height: 0,
+			channels: 1,
+			pixels: texture::Pixels::U8(vec![]),
+		};
+		assert!(Terrain::from_image(&empty, &options()).is_err());
+		let missing = Path::new("no-such-heightmap.png");
+		let err = Terrain::from_heightmap(missing, &options()).err().unwrap();
+		assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+	}
+}
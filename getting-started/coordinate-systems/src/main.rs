@@ -222,6 +222,9 @@ fn main() {
 
 	gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
 
+	let (width, height) = window.get_framebuffer_size();
+	let mut aspect = width as f32 / height as f32;
+
 	let vao: u32;
 	let shader_program: u32;
 	let texture: u32;
@@ -231,9 +234,15 @@ fn main() {
 	while !window.should_close() {
 		for (_, event) in glfw::flush_messages(&events) {
 			match event {
-				glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
-					gl::Viewport(0, 0, width, height)
-				},
+				glfw::WindowEvent::FramebufferSize(width, height) => {
+					unsafe {
+						gl::Viewport(0, 0, width, height);
+					}
+					// Minimised windows report zero.
+					if height > 0 {
+						aspect = width as f32 / height as f32;
+					}
+				}
 				glfw::WindowEvent::Key(
 					glfw::Key::Escape,
 					_,
@@ -247,7 +256,7 @@ fn main() {
 		unsafe {
 			let model = Matrix4::from_angle_x(Deg(-55.0));
 			let view = Matrix4::from_translation(vec3(0.0, 0.0, -3.0));
-			let projection: Matrix4<f32> = perspective(Deg(45.0), 800 as f32 / 600 as f32, 0.1, 100.0);
+			let projection: Matrix4<f32> = perspective(Deg(45.0), aspect, 0.1, 100.0);
 
 			let model_loc = gl::GetUniformLocation(shader_program, c_str!("model").as_ptr());
 			let view_loc = gl::GetUniformLocation(shader_program, c_str!("view").as_ptr());
@@ -1,14 +1,17 @@
-#![feature(destructuring_assignment)]
-#![feature(or_patterns)]
-
 extern crate cgmath;
 extern crate gl;
 extern crate glfw;
 extern crate image;
 
+mod camera;
+mod flythrough;
+mod gamepad;
+mod gpu;
+mod input;
+mod record;
 mod shader;
+mod vertex;
 use crate::glfw::Context;
-use cgmath::prelude::*;
 
 fn gl_init() -> (
 	glfw::Glfw,
@@ -32,6 +35,7 @@ fn gl_init() -> (
 	window.set_key_polling(true);
 	window.set_cursor_pos_polling(true);
 	window.set_scroll_polling(true);
+	window.set_mouse_button_polling(true);
 	window.set_framebuffer_size_polling(true);
 	window.set_cursor_mode(glfw::CursorMode::Disabled);
 
@@ -46,70 +50,72 @@ fn gl_init() -> (
 	(glfw, window, events)
 }
 
-fn create_vao() -> (u32, u32) {
-	let mut object_vao: u32 = 0;
-	let mut light_vao: u32 = 0;
-	let mut vbo: u32 = 0;
+// The cube vertices are shared by both vertex arrays.
+struct CubeVertex {
+	position: [f32; 3],
+	normal: [f32; 3],
+}
 
-	unsafe {
-		gl::GenVertexArrays(1, &mut object_vao);
-		gl::BindVertexArray(object_vao);
+vertex::vertex_layout!(CubeVertex {
+	position: 0,
+	normal: 1,
+});
+
+struct CubeArrays {
+	object: gpu::VertexArray,
+	light: gpu::VertexArray,
+	_vbo: gpu::Buffer,
+}
 
-		gl::GenBuffers(1, &mut vbo);
-		gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+fn create_vao() -> CubeArrays {
+	let object_vao = gpu::VertexArray::new();
+	let light_vao = gpu::VertexArray::new();
+	let vbo = gpu::Buffer::new();
+
+	let vertices: std::vec::Vec<CubeVertex> = CUBE_VERTICES
+		.chunks(6)
+		.map(|v| CubeVertex {
+			position: [v[0], v[1], v[2]],
+			normal: [v[3], v[4], v[5]],
+		})
+		.collect();
+
+	object_vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	unsafe {
 		gl::BufferData(
 			gl::ARRAY_BUFFER,
-			(CUBE_VERTICES.len() * std::mem::size_of::<gl::types::GLfloat>())
+			(vertices.len() * std::mem::size_of::<CubeVertex>())
 				as gl::types::GLsizeiptr,
-			&CUBE_VERTICES[0] as *const f32 as *const std::os::raw::c_void,
+			vertices.as_ptr() as *const std::os::raw::c_void,
 			gl::STATIC_DRAW,
 		);
-
-		let stride =
-			6 * std::mem::size_of::<gl::types::GLfloat>() as gl::types::GLsizei;
-		gl::VertexAttribPointer(
-			0,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			std::ptr::null(),
-		);
-		gl::EnableVertexAttribArray(0);
-
-		gl::VertexAttribPointer(
-			1,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			(3 * std::mem::size_of::<gl::types::GLfloat>()) as *const std::os::raw::c_void,
-		);
-		gl::EnableVertexAttribArray(1);
-
-		gl::GenVertexArrays(1, &mut light_vao);
-		gl::BindVertexArray(light_vao);
-		gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-		gl::VertexAttribPointer(
-			0,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			std::ptr::null(),
-		);
-		gl::EnableVertexAttribArray(0);
 	}
+	vertex::set_attributes::<CubeVertex>(0, 0);
+
+	// The light shader only reads the position.
+	light_vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	vertex::set_attributes::<CubeVertex>(0, 0);
 
-	(object_vao, light_vao)
+	CubeArrays {
+		object: object_vao,
+		light: light_vao,
+		_vbo: vbo,
+	}
 }
 
 fn main() {
 	let (mut glfw, mut window, events) = gl_init();
-	let mut camera_pos = cgmath::point3(0.0, 0.0, 6.0);
-	let mut camera_front = cgmath::vec3(0.0, 0.0, -1.0);
-	let camera_up = cgmath::vec3(0.0, 1.0, 0.0);
-	let camera_speed = 2.5;
+	let mut camera = camera::Camera::new(cgmath::point3(0.0, 0.0, 6.0));
+	let (width, height) = window.get_framebuffer_size();
+	camera.projection.resize(width, height);
+	let bindings = input::Bindings::load_or_default("bindings.toml");
+	let mut gamepad = gamepad::Gamepad::default();
+	let mut session = record::Session::from_args(&window);
+	let mut flythrough =
+		flythrough::Flythrough::load_or_default("flythrough.txt");
+	let mut wireframe = false;
 
 	let light_pos = cgmath::vec3(1.2, 1.0, 2.0);
 	let light_color = cgmath::vec3(1.0, 1.0, 1.0);
@@ -119,98 +125,87 @@ fn main() {
 	let object_color = cgmath::vec3(1.0, 0.5, 0.31);
 	let object_shader = shader::create_shader("object.vert", "object.frag");
 
-	let (object_vao, light_vao) = create_vao();
+	let cube_arrays = create_vao();
 
 	let mut last_frame: f32 = 0.0;
-	let mut first_mouse = true;
-	let mut yaw: f32 = -90.0;
-	let mut pitch: f32 = 0.0;
-	let mut last_x: f32 = 400.0;
-	let mut last_y: f32 = 300.0;
 
 	while !window.should_close() {
-		let current_frame = glfw.get_time() as f32;
+		let frame = match session.next_frame(
+			&glfw,
+			&window,
+			&events,
+			&bindings,
+			&mut gamepad,
+		) {
+			Some(frame) => frame,
+			None => break,
+		};
+		let current_frame = frame.time as f32;
 		let delta_time = current_frame - last_frame;
 		last_frame = current_frame;
 
-		for (_, event) in glfw::flush_messages(&events) {
+		let mut actions = gamepad.triggered();
+		for event in frame.events {
+			actions.extend(bindings.triggered(&event));
 			match event {
-				glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
-					gl::Viewport(0, 0, width, height)
-				},
-				glfw::WindowEvent::Key(
-					glfw::Key::Escape | glfw::Key::Q,
-					_,
-					glfw::Action::Release,
-					_,
-				) => {
-					window.set_should_close(true);
+				glfw::WindowEvent::FramebufferSize(width, height) => {
+					unsafe {
+						gl::Viewport(0, 0, width, height);
+					}
+					camera.projection.resize(width, height);
 				}
 				glfw::WindowEvent::CursorPos(xpos, ypos) => {
-					let (xpos, ypos) = (xpos as f32, ypos as f32);
-					if first_mouse {
-						last_x = xpos;
-						last_y = ypos;
-						first_mouse = false;
-					}
-
-					let mut xoffset = xpos - last_x;
-					let mut yoffset = last_y - ypos;
-					last_x = xpos;
-					last_y = ypos;
-
-					let sensitivity: f32 = 0.1;
-					xoffset *= sensitivity;
-					yoffset *= sensitivity;
-
-					yaw += xoffset;
-					pitch += yoffset;
+					camera.mouse_moved(xpos as f32, ypos as f32);
+				}
+				glfw::WindowEvent::Scroll(_, yoffset) => {
+					camera.scroll(yoffset as f32);
+				}
+				_ => {}
+			}
+		}
 
-					if pitch > 89.0 {
-						pitch = 89.0;
-					}
-					if pitch < -89.0 {
-						pitch = -89.0;
+		for action in actions {
+			match action {
+				input::Action::Quit => {
+					window.set_should_close(true);
+				}
+				input::Action::NextCameraMode => {
+					camera.next_mode();
+				}
+				input::Action::ToggleProjection => {
+					camera.toggle_projection();
+				}
+				input::Action::AddKeyframe => {
+					flythrough.add_keyframe(&camera);
+				}
+				input::Action::ClearKeyframes => {
+					flythrough.clear();
+				}
+				input::Action::PlayFlythrough => {
+					flythrough.toggle(&mut camera);
+				}
+				input::Action::ToggleWireframe => {
+					wireframe = !wireframe;
+					let mode = if wireframe { gl::LINE } else { gl::FILL };
+					unsafe {
+						gl::PolygonMode(gl::FRONT_AND_BACK, mode);
 					}
-
-					let front = cgmath::Vector3 {
-						x: yaw.to_radians().cos() * pitch.to_radians().cos(),
-						y: pitch.to_radians().sin(),
-						z: yaw.to_radians().sin() * pitch.to_radians().cos(),
-					};
-					camera_front = front.normalize();
 				}
 				_ => {}
 			}
 		}
 
-		if window.get_key(glfw::Key::W) == glfw::Action::Press {
-			camera_pos += camera_speed * camera_front * delta_time;
-		}
-		if window.get_key(glfw::Key::S) == glfw::Action::Press {
-			camera_pos -= camera_speed * camera_front * delta_time;
-		}
-		if window.get_key(glfw::Key::A) == glfw::Action::Press {
-			camera_pos -=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
-		}
-		if window.get_key(glfw::Key::D) == glfw::Action::Press {
-			camera_pos +=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
+		if flythrough.playing() {
+			flythrough.update(&mut camera, delta_time);
+		} else {
+			camera.process_input(&bindings, &frame.held, delta_time);
+			camera.process_gamepad(&gamepad, delta_time);
+			camera.update(delta_time);
 		}
 
 		unsafe {
-			let view = cgmath::Matrix4::look_at_rh(
-				camera_pos,
-				camera_pos + camera_front,
-				camera_up,
-			);
-			let projection: cgmath::Matrix4<f32> = cgmath::perspective(
-				cgmath::Deg(45.0),
-				800 as f32 / 600 as f32,
-				0.1,
-				100.0,
-			);
+			let view = camera.view();
+			let projection = camera.projection();
 
 			gl::ClearColor(0.22, 0.18, 0.22, 1.0);
 			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -224,7 +219,7 @@ fn main() {
 			let model = cgmath::Matrix4::from_translation(object_pos);
 			shader::set_mat4(object_shader, "model", model);
 
-			gl::BindVertexArray(object_vao);
+			cube_arrays.object.bind();
 			gl::DrawArrays(gl::TRIANGLES, 0, 36);
 
 			gl::UseProgram(light_shader);
@@ -234,13 +229,16 @@ fn main() {
 			model = model * cgmath::Matrix4::from_scale(0.2);
 			shader::set_mat4(light_shader, "model", model);
 
-			gl::BindVertexArray(light_vao);
+			cube_arrays.light.bind();
 			gl::DrawArrays(gl::TRIANGLES, 0, 36);
 		}
 
 		glfw.poll_events();
 		window.swap_buffers();
 	}
+
+	drop(cube_arrays);
+	gpu::report_leaks();
 }
 
 static CUBE_VERTICES: [f32; 216] = [
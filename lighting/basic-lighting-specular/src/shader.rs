@@ -40,9 +40,7 @@ pub fn create_shader(vert_path: &str, frag_path: &str) -> u32 {
 
 	let vertex_shader = load_shader(vs_src, gl::VERTEX_SHADER);
 	let fragment_shader = load_shader(fs_src, gl::FRAGMENT_SHADER);
-	let shader_program = compile_program(vertex_shader, fragment_shader);
-
-	shader_program
+	compile_program(vertex_shader, fragment_shader)
 }
 
 pub fn set_mat4(program: u32, name: &str, data: cgmath::Matrix4<f32>) {
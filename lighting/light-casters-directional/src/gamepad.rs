@@ -0,0 +1,399 @@
+use crate::input::Action;
+
+// What a gamepad is doing, read from glfw or made up by a test, see
+// `Gamepad::inject`. Indexed by `glfw::GamepadAxis` and
+// `glfw::GamepadButton`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadState {
+	// Sticks from -1 to 1 with y growing downwards, triggers from -1
+	// released to 1 pulled all the way.
+	pub axes: [f32; 6],
+	pub buttons: [bool; 15],
+}
+
+impl GamepadState {
+	// Centred sticks and released triggers.
+	pub fn idle() -> GamepadState {
+		let mut state = GamepadState::default();
+		state.set_axis(glfw::GamepadAxis::AxisLeftTrigger, -1.0);
+		state.set_axis(glfw::GamepadAxis::AxisRightTrigger, -1.0);
+		state
+	}
+
+	fn from_glfw(state: &glfw::GamepadState) -> GamepadState {
+		let mut read = GamepadState::default();
+		for (i, &axis) in AXES.iter().enumerate() {
+			read.axes[i] = state.get_axis(axis);
+		}
+		for (i, &button) in BUTTONS.iter().enumerate() {
+			read.buttons[i] =
+				state.get_button_state(button) == glfw::Action::Press;
+		}
+		read
+	}
+
+	pub fn axis(&self, axis: glfw::GamepadAxis) -> f32 {
+		self.axes[axis as usize]
+	}
+
+	pub fn set_axis(&mut self, axis: glfw::GamepadAxis, value: f32) {
+		self.axes[axis as usize] = value;
+	}
+
+	pub fn button(&self, button: glfw::GamepadButton) -> bool {
+		self.buttons[button as usize]
+	}
+}
+
+const AXES: [glfw::GamepadAxis; 6] = [
+	glfw::GamepadAxis::AxisLeftX,
+	glfw::GamepadAxis::AxisLeftY,
+	glfw::GamepadAxis::AxisRightX,
+	glfw::GamepadAxis::AxisRightY,
+	glfw::GamepadAxis::AxisLeftTrigger,
+	glfw::GamepadAxis::AxisRightTrigger,
+];
+
+const BUTTONS: [glfw::GamepadButton; 15] = [
+	glfw::GamepadButton::ButtonA,
+	glfw::GamepadButton::ButtonB,
+	glfw::GamepadButton::ButtonX,
+	glfw::GamepadButton::ButtonY,
+	glfw::GamepadButton::ButtonLeftBumper,
+	glfw::GamepadButton::ButtonRightBumper,
+	glfw::GamepadButton::ButtonBack,
+	glfw::GamepadButton::ButtonStart,
+	glfw::GamepadButton::ButtonGuide,
+	glfw::GamepadButton::ButtonLeftThumb,
+	glfw::GamepadButton::ButtonRightThumb,
+	glfw::GamepadButton::ButtonDpadUp,
+	glfw::GamepadButton::ButtonDpadRight,
+	glfw::GamepadButton::ButtonDpadDown,
+	glfw::GamepadButton::ButtonDpadLeft,
+];
+
+const JOYSTICKS: [glfw::JoystickId; 16] = [
+	glfw::JoystickId::Joystick1,
+	glfw::JoystickId::Joystick2,
+	glfw::JoystickId::Joystick3,
+	glfw::JoystickId::Joystick4,
+	glfw::JoystickId::Joystick5,
+	glfw::JoystickId::Joystick6,
+	glfw::JoystickId::Joystick7,
+	glfw::JoystickId::Joystick8,
+	glfw::JoystickId::Joystick9,
+	glfw::JoystickId::Joystick10,
+	glfw::JoystickId::Joystick11,
+	glfw::JoystickId::Joystick12,
+	glfw::JoystickId::Joystick13,
+	glfw::JoystickId::Joystick14,
+	glfw::JoystickId::Joystick15,
+	glfw::JoystickId::Joystick16,
+];
+
+// Buttons that set off actions when released, like the keys in
+// `input::Bindings::triggered`.
+const BUTTON_ACTIONS: [(glfw::GamepadButton, Action); 5] = [
+	(glfw::GamepadButton::ButtonY, Action::NextCameraMode),
+	(glfw::GamepadButton::ButtonX, Action::ToggleProjection),
+	(glfw::GamepadButton::ButtonBack, Action::ToggleWireframe),
+	(glfw::GamepadButton::ButtonDpadUp, Action::ToggleSky),
+	(glfw::GamepadButton::ButtonDpadDown, Action::ToggleWalking),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct GamepadOptions {
+	// How far a stick or trigger has to move before it does anything, the
+	// rest of its travel is stretched to cover the whole range.
+	pub dead_zone: f32,
+	// Travel past the dead zone is raised to this power, so small
+	// movements are finer. 1 is linear.
+	pub exponent: f32,
+	// Degrees per second with the right stick all the way over.
+	pub look_speed: f32,
+	pub invert_y: bool,
+}
+
+impl Default for GamepadOptions {
+	fn default() -> GamepadOptions {
+		GamepadOptions {
+			dead_zone: 0.15,
+			exponent: 2.0,
+			look_speed: 120.0,
+			invert_y: false,
+		}
+	}
+}
+
+impl GamepadOptions {
+	// Both axes of a stick together, so diagonals aren't cut off by the
+	// dead zone and come out no faster than straight ahead.
+	pub fn stick(&self, x: f32, y: f32) -> cgmath::Vector2<f32> {
+		let length = (x * x + y * y).sqrt();
+		if length <= self.dead_zone {
+			return cgmath::vec2(0.0, 0.0);
+		}
+		let scaled = self.curve(length);
+		cgmath::vec2(x, y) * (scaled / length)
+	}
+
+	// A trigger from -1 released to 1 pulled, as 0 to 1.
+	pub fn trigger(&self, value: f32) -> f32 {
+		let pulled = (value + 1.0) / 2.0;
+		if pulled <= self.dead_zone {
+			0.0
+		} else {
+			self.curve(pulled)
+		}
+	}
+
+	fn curve(&self, travel: f32) -> f32 {
+		((travel - self.dead_zone) / (1.0 - self.dead_zone))
+			.min(1.0)
+			.powf(self.exponent)
+	}
+}
+
+// The first connected gamepad. Controllers can come and go while running,
+// `update` picks up whichever is plugged in.
+pub struct Gamepad {
+	pub options: GamepadOptions,
+	joystick: Option<glfw::JoystickId>,
+	injected: Option<GamepadState>,
+	state: GamepadState,
+	previous: GamepadState,
+}
+
+impl Default for Gamepad {
+	fn default() -> Gamepad {
+		Gamepad {
+			options: GamepadOptions::default(),
+			joystick: None,
+			injected: None,
+			state: GamepadState::idle(),
+			previous: GamepadState::idle(),
+		}
+	}
+}
+
+impl Gamepad {
+	// Reads the state for this frame, once a frame.
+	pub fn update(&mut self, glfw: &glfw::Glfw) {
+		self.previous = self.state;
+		self.state = match self.injected {
+			Some(state) => state,
+			None => self.read(glfw).unwrap_or_else(GamepadState::idle),
+		};
+	}
+
+	// Used instead of any real controller from the next `update` on, until
+	// given `None`.
+	pub fn inject(&mut self, state: Option<GamepadState>) {
+		self.injected = state;
+	}
+
+	pub fn state(&self) -> &GamepadState {
+		&self.state
+	}
+
+	fn read(&mut self, glfw: &glfw::Glfw) -> Option<GamepadState> {
+		if let Some(id) = self.joystick {
+			if !glfw.get_joystick(id).is_gamepad() {
+				println!("Gamepad disconnected");
+				self.joystick = None;
+			}
+		}
+		if self.joystick.is_none() {
+			self.joystick = JOYSTICKS
+				.iter()
+				.copied()
+				.find(|&id| glfw.get_joystick(id).is_gamepad());
+			let joystick = glfw.get_joystick(self.joystick?);
+			println!(
+				"Gamepad connected: {}",
+				joystick.get_gamepad_name().unwrap_or_default()
+			);
+		}
+		let state = glfw.get_joystick(self.joystick?).get_gamepad_state()?;
+		Some(GamepadState::from_glfw(&state))
+	}
+
+	// How far 0 to 1 the controls for a movement or roll action are
+	// pushed, the left stick and triggers move and the bumpers roll.
+	pub fn amount(&self, action: Action) -> f32 {
+		let left = self.options.stick(
+			self.state.axis(glfw::GamepadAxis::AxisLeftX),
+			self.state.axis(glfw::GamepadAxis::AxisLeftY),
+		);
+		let button = |button| {
+			if self.state.button(button) {
+				1.0
+			} else {
+				0.0
+			}
+		};
+		match action {
+			Action::MoveForward => (-left.y).max(0.0),
+			Action::MoveBackward => left.y.max(0.0),
+			Action::MoveLeft => (-left.x).max(0.0),
+			Action::MoveRight => left.x.max(0.0),
+			Action::MoveUp => self
+				.options
+				.trigger(self.state.axis(glfw::GamepadAxis::AxisRightTrigger)),
+			Action::MoveDown => self
+				.options
+				.trigger(self.state.axis(glfw::GamepadAxis::AxisLeftTrigger)),
+			Action::RollLeft => button(glfw::GamepadButton::ButtonLeftBumper),
+			Action::RollRight => button(glfw::GamepadButton::ButtonRightBumper),
+			_ => 0.0,
+		}
+	}
+
+	// Degrees per second from the right stick, positive turns right and
+	// up as `camera::Camera::rotate` takes them.
+	pub fn look(&self) -> cgmath::Vector2<f32> {
+		let right = self.options.stick(
+			self.state.axis(glfw::GamepadAxis::AxisRightX),
+			self.state.axis(glfw::GamepadAxis::AxisRightY),
+		);
+		let y = if self.options.invert_y {
+			right.y
+		} else {
+			-right.y
+		};
+		cgmath::vec2(right.x, y) * self.options.look_speed
+	}
+
+	// Actions whose buttons were released since the last `update`.
+	pub fn triggered(&self) -> std::vec::Vec<Action> {
+		BUTTON_ACTIONS
+			.iter()
+			.filter(|&&(button, _)| {
+				self.previous.button(button) && !self.state.button(button)
+			})
+			.map(|&(_, action)| action)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::prelude::*;
+
+	fn assert_close(a: f32, b: f32) {
+		assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+	}
+
+	fn gamepad(state: GamepadState) -> Gamepad {
+		Gamepad {
+			state,
+			..Gamepad::default()
+		}
+	}
+
+	// Everything a gamepad moves or rolls with.
+	const ACTIONS: [Action; 8] = [
+		Action::MoveForward,
+		Action::MoveBackward,
+		Action::MoveLeft,
+		Action::MoveRight,
+		Action::MoveUp,
+		Action::MoveDown,
+		Action::RollLeft,
+		Action::RollRight,
+	];
+
+	#[test]
+	fn sticks_ignore_the_dead_zone() {
+		let options = GamepadOptions::default();
+		assert_eq!(options.stick(0.0, 0.0), cgmath::vec2(0.0, 0.0));
+		assert_eq!(options.stick(0.15, 0.0), cgmath::vec2(0.0, 0.0));
+		assert_eq!(options.stick(0.09, -0.09), cgmath::vec2(0.0, 0.0));
+		let past = options.stick(0.16, 0.0);
+		assert!(past.x > 0.0 && past.x < 1e-3);
+	}
+
+	#[test]
+	fn sticks_curve_up_to_full_deflection() {
+		let options = GamepadOptions::default();
+		assert_close(options.stick(1.0, 0.0).x, 1.0);
+		assert_close(options.stick(0.0, -1.0).y, -1.0);
+		// Halfway past the dead zone, squared.
+		assert_close(options.stick(0.575, 0.0).x, 0.25);
+		// Diagonals, and corners of square gates past the unit circle, are
+		// no faster than straight ahead.
+		let diagonal = options.stick(0.5f32.sqrt(), 0.5f32.sqrt());
+		assert_close(diagonal.magnitude(), 1.0);
+		assert_close(diagonal.x, diagonal.y);
+		assert_close(options.stick(1.0, 1.0).magnitude(), 1.0);
+
+		let linear = GamepadOptions {
+			exponent: 1.0,
+			..options
+		};
+		assert_close(linear.stick(0.575, 0.0).x, 0.5);
+	}
+
+	#[test]
+	fn triggers_from_released_to_pulled() {
+		let options = GamepadOptions::default();
+		assert_eq!(options.trigger(-1.0), 0.0);
+		assert_eq!(options.trigger(-0.7), 0.0);
+		assert_close(options.trigger(1.0), 1.0);
+		assert_close(options.trigger(0.15), 0.25);
+	}
+
+	#[test]
+	fn idle_pads_do_nothing() {
+		let gamepad = gamepad(GamepadState::idle());
+		for &action in ACTIONS.iter() {
+			assert_eq!(gamepad.amount(action), 0.0);
+		}
+		assert_eq!(gamepad.look(), cgmath::vec2(0.0, 0.0));
+		assert!(gamepad.triggered().is_empty());
+	}
+
+	#[test]
+	fn controls_map_to_actions() {
+		let mut state = GamepadState::idle();
+		// Pushed up and to the left, y growing downwards.
+		state.set_axis(glfw::GamepadAxis::AxisLeftX, -1.0);
+		state.set_axis(glfw::GamepadAxis::AxisLeftY, -0.1);
+		state.set_axis(glfw::GamepadAxis::AxisRightTrigger, 1.0);
+		state.buttons[glfw::GamepadButton::ButtonRightBumper as usize] = true;
+		let gamepad = gamepad(state);
+		let amounts: std::vec::Vec<f32> = ACTIONS
+			.iter()
+			.map(|&action| gamepad.amount(action))
+			.collect();
+		// Past full deflection, so the direction at full speed.
+		let length = 1.01f32.sqrt();
+		assert_close(amounts[0], 0.1 / length);
+		assert_eq!(amounts[1], 0.0);
+		assert_close(amounts[2], 1.0 / length);
+		assert_eq!(&amounts[3..], &[0.0, 1.0, 0.0, 0.0, 1.0]);
+	}
+
+	#[test]
+	fn looking_up_is_pushing_up_unless_inverted() {
+		let mut state = GamepadState::idle();
+		state.set_axis(glfw::GamepadAxis::AxisRightY, -1.0);
+		let mut gamepad = gamepad(state);
+		assert_close(gamepad.look().y, 120.0);
+		gamepad.options.invert_y = true;
+		assert_close(gamepad.look().y, -120.0);
+	}
+
+	#[test]
+	fn buttons_trigger_on_release() {
+		let mut pressed = GamepadState::idle();
+		pressed.buttons[glfw::GamepadButton::ButtonY as usize] = true;
+		pressed.buttons[glfw::GamepadButton::ButtonA as usize] = true;
+		let mut gamepad = gamepad(pressed);
+		assert!(gamepad.triggered().is_empty());
+		gamepad.previous = pressed;
+		gamepad.state = GamepadState::idle();
+		assert_eq!(gamepad.triggered(), vec![Action::NextCameraMode]);
+	}
+}
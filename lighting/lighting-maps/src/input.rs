@@ -0,0 +1,595 @@
+use std::path::Path;
+
+// What the controls do, whatever they are bound to. Chapters handle the ones
+// they have a use for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+	MoveForward,
+	MoveBackward,
+	MoveLeft,
+	MoveRight,
+	MoveUp,
+	MoveDown,
+	RollLeft,
+	RollRight,
+	NextCameraMode,
+	ToggleProjection,
+	ToggleWireframe,
+	ToggleSky,
+	ToggleWalking,
+	Export,
+	AddKeyframe,
+	ClearKeyframes,
+	PlayFlythrough,
+	Pick,
+	TogglePickMode,
+	ToggleCursor,
+	NextGizmoMode,
+	Snap,
+	Quit,
+}
+
+// Names in the bindings file.
+const ACTIONS: [(&str, Action); 23] = [
+	("move_forward", Action::MoveForward),
+	("move_backward", Action::MoveBackward),
+	("move_left", Action::MoveLeft),
+	("move_right", Action::MoveRight),
+	("move_up", Action::MoveUp),
+	("move_down", Action::MoveDown),
+	("roll_left", Action::RollLeft),
+	("roll_right", Action::RollRight),
+	("next_camera_mode", Action::NextCameraMode),
+	("toggle_projection", Action::ToggleProjection),
+	("toggle_wireframe", Action::ToggleWireframe),
+	("toggle_sky", Action::ToggleSky),
+	("toggle_walking", Action::ToggleWalking),
+	("export", Action::Export),
+	("add_keyframe", Action::AddKeyframe),
+	("clear_keyframes", Action::ClearKeyframes),
+	("play_flythrough", Action::PlayFlythrough),
+	("pick", Action::Pick),
+	("toggle_pick_mode", Action::TogglePickMode),
+	("toggle_cursor", Action::ToggleCursor),
+	("next_gizmo_mode", Action::NextGizmoMode),
+	("snap", Action::Snap),
+	("quit", Action::Quit),
+];
+
+const DEFAULTS: [(Action, &str); 24] = [
+	(Action::MoveForward, "W"),
+	(Action::MoveBackward, "S"),
+	(Action::MoveLeft, "A"),
+	(Action::MoveRight, "D"),
+	(Action::MoveUp, "Space"),
+	(Action::MoveDown, "LeftControl"),
+	(Action::RollLeft, "Q"),
+	(Action::RollRight, "E"),
+	(Action::NextCameraMode, "C"),
+	(Action::ToggleProjection, "O"),
+	(Action::ToggleWireframe, "P"),
+	(Action::ToggleSky, "B"),
+	(Action::ToggleWalking, "F"),
+	(Action::Export, "X"),
+	(Action::AddKeyframe, "K"),
+	(Action::ClearKeyframes, "Shift+K"),
+	(Action::PlayFlythrough, "L"),
+	(Action::Pick, "MouseLeft"),
+	(Action::TogglePickMode, "I"),
+	(Action::ToggleCursor, "Tab"),
+	(Action::NextGizmoMode, "G"),
+	(Action::Snap, "LeftShift"),
+	(Action::Quit, "Escape"),
+	(Action::Quit, "Ctrl+Q"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+	Key(glfw::Key),
+	Mouse(glfw::MouseButton),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+	pub input: Input,
+	// Have to be held along with the input.
+	pub modifiers: glfw::Modifiers,
+}
+
+impl Binding {
+	// A key or mouse button name, optionally after modifiers, e.g. "W",
+	// "Ctrl+S" or "Shift+MouseLeft".
+	pub fn parse(text: &str) -> Option<Binding> {
+		let mut parts: std::vec::Vec<&str> = text.split('+').collect();
+		let input = input_from_name(parts.pop()?.trim())?;
+		let mut modifiers = glfw::Modifiers::empty();
+		for part in parts {
+			modifiers |= match part.trim().to_lowercase().as_str() {
+				"shift" => glfw::Modifiers::Shift,
+				"ctrl" | "control" => glfw::Modifiers::Control,
+				"alt" => glfw::Modifiers::Alt,
+				"super" | "cmd" => glfw::Modifiers::Super,
+				_ => return None,
+			};
+		}
+		Some(Binding { input, modifiers })
+	}
+
+	fn is_held(&self, held: &Held) -> bool {
+		held.is_held(self.input) && held.modifiers().contains(self.modifiers)
+	}
+
+	// Whether `chord` needs more modifiers than this binding and holding it
+	// holds this binding's input too, as its key or one of its modifiers.
+	fn overlaps(&self, chord: &Binding) -> bool {
+		if chord.modifiers == self.modifiers
+			|| !chord.modifiers.contains(self.modifiers)
+		{
+			return false;
+		}
+		chord.input == self.input
+			|| MODIFIER_KEYS.iter().any(|&(key, modifier)| {
+				self.input == Input::Key(key)
+					&& chord.modifiers.contains(modifier)
+			})
+	}
+}
+
+pub struct Bindings {
+	bindings: std::vec::Vec<(Action, Binding)>,
+}
+
+impl Default for Bindings {
+	fn default() -> Bindings {
+		Bindings {
+			bindings: DEFAULTS
+				.iter()
+				.map(|&(action, text)| {
+					(action, Binding::parse(text).expect("Bad default binding"))
+				})
+				.collect(),
+		}
+	}
+}
+
+impl Bindings {
+	// Reads a small subset of TOML: a `[bindings]` table of actions, each
+	// set to a binding or a list of them on one line, e.g.
+	//
+	//     [bindings]
+	//     move_forward = ["Z", "Up"]
+	//     quit = "Ctrl+Q"
+	//
+	// Actions the file names replace their defaults, the rest keep them.
+	pub fn load(path: &Path) -> std::io::Result<Bindings> {
+		parse_bindings(&std::fs::read_to_string(path)?)
+	}
+
+	// The defaults when there is no file, and when it doesn't parse after
+	// saying why.
+	pub fn load_or_default(path: &str) -> Bindings {
+		let path = Path::new(path);
+		if !path.exists() {
+			return Bindings::default();
+		}
+		match Bindings::load(path) {
+			Ok(bindings) => bindings,
+			Err(err) => {
+				eprintln!("Ignoring {}: {}", path.display(), err);
+				Bindings::default()
+			}
+		}
+	}
+
+	pub fn bindings(&self, action: Action) -> impl Iterator<Item = &Binding> {
+		self.bindings
+			.iter()
+			.filter(move |(bound, _)| *bound == action)
+			.map(|(_, binding)| binding)
+	}
+
+	pub fn set(&mut self, action: Action, bindings: &[Binding]) {
+		self.bindings.retain(|(bound, _)| *bound != action);
+		for &binding in bindings {
+			self.bindings.push((action, binding));
+		}
+	}
+
+	// Whether any of the action's bindings is held down, for continuous
+	// actions like movement. Modifiers the binding doesn't name don't
+	// matter, but a held chord keeps its keys to itself: holding Ctrl+Q to
+	// quit neither moves down on LeftControl nor rolls on Q.
+	pub fn pressed(&self, action: Action, held: &Held) -> bool {
+		self.bindings(action).any(|binding| {
+			binding.is_held(held)
+				&& !self.bindings.iter().any(|(_, chord)| {
+					binding.overlaps(chord) && chord.is_held(held)
+				})
+		})
+	}
+
+	// The actions an event sets off, once each time a binding is released
+	// with exactly its modifiers held.
+	pub fn triggered(
+		&self,
+		event: &glfw::WindowEvent,
+	) -> std::vec::Vec<Action> {
+		let (input, modifiers) = match *event {
+			glfw::WindowEvent::Key(
+				key,
+				_,
+				glfw::Action::Release,
+				modifiers,
+			) => (Input::Key(key), modifiers),
+			glfw::WindowEvent::MouseButton(
+				button,
+				glfw::Action::Release,
+				modifiers,
+			) => (Input::Mouse(button), modifiers),
+			_ => return std::vec::Vec::new(),
+		};
+		let modifiers = modifiers
+			& (glfw::Modifiers::Shift
+				| glfw::Modifiers::Control
+				| glfw::Modifiers::Alt
+				| glfw::Modifiers::Super);
+		self.bindings
+			.iter()
+			.filter(|(_, binding)| {
+				binding.input == input && binding.modifiers == modifiers
+			})
+			.map(|&(action, _)| action)
+			.collect()
+	}
+}
+
+const MODIFIER_KEYS: [(glfw::Key, glfw::Modifiers); 8] = [
+	(glfw::Key::LeftShift, glfw::Modifiers::Shift),
+	(glfw::Key::RightShift, glfw::Modifiers::Shift),
+	(glfw::Key::LeftControl, glfw::Modifiers::Control),
+	(glfw::Key::RightControl, glfw::Modifiers::Control),
+	(glfw::Key::LeftAlt, glfw::Modifiers::Alt),
+	(glfw::Key::RightAlt, glfw::Modifiers::Alt),
+	(glfw::Key::LeftSuper, glfw::Modifiers::Super),
+	(glfw::Key::RightSuper, glfw::Modifiers::Super),
+];
+
+// The bound keys and buttons held down at the start of a frame. Polled once
+// a frame rather than asking the window, so it can be recorded and
+// replayed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Held {
+	pub inputs: std::vec::Vec<Input>,
+}
+
+impl Held {
+	pub fn poll(window: &glfw::Window, bindings: &Bindings) -> Held {
+		let mut held = Held::default();
+		let modifiers = MODIFIER_KEYS.iter().map(|&(key, _)| Input::Key(key));
+		let bound = bindings.bindings.iter().map(|(_, binding)| binding.input);
+		for input in modifiers.chain(bound) {
+			let down = match input {
+				Input::Key(key) => window.get_key(key),
+				Input::Mouse(button) => window.get_mouse_button(button),
+			};
+			if down == glfw::Action::Press && !held.is_held(input) {
+				held.inputs.push(input);
+			}
+		}
+		held
+	}
+
+	pub fn is_held(&self, input: Input) -> bool {
+		self.inputs.contains(&input)
+	}
+
+	pub fn modifiers(&self) -> glfw::Modifiers {
+		let mut modifiers = glfw::Modifiers::empty();
+		for &(key, modifier) in MODIFIER_KEYS.iter() {
+			if self.is_held(Input::Key(key)) {
+				modifiers |= modifier;
+			}
+		}
+		modifiers
+	}
+}
+
+fn invalid(line: usize, message: &str) -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::InvalidData,
+		format!("line {}: {}", line, message),
+	)
+}
+
+pub fn parse_bindings(text: &str) -> std::io::Result<Bindings> {
+	let mut bindings = Bindings::default();
+	let mut in_bindings = false;
+	for (number, line) in text.lines().enumerate() {
+		let number = number + 1;
+		// Names and bindings never contain '#'.
+		let line = line.split('#').next().unwrap_or("").trim();
+		if line.is_empty() {
+			continue;
+		}
+		if line.starts_with('[') {
+			in_bindings = line == "[bindings]";
+			continue;
+		}
+		if !in_bindings {
+			continue;
+		}
+
+		let mut parts = line.splitn(2, '=');
+		let name = parts.next().unwrap_or("").trim();
+		let value = parts
+			.next()
+			.ok_or_else(|| invalid(number, "expected `action = binding`"))?
+			.trim();
+		let action = ACTIONS
+			.iter()
+			.find(|(action_name, _)| *action_name == name)
+			.map(|&(_, action)| action)
+			.ok_or_else(|| invalid(number, "unknown action"))?;
+
+		let list = if value.starts_with('[') && value.ends_with(']') {
+			&value[1..value.len() - 1]
+		} else {
+			value
+		};
+		let mut parsed = std::vec::Vec::new();
+		for item in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+			if item.len() < 2 || !item.starts_with('"') || !item.ends_with('"')
+			{
+				return Err(invalid(number, "bindings have to be quoted"));
+			}
+			let binding = Binding::parse(&item[1..item.len() - 1])
+				.ok_or_else(|| invalid(number, "unknown key or button"))?;
+			parsed.push(binding);
+		}
+		bindings.set(action, &parsed);
+	}
+	Ok(bindings)
+}
+
+macro_rules! key_names {
+	($($key:ident),* $(,)?) => {
+		[$((stringify!($key), glfw::Key::$key)),*]
+	};
+}
+
+// glfw's own names for them, compared ignoring case.
+pub fn input_from_name(name: &str) -> Option<Input> {
+	let keys = key_names![
+		A,
+		B,
+		C,
+		D,
+		E,
+		F,
+		G,
+		H,
+		I,
+		J,
+		K,
+		L,
+		M,
+		N,
+		O,
+		P,
+		Q,
+		R,
+		S,
+		T,
+		U,
+		V,
+		W,
+		X,
+		Y,
+		Z,
+		Num0,
+		Num1,
+		Num2,
+		Num3,
+		Num4,
+		Num5,
+		Num6,
+		Num7,
+		Num8,
+		Num9,
+		Space,
+		Apostrophe,
+		Comma,
+		Minus,
+		Period,
+		Slash,
+		Semicolon,
+		Equal,
+		LeftBracket,
+		Backslash,
+		RightBracket,
+		GraveAccent,
+		Escape,
+		Enter,
+		Tab,
+		Backspace,
+		Insert,
+		Delete,
+		Right,
+		Left,
+		Down,
+		Up,
+		PageUp,
+		PageDown,
+		Home,
+		End,
+		F1,
+		F2,
+		F3,
+		F4,
+		F5,
+		F6,
+		F7,
+		F8,
+		F9,
+		F10,
+		F11,
+		F12,
+		LeftShift,
+		LeftControl,
+		LeftAlt,
+		LeftSuper,
+		RightShift,
+		RightControl,
+		RightAlt,
+		RightSuper,
+		Menu,
+	];
+	let buttons = [
+		("MouseLeft", glfw::MouseButton::Button1),
+		("MouseRight", glfw::MouseButton::Button2),
+		("MouseMiddle", glfw::MouseButton::Button3),
+		("Mouse4", glfw::MouseButton::Button4),
+		("Mouse5", glfw::MouseButton::Button5),
+	];
+	keys.iter()
+		.find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+		.map(|&(_, key)| Input::Key(key))
+		.or_else(|| {
+			buttons
+				.iter()
+				.find(|(button_name, _)| button_name.eq_ignore_ascii_case(name))
+				.map(|&(_, button)| Input::Mouse(button))
+		})
+}
+
+// The name `input_from_name` reads back, `None` for keys that can't be
+// bound.
+pub fn input_name(input: Input) -> Option<std::string::String> {
+	let name = match input {
+		Input::Key(key) => format!("{:?}", key),
+		Input::Mouse(glfw::MouseButton::Button1) => "MouseLeft".to_string(),
+		Input::Mouse(glfw::MouseButton::Button2) => "MouseRight".to_string(),
+		Input::Mouse(glfw::MouseButton::Button3) => "MouseMiddle".to_string(),
+		Input::Mouse(glfw::MouseButton::Button4) => "Mouse4".to_string(),
+		Input::Mouse(glfw::MouseButton::Button5) => "Mouse5".to_string(),
+		Input::Mouse(_) => return None,
+	};
+	Some(name).filter(|name| input_from_name(name) == Some(input))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn held(inputs: &[&str]) -> Held {
+		Held {
+			inputs: inputs
+				.iter()
+				.map(|name| input_from_name(name).unwrap())
+				.collect(),
+		}
+	}
+
+	fn release(
+		key: glfw::Key,
+		modifiers: glfw::Modifiers,
+	) -> glfw::WindowEvent {
+		glfw::WindowEvent::Key(key, 0, glfw::Action::Release, modifiers)
+	}
+
+	#[test]
+	fn chords_keep_their_keys() {
+		let bindings = Bindings::default();
+		let quitting = held(&["LeftControl", "Q"]);
+		assert!(!bindings.pressed(Action::MoveDown, &quitting));
+		assert!(!bindings.pressed(Action::RollLeft, &quitting));
+		assert_eq!(
+			bindings
+				.triggered(&release(glfw::Key::Q, glfw::Modifiers::Control)),
+			vec![Action::Quit]
+		);
+
+		let clearing = held(&["LeftShift", "K"]);
+		assert!(!bindings.pressed(Action::Snap, &clearing));
+
+		// Each on its own, or alongside keys no chord needs, still works.
+		assert!(bindings.pressed(Action::MoveDown, &held(&["LeftControl"])));
+		assert!(
+			bindings.pressed(Action::MoveDown, &held(&["LeftControl", "W"]))
+		);
+		assert!(
+			bindings.pressed(Action::MoveForward, &held(&["LeftControl", "W"]))
+		);
+		assert!(bindings.pressed(Action::RollLeft, &held(&["Q"])));
+		assert!(
+			bindings.pressed(Action::Snap, &held(&["LeftShift", "MouseLeft"]))
+		);
+	}
+
+	#[test]
+	fn right_hand_modifiers_count_too() {
+		let bindings = Bindings::default();
+		let quitting = held(&["RightControl", "Q"]);
+		assert!(!bindings.pressed(Action::RollLeft, &quitting));
+		assert!(!bindings.pressed(Action::MoveDown, &quitting));
+		assert!(bindings.pressed(Action::RollLeft, &held(&["RightShift", "Q"])));
+	}
+
+	#[test]
+	fn files_replace_only_the_actions_they_name() {
+		let bindings = parse_bindings(
+			"# AZERTY\n[bindings]\nmove_forward = [\"Z\", \"Up\"]\nquit = \"Alt+F4\"\n",
+		)
+		.unwrap();
+		let forward: std::vec::Vec<Binding> =
+			bindings.bindings(Action::MoveForward).copied().collect();
+		assert_eq!(
+			forward,
+			vec![Binding::parse("Z").unwrap(), Binding::parse("up").unwrap()]
+		);
+		let quit: std::vec::Vec<Binding> =
+			bindings.bindings(Action::Quit).copied().collect();
+		assert_eq!(
+			quit,
+			vec![Binding {
+				input: Input::Key(glfw::Key::F4),
+				modifiers: glfw::Modifiers::Alt,
+			}]
+		);
+		assert!(bindings.pressed(Action::MoveLeft, &held(&["A"])));
+	}
+
+	#[test]
+	fn bad_files_say_which_line() {
+		let error = |text| parse_bindings(text).err().unwrap().to_string();
+		assert_eq!(
+			error("[bindings]\njump = \"Space\""),
+			"line 2: unknown action"
+		);
+		assert_eq!(
+			error("[bindings]\nquit = Escape"),
+			"line 2: bindings have to be quoted"
+		);
+		assert_eq!(
+			error("[bindings]\nquit = \"Hyper+Q\""),
+			"line 2: unknown key or button"
+		);
+		assert_eq!(
+			error("[bindings]\n\nquit"),
+			"line 3: expected `action = binding`"
+		);
+		// Other tables are skipped.
+		assert!(parse_bindings("[window]\nwidth = 800").is_ok());
+	}
+
+	#[test]
+	fn names_round_trip() {
+		for name in &["W", "LeftControl", "F12", "MouseLeft", "Mouse5", "Num0"]
+		{
+			let input = input_from_name(name).unwrap();
+			assert_eq!(input_name(input).as_deref(), Some(*name));
+		}
+		assert_eq!(input_name(Input::Mouse(glfw::MouseButton::Button8)), None);
+		assert_eq!(Binding::parse("Ctrl+"), None);
+	}
+}
@@ -0,0 +1,579 @@
+use crate::gamepad;
+use crate::input;
+use cgmath::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+	// Mouse look and WASD along the view direction, kept level and short of
+	// looking straight up or down.
+	Fps,
+	// Circles `target`, the mouse turns around it and scrolling zooms.
+	Orbit,
+	// Turns freely, rolls, and rises and sinks. Scrolling sets the speed
+	// instead of the zoom.
+	Fly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Movement {
+	Forward,
+	Backward,
+	Left,
+	Right,
+	Up,
+	Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionKind {
+	Perspective,
+	Orthographic,
+}
+
+// Follows the framebuffer size so resizing the window doesn't stretch the
+// scene.
+#[derive(Clone, Copy, Debug)]
+pub struct Projection {
+	pub kind: ProjectionKind,
+	// Vertical field of view of the perspective projection, in degrees.
+	pub fov: f32,
+	// Half the height of the orthographic view volume, in world units.
+	pub half_height: f32,
+	pub near: f32,
+	pub far: f32,
+	// Framebuffer size in pixels.
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Projection {
+	pub fn new(width: u32, height: u32) -> Projection {
+		Projection {
+			kind: ProjectionKind::Perspective,
+			fov: 45.0,
+			half_height: 2.5,
+			near: 0.1,
+			far: 100.0,
+			width,
+			height,
+		}
+	}
+
+	// From `glfw::WindowEvent::FramebufferSize`. Minimised windows report
+	// zero, which keeps the previous size.
+	pub fn resize(&mut self, width: i32, height: i32) {
+		if width > 0 && height > 0 {
+			self.width = width as u32;
+			self.height = height as u32;
+		}
+	}
+
+	pub fn aspect(&self) -> f32 {
+		self.width as f32 / self.height as f32
+	}
+
+	pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+		match self.kind {
+			ProjectionKind::Perspective => cgmath::perspective(
+				cgmath::Deg(self.fov),
+				self.aspect(),
+				self.near,
+				self.far,
+			),
+			ProjectionKind::Orthographic => {
+				let half_width = self.half_height * self.aspect();
+				cgmath::ortho(
+					-half_width,
+					half_width,
+					-self.half_height,
+					self.half_height,
+					self.near,
+					self.far,
+				)
+			}
+		}
+	}
+
+	// Narrows the field of view, or shrinks the orthographic volume, as
+	// `yoffset` grows.
+	pub fn zoom(&mut self, yoffset: f32) {
+		match self.kind {
+			ProjectionKind::Perspective => {
+				self.fov = (self.fov - yoffset).clamp(1.0, 45.0);
+			}
+			ProjectionKind::Orthographic => {
+				self.half_height =
+					(self.half_height * 0.9f32.powf(yoffset)).max(0.01);
+			}
+		}
+	}
+
+	// Switches kind. Things `distance` away keep their size on screen.
+	pub fn toggle(&mut self, distance: f32) {
+		let tan = (self.fov.to_radians() * 0.5).tan();
+		match self.kind {
+			ProjectionKind::Perspective => {
+				self.half_height = distance * tan;
+				self.kind = ProjectionKind::Orthographic;
+			}
+			ProjectionKind::Orthographic => {
+				self.kind = ProjectionKind::Perspective;
+			}
+		}
+	}
+}
+
+pub struct Camera {
+	pub mode: CameraMode,
+	pub position: cgmath::Point3<f32>,
+	// Turns -z, the way the camera looks, into the world. Kept unit length.
+	pub orientation: cgmath::Quaternion<f32>,
+	// What orbit mode circles, and how far away.
+	pub target: cgmath::Point3<f32>,
+	pub distance: f32,
+	// Units per second.
+	pub speed: f32,
+	// Degrees per pixel.
+	pub sensitivity: f32,
+	// Degrees per second.
+	pub roll_speed: f32,
+	// Seconds for the mouse to catch up most of the way, 0 turns at once.
+	pub smoothing: f32,
+	pub projection: Projection,
+	first_mouse: bool,
+	last_x: f32,
+	last_y: f32,
+	// Mouse turning in degrees not yet applied, see `update`.
+	pending: cgmath::Vector2<f32>,
+}
+
+// Degrees away from straight up or down that the level modes stop at, the
+// view would flip over past them.
+const MAX_PITCH: f32 = 89.0;
+
+const MOVEMENTS: [(input::Action, Movement); 6] = [
+	(input::Action::MoveForward, Movement::Forward),
+	(input::Action::MoveBackward, Movement::Backward),
+	(input::Action::MoveLeft, Movement::Left),
+	(input::Action::MoveRight, Movement::Right),
+	(input::Action::MoveUp, Movement::Up),
+	(input::Action::MoveDown, Movement::Down),
+];
+
+impl Camera {
+	// An FPS camera looking down -z, as the chapters have always started.
+	pub fn new(position: cgmath::Point3<f32>) -> Camera {
+		Camera {
+			mode: CameraMode::Fps,
+			position,
+			orientation: cgmath::Quaternion::one(),
+			target: cgmath::point3(0.0, 0.0, 0.0),
+			distance: position.to_vec().magnitude(),
+			speed: 2.5,
+			sensitivity: 0.1,
+			roll_speed: 90.0,
+			smoothing: 0.03,
+			projection: Projection::new(800, 600),
+			first_mouse: true,
+			last_x: 400.0,
+			last_y: 300.0,
+			pending: cgmath::vec2(0.0, 0.0),
+		}
+	}
+
+	pub fn front(&self) -> cgmath::Vector3<f32> {
+		self.orientation.rotate_vector(-cgmath::Vector3::unit_z())
+	}
+
+	pub fn right(&self) -> cgmath::Vector3<f32> {
+		self.orientation.rotate_vector(cgmath::Vector3::unit_x())
+	}
+
+	pub fn up(&self) -> cgmath::Vector3<f32> {
+		self.orientation.rotate_vector(cgmath::Vector3::unit_y())
+	}
+
+	// Degrees up from the horizon.
+	pub fn pitch(&self) -> f32 {
+		self.front().y.clamp(-1.0, 1.0).asin().to_degrees()
+	}
+
+	pub fn view(&self) -> cgmath::Matrix4<f32> {
+		cgmath::Matrix4::from(self.orientation.conjugate())
+			* cgmath::Matrix4::from_translation(-self.position.to_vec())
+	}
+
+	pub fn projection(&self) -> cgmath::Matrix4<f32> {
+		self.projection.matrix()
+	}
+
+	// Between perspective and orthographic, keeping the target's size.
+	pub fn toggle_projection(&mut self) {
+		let distance = (self.target - self.position).magnitude().max(0.1);
+		self.projection.toggle(distance);
+	}
+
+	// Turns to face `target` from where the camera is, without roll.
+	pub fn look_at(&mut self, target: cgmath::Point3<f32>) {
+		let direction = target - self.position;
+		if direction.magnitude2() == 0.0 {
+			return;
+		}
+		self.look_along(direction.normalize());
+	}
+
+	// Level with the horizon, facing as close to `direction` as the mode
+	// allows.
+	fn look_along(&mut self, direction: cgmath::Vector3<f32>) {
+		let heading = direction.x.atan2(-direction.z).to_degrees();
+		let mut pitch = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+		if self.mode != CameraMode::Fly {
+			pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+		}
+		self.orientation =
+			cgmath::Quaternion::from_angle_y(cgmath::Deg(-heading))
+				* cgmath::Quaternion::from_angle_x(cgmath::Deg(pitch));
+	}
+
+	// Orbit mode keeps circling the current target from where the camera
+	// is, the other modes carry on from the orbit's position and direction.
+	// Leaving flight levels the camera out.
+	pub fn set_mode(&mut self, mode: CameraMode) {
+		let previous = self.mode;
+		self.mode = mode;
+		if mode == CameraMode::Orbit && previous != CameraMode::Orbit {
+			self.distance = (self.position - self.target).magnitude().max(0.1);
+			let target = self.target;
+			self.look_at(target);
+		} else if mode != CameraMode::Fly {
+			self.look_along(self.front());
+		}
+		self.update_orbit();
+	}
+
+	// FPS, orbit, fly and round again.
+	pub fn next_mode(&mut self) {
+		self.set_mode(match self.mode {
+			CameraMode::Fps => CameraMode::Orbit,
+			CameraMode::Orbit => CameraMode::Fly,
+			CameraMode::Fly => CameraMode::Fps,
+		});
+	}
+
+	// From `glfw::WindowEvent::CursorPos`. The turn is spread over the
+	// next frames by `update`.
+	pub fn mouse_moved(&mut self, xpos: f32, ypos: f32) {
+		if self.first_mouse {
+			self.last_x = xpos;
+			self.last_y = ypos;
+			self.first_mouse = false;
+		}
+		let xoffset = xpos - self.last_x;
+		// Reversed, window y grows downwards.
+		let yoffset = self.last_y - ypos;
+		self.last_x = xpos;
+		self.last_y = ypos;
+		self.pending += cgmath::vec2(xoffset, yoffset) * self.sensitivity;
+	}
+
+	// After the cursor was let go of, so taking it back doesn't turn the
+	// camera by however far it moved meanwhile. Only chapters that free
+	// the cursor for picking need it.
+	#[allow(dead_code)]
+	pub fn reset_mouse(&mut self) {
+		self.first_mouse = true;
+	}
+
+	// Once a frame, applies the smoothed mouse turning.
+	pub fn update(&mut self, delta_time: f32) {
+		let amount = if self.smoothing > 0.0 {
+			1.0 - (-delta_time / self.smoothing).exp()
+		} else {
+			1.0
+		};
+		let turn = self.pending * amount;
+		self.pending -= turn;
+		self.rotate(turn.x, turn.y);
+	}
+
+	// Degrees, positive turns right and up. Flight turns around the
+	// camera's own axes, the other modes around the world's up and stop
+	// short of looking straight up or down.
+	pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+		let turn = |axis, degrees| {
+			cgmath::Quaternion::from_axis_angle(axis, cgmath::Deg(degrees))
+		};
+		if self.mode == CameraMode::Fly {
+			self.orientation = self.orientation
+				* turn(cgmath::Vector3::unit_y(), -yaw)
+				* turn(cgmath::Vector3::unit_x(), pitch);
+		} else {
+			let current = self.pitch();
+			let pitch =
+				(current + pitch).clamp(-MAX_PITCH, MAX_PITCH) - current;
+			self.orientation = turn(cgmath::Vector3::unit_y(), -yaw)
+				* self.orientation
+				* turn(cgmath::Vector3::unit_x(), pitch);
+		}
+		self.orientation = self.orientation.normalize();
+		self.update_orbit();
+	}
+
+	// Degrees, positive banks right. Only flight rolls.
+	pub fn roll(&mut self, degrees: f32) {
+		if self.mode != CameraMode::Fly {
+			return;
+		}
+		self.orientation = (self.orientation
+			* cgmath::Quaternion::from_angle_z(cgmath::Deg(-degrees)))
+		.normalize();
+	}
+
+	// From `glfw::WindowEvent::Scroll`.
+	pub fn scroll(&mut self, yoffset: f32) {
+		match self.mode {
+			CameraMode::Fps => self.projection.zoom(yoffset),
+			// Moving closer changes nothing orthographically.
+			CameraMode::Orbit
+				if self.projection.kind == ProjectionKind::Orthographic =>
+			{
+				self.projection.zoom(yoffset)
+			}
+			CameraMode::Orbit => {
+				self.distance = (self.distance * 0.9f32.powf(yoffset)).max(0.1);
+				self.update_orbit();
+			}
+			CameraMode::Fly => {
+				self.speed = (self.speed * 1.2f32.powf(yoffset)).max(0.1);
+			}
+		}
+	}
+
+	// Orbit mode moves closer and further with forward and backward, and
+	// circles the target with left and right.
+	pub fn process_movement(&mut self, movement: Movement, delta_time: f32) {
+		let velocity = self.speed * delta_time;
+		match (self.mode, movement) {
+			(CameraMode::Orbit, Movement::Forward) => {
+				self.distance = (self.distance - velocity).max(0.1);
+			}
+			(CameraMode::Orbit, Movement::Backward) => {
+				self.distance += velocity;
+			}
+			// Turning the view right swings the camera left around the
+			// target.
+			(CameraMode::Orbit, Movement::Left) => {
+				self.rotate((velocity / self.distance).to_degrees(), 0.0);
+			}
+			(CameraMode::Orbit, Movement::Right) => {
+				self.rotate(-(velocity / self.distance).to_degrees(), 0.0);
+			}
+			(CameraMode::Orbit, _)
+			| (CameraMode::Fps, Movement::Up)
+			| (CameraMode::Fps, Movement::Down) => {}
+			(_, Movement::Forward) => self.position += self.front() * velocity,
+			(_, Movement::Backward) => self.position -= self.front() * velocity,
+			(_, Movement::Left) => self.position -= self.right() * velocity,
+			(_, Movement::Right) => self.position += self.right() * velocity,
+			(_, Movement::Up) => self.position += self.up() * velocity,
+			(_, Movement::Down) => self.position -= self.up() * velocity,
+		}
+		self.update_orbit();
+	}
+
+	// Movement and roll from whatever the actions are bound to.
+	pub fn process_input(
+		&mut self,
+		bindings: &input::Bindings,
+		held: &input::Held,
+		delta_time: f32,
+	) {
+		for &(action, movement) in MOVEMENTS.iter() {
+			if bindings.pressed(action, held) {
+				self.process_movement(movement, delta_time);
+			}
+		}
+		if bindings.pressed(input::Action::RollLeft, held) {
+			self.roll(-self.roll_speed * delta_time);
+		}
+		if bindings.pressed(input::Action::RollRight, held) {
+			self.roll(self.roll_speed * delta_time);
+		}
+	}
+
+	// The same actions from a gamepad, part way for sticks part way over,
+	// and the right stick turning like the mouse.
+	pub fn process_gamepad(
+		&mut self,
+		gamepad: &gamepad::Gamepad,
+		delta_time: f32,
+	) {
+		for &(action, movement) in MOVEMENTS.iter() {
+			let amount = gamepad.amount(action);
+			if amount > 0.0 {
+				self.process_movement(movement, delta_time * amount);
+			}
+		}
+		let roll = gamepad.amount(input::Action::RollRight)
+			- gamepad.amount(input::Action::RollLeft);
+		self.roll(roll * self.roll_speed * delta_time);
+		self.pending += gamepad.look() * delta_time;
+	}
+
+	fn update_orbit(&mut self) {
+		if self.mode == CameraMode::Orbit {
+			self.position = self.target - self.front() * self.distance;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_close(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) {
+		assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+	}
+
+	fn camera(mode: CameraMode) -> Camera {
+		let mut camera = Camera::new(cgmath::point3(0.0, 0.0, 6.0));
+		camera.set_mode(mode);
+		camera
+	}
+
+	#[test]
+	fn fps_pitch_stops_short_of_vertical() {
+		let mut camera = camera(CameraMode::Fps);
+		let mut previous = camera.pitch();
+		for _ in 0..30 {
+			camera.rotate(0.0, 5.0);
+			// asin near the clamp is only good to a few millionths.
+			assert!(camera.pitch() > previous - 1e-3);
+			previous = camera.pitch();
+		}
+		assert!((camera.pitch() - MAX_PITCH).abs() < 1e-3);
+		for _ in 0..60 {
+			camera.rotate(0.0, -5.0);
+		}
+		assert!((camera.pitch() + MAX_PITCH).abs() < 1e-3);
+	}
+
+	#[test]
+	fn fps_turning_never_rolls() {
+		let mut camera = camera(CameraMode::Fps);
+		// Circles while bobbing up and down, against the clamp on both
+		// sides, far more often than a session would.
+		for step in 0..5000 {
+			let pitch = if (step / 100) % 2 == 0 { 3.0 } else { -3.0 };
+			camera.rotate(7.3, pitch);
+			assert!(camera.right().y.abs() < 1e-3);
+		}
+		assert!((camera.orientation.magnitude() - 1.0).abs() < 1e-4);
+		assert!(camera.up().y > 0.0);
+	}
+
+	#[test]
+	fn fly_pitches_through_vertical_smoothly() {
+		let mut camera = camera(CameraMode::Fly);
+		let mut front = camera.front();
+		for _ in 0..360 {
+			camera.rotate(0.0, 1.0);
+			// Each step turns exactly one degree, no jumps near the poles.
+			let step = front.dot(camera.front()).clamp(-1.0, 1.0).acos();
+			assert!((step.to_degrees() - 1.0).abs() < 1e-2);
+			assert_close(camera.right(), cgmath::Vector3::unit_x());
+			front = camera.front();
+		}
+		assert_close(camera.front(), -cgmath::Vector3::unit_z());
+	}
+
+	#[test]
+	fn only_flight_rolls() {
+		let mut camera = camera(CameraMode::Fly);
+		camera.roll(90.0);
+		assert_close(camera.right(), -cgmath::Vector3::unit_y());
+		assert_close(camera.front(), -cgmath::Vector3::unit_z());
+
+		// Landing levels the camera out again.
+		camera.set_mode(CameraMode::Fps);
+		assert_close(camera.right(), cgmath::Vector3::unit_x());
+		camera.roll(90.0);
+		assert_close(camera.right(), cgmath::Vector3::unit_x());
+	}
+
+	#[test]
+	fn orbit_circles_the_target() {
+		let mut camera = camera(CameraMode::Orbit);
+		assert!((camera.distance - 6.0).abs() < 1e-4);
+		camera.rotate(90.0, 0.0);
+		assert_close(camera.position.to_vec(), cgmath::vec3(-6.0, 0.0, 0.0));
+		camera.rotate(33.0, 20.0);
+		let offset = camera.target - camera.position;
+		assert!((offset.magnitude() - 6.0).abs() < 1e-3);
+		assert_close(offset.normalize(), camera.front());
+
+		camera.scroll(1.0);
+		assert!((camera.distance - 5.4).abs() < 1e-4);
+		camera.process_movement(Movement::Up, 1.0);
+		assert!(
+			((camera.target - camera.position).magnitude() - 5.4).abs() < 1e-3
+		);
+	}
+
+	#[test]
+	fn fps_walks_along_the_view() {
+		let mut camera = camera(CameraMode::Fps);
+		camera.process_movement(Movement::Forward, 2.0);
+		assert_close(camera.position.to_vec(), cgmath::vec3(0.0, 0.0, 1.0));
+		camera.process_movement(Movement::Up, 2.0);
+		assert_close(camera.position.to_vec(), cgmath::vec3(0.0, 0.0, 1.0));
+		camera.process_movement(Movement::Right, 2.0);
+		assert_close(camera.position.to_vec(), cgmath::vec3(5.0, 0.0, 1.0));
+	}
+
+	// Normalised device coordinates of a point in front of the camera.
+	fn ndc(
+		projection: &Projection,
+		point: cgmath::Vector3<f32>,
+	) -> cgmath::Vector3<f32> {
+		let clip = projection.matrix() * point.extend(1.0);
+		clip.truncate() / clip.w
+	}
+
+	#[test]
+	fn orthographic_keeps_the_size_at_the_toggle_distance() {
+		let mut projection = Projection::new(800, 600);
+		let edge = cgmath::vec3(0.0, 4.0 * (22.5f32).to_radians().tan(), -4.0);
+		assert!((ndc(&projection, edge).y - 1.0).abs() < 1e-4);
+
+		projection.toggle(4.0);
+		assert_eq!(projection.kind, ProjectionKind::Orthographic);
+		assert!((ndc(&projection, edge).y - 1.0).abs() < 1e-4);
+		// Parallel, so distance no longer matters.
+		let further = cgmath::vec3(edge.x, edge.y, -40.0);
+		assert!((ndc(&projection, further).y - 1.0).abs() < 1e-4);
+		let corner =
+			cgmath::vec3(projection.half_height * 800.0 / 600.0, 0.0, -1.0);
+		assert!((ndc(&projection, corner).x - 1.0).abs() < 1e-4);
+
+		projection.zoom(1.0);
+		assert!(ndc(&projection, edge).y > 1.0);
+		projection.toggle(4.0);
+		assert_eq!(projection.kind, ProjectionKind::Perspective);
+	}
+
+	#[test]
+	fn projection_follows_the_framebuffer() {
+		let mut projection = Projection::new(800, 600);
+		projection.resize(1920, 1080);
+		assert!((projection.aspect() - 16.0 / 9.0).abs() < 1e-6);
+		// Minimised.
+		projection.resize(0, 0);
+		assert_eq!((projection.width, projection.height), (1920, 1080));
+
+		projection.zoom(100.0);
+		assert_eq!(projection.fov, 1.0);
+		projection.zoom(-100.0);
+		assert_eq!(projection.fov, 45.0);
+	}
+}
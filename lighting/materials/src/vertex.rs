@@ -0,0 +1,250 @@
+// Vertex arrays set up from a description of the vertex type, so strides and
+// offsets come from the compiler instead of being counted by hand.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attribute {
+	// Relative to the first location passed to `set_attributes`.
+	pub location: u32,
+	// Per location, 1 to 4.
+	pub components: i32,
+	pub type_: gl::types::GLenum,
+	pub normalized: bool,
+	// Matrices take one location per column.
+	pub columns: u32,
+	// In bytes from the start of the vertex.
+	pub offset: usize,
+	pub size: usize,
+}
+
+pub trait VertexLayout: Sized {
+	fn attributes() -> std::vec::Vec<Attribute>;
+
+	fn stride() -> usize {
+		std::mem::size_of::<Self>()
+	}
+}
+
+// Field types a vertex attribute can be read from. They all reach the shader
+// as floats, normalised or not.
+pub trait Component {
+	const COMPONENTS: i32;
+	const TYPE: gl::types::GLenum;
+	const COLUMNS: u32 = 1;
+}
+
+impl Component for f32 {
+	const COMPONENTS: i32 = 1;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for [f32; 2] {
+	const COMPONENTS: i32 = 2;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for [f32; 3] {
+	const COMPONENTS: i32 = 3;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for [f32; 4] {
+	const COMPONENTS: i32 = 4;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for [u8; 4] {
+	const COMPONENTS: i32 = 4;
+	const TYPE: gl::types::GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl Component for cgmath::Vector2<f32> {
+	const COMPONENTS: i32 = 2;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for cgmath::Vector3<f32> {
+	const COMPONENTS: i32 = 3;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for cgmath::Vector4<f32> {
+	const COMPONENTS: i32 = 4;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+}
+
+impl Component for cgmath::Matrix4<f32> {
+	const COMPONENTS: i32 = 4;
+	const TYPE: gl::types::GLenum = gl::FLOAT;
+	const COLUMNS: u32 = 4;
+}
+
+// Called by `vertex_layout!` with a field pointer taken from `base`, which
+// is where the offset and the field type come from.
+pub fn attribute<T, F: Component>(
+	location: u32,
+	normalized: bool,
+	base: *const T,
+	field: *const F,
+) -> Attribute {
+	Attribute {
+		location,
+		components: F::COMPONENTS,
+		type_: F::TYPE,
+		normalized,
+		columns: F::COLUMNS,
+		offset: field as usize - base as usize,
+		size: std::mem::size_of::<F>(),
+	}
+}
+
+// Implements `VertexLayout` for a struct by listing the fields that are
+// attributes with their locations, e.g.
+//
+//     vertex_layout!(Vertex { position: 0, normal: 1, color: 2 normalized });
+//
+// Fields left out are not passed to the shader.
+macro_rules! vertex_layout {
+	($type:ident { $($field:ident: $location:literal $($normalized:ident)?),* $(,)? }) => {
+		impl crate::vertex::VertexLayout for $type {
+			fn attributes() -> std::vec::Vec<crate::vertex::Attribute> {
+				let vertex = std::mem::MaybeUninit::<$type>::uninit();
+				let base = vertex.as_ptr();
+				// Only addresses are taken, nothing uninitialised is read.
+				unsafe {
+					vec![$(crate::vertex::attribute(
+						$location,
+						crate::vertex::vertex_layout!(@normalized $($normalized)?),
+						base,
+						std::ptr::addr_of!((*base).$field),
+					)),*]
+				}
+			}
+		}
+	};
+	(@normalized normalized) => {
+		true
+	};
+	(@normalized) => {
+		false
+	};
+}
+pub(crate) use vertex_layout;
+
+// Points the attributes of `T` at the buffer bound to ARRAY_BUFFER, for the
+// bound vertex array, starting at `first_location`. A divisor of 1 steps
+// them once per instance instead of once per vertex.
+pub fn set_attributes<T: VertexLayout>(first_location: u32, divisor: u32) {
+	let stride = T::stride() as gl::types::GLsizei;
+	for attribute in T::attributes() {
+		let column_size = attribute.size / attribute.columns as usize;
+		for column in 0..attribute.columns {
+			let location = first_location + attribute.location + column;
+			let offset = attribute.offset + column as usize * column_size;
+			unsafe {
+				gl::EnableVertexAttribArray(location);
+				gl::VertexAttribPointer(
+					location,
+					attribute.components,
+					attribute.type_,
+					if attribute.normalized {
+						gl::TRUE
+					} else {
+						gl::FALSE
+					},
+					stride,
+					offset as *const std::os::raw::c_void,
+				);
+				gl::VertexAttribDivisor(location, divisor);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Not `repr(C)`, so the compiler is free to reorder the fields.
+	struct Mixed {
+		position: [f32; 3],
+		color: [u8; 4],
+		_unused: f64,
+		tex_coords: cgmath::Vector2<f32>,
+		weight: f32,
+		transform: cgmath::Matrix4<f32>,
+	}
+
+	vertex_layout!(Mixed {
+		position: 0,
+		color: 1 normalized,
+		tex_coords: 2,
+		weight: 3,
+		transform: 4,
+	});
+
+	fn float(location: u32, components: i32, offset: usize) -> Attribute {
+		Attribute {
+			location,
+			components,
+			type_: gl::FLOAT,
+			normalized: false,
+			columns: 1,
+			offset,
+			size: components as usize * std::mem::size_of::<f32>(),
+		}
+	}
+
+	#[test]
+	fn stride_is_the_size_of_the_vertex() {
+		assert_eq!(Mixed::stride(), std::mem::size_of::<Mixed>());
+	}
+
+	#[test]
+	fn attributes_match_the_compilers_layout() {
+		let attributes = Mixed::attributes();
+		assert_eq!(attributes.len(), 5);
+		assert_eq!(
+			attributes[0],
+			float(0, 3, std::mem::offset_of!(Mixed, position))
+		);
+		assert_eq!(
+			attributes[1],
+			Attribute {
+				location: 1,
+				components: 4,
+				type_: gl::UNSIGNED_BYTE,
+				normalized: true,
+				columns: 1,
+				offset: std::mem::offset_of!(Mixed, color),
+				size: 4,
+			}
+		);
+		assert_eq!(
+			attributes[2],
+			float(2, 2, std::mem::offset_of!(Mixed, tex_coords))
+		);
+		assert_eq!(
+			attributes[3],
+			float(3, 1, std::mem::offset_of!(Mixed, weight))
+		);
+		assert_eq!(
+			attributes[4],
+			Attribute {
+				location: 4,
+				components: 4,
+				type_: gl::FLOAT,
+				normalized: false,
+				columns: 4,
+				offset: std::mem::offset_of!(Mixed, transform),
+				size: std::mem::size_of::<cgmath::Matrix4<f32>>(),
+			}
+		);
+	}
+
+	#[test]
+	fn attributes_stay_within_the_vertex() {
+		for attribute in Mixed::attributes() {
+			assert!(attribute.offset + attribute.size <= Mixed::stride());
+		}
+	}
+}
@@ -0,0 +1,22 @@
+#version 330 core
+out vec4 FragColor;
+
+uniform sampler2D panorama;
+uniform float size;
+// The face being drawn, see cubemap::FACES.
+uniform vec3 forward;
+uniform vec3 right;
+uniform vec3 down;
+
+const float PI = 3.14159265359;
+
+void main()
+{
+	vec2 st = gl_FragCoord.xy / size * 2.0 - 1.0;
+	vec3 direction = normalize(forward + right * st.x + down * st.y);
+	vec2 uv = vec2(
+		atan(direction.z, direction.x) / (2.0 * PI) + 0.5,
+		0.5 - asin(direction.y) / PI
+	);
+	FragColor = vec4(texture(panorama, uv).rgb, 1.0);
+}
@@ -1,7 +1,9 @@
 #version 330 core
 out vec4 FragColor;
 
+in vec4 Color;
+
 void main()
 {
-	FragColor = vec4(1.0);
+	FragColor = Color;
 }
\ No newline at end of file
@@ -1,12 +1,16 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
+layout (location = 3) in mat4 aModel;
+layout (location = 7) in vec4 aColor;
 
-uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
 
+out vec4 Color;
+
 void main()
 {
-	gl_Position = projection * view * model * vec4(aPos, 1.0);
+	Color = aColor;
+	gl_Position = projection * view * aModel * vec4(aPos, 1.0);
 }
\ No newline at end of file
@@ -54,6 +54,7 @@ uniform vec3 viewPos;
 in vec2 TexCoords;
 in vec3 Normal;
 in vec3 FragPos;
+in vec4 Color;
 
 out vec4 FragColor;
 
@@ -128,5 +129,5 @@ void main()
 
 	result += CalcSpotLight(spotLight, norm, FragPos, viewDir);
 
-	FragColor = vec4(result, 1.0);
+	FragColor = vec4(result, 1.0) * Color;
 }
\ No newline at end of file
@@ -1,15 +1,28 @@
-#![feature(destructuring_assignment)]
-#![feature(or_patterns)]
-
 extern crate cgmath;
 extern crate gl;
 extern crate glfw;
 extern crate image;
-
+extern crate png;
+
+mod bounds;
+mod camera;
+mod cubemap;
+mod dds;
+mod flythrough;
+mod gamepad;
+mod gizmo;
+mod gpu;
+mod input;
+mod instance;
+mod ktx2;
+mod ray;
+mod record;
 mod shader;
+mod skybox;
+mod texture;
+mod vertex;
 use crate::glfw::Context;
 use cgmath::prelude::*;
-use image::GenericImage;
 
 fn gl_init() -> (
 	glfw::Glfw,
@@ -33,6 +46,7 @@ fn gl_init() -> (
 	window.set_key_polling(true);
 	window.set_cursor_pos_polling(true);
 	window.set_scroll_polling(true);
+	window.set_mouse_button_polling(true);
 	window.set_framebuffer_size_polling(true);
 	window.set_cursor_mode(glfw::CursorMode::Disabled);
 
@@ -47,145 +61,109 @@ fn gl_init() -> (
 	(glfw, window, events)
 }
 
-fn create_vao() -> (u32, u32) {
-	let mut object_vao: u32 = 0;
-	let mut light_vao: u32 = 0;
-	let mut vbo: u32 = 0;
+// The cube vertices are shared by both vertex arrays, each of which also has
+// its own instance buffer at location 3.
+struct CubeVertex {
+	position: [f32; 3],
+	normal: [f32; 3],
+	tex_coords: [f32; 2],
+}
 
-	unsafe {
-		gl::GenVertexArrays(1, &mut object_vao);
-		gl::BindVertexArray(object_vao);
+vertex::vertex_layout!(CubeVertex {
+	position: 0,
+	normal: 1,
+	tex_coords: 2,
+});
+
+struct CubeArrays {
+	object: gpu::VertexArray,
+	object_instances: gpu::Buffer,
+	light: gpu::VertexArray,
+	light_instances: gpu::Buffer,
+	_vbo: gpu::Buffer,
+}
 
-		gl::GenBuffers(1, &mut vbo);
-		gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+// The value after `flag` on the command line, if it's there.
+fn arg(flag: &str) -> Option<std::string::String> {
+	let args: std::vec::Vec<std::string::String> = std::env::args().collect();
+	args.iter()
+		.position(|arg| arg == flag)
+		.and_then(|i| args.get(i + 1))
+		.cloned()
+}
+
+fn create_vao() -> CubeArrays {
+	let object_vao = gpu::VertexArray::new();
+	let light_vao = gpu::VertexArray::new();
+	let vbo = gpu::Buffer::new();
+
+	let vertices: Vec<CubeVertex> = CUBE_VERTICES
+		.chunks(8)
+		.map(|v| CubeVertex {
+			position: [v[0], v[1], v[2]],
+			normal: [v[3], v[4], v[5]],
+			tex_coords: [v[6], v[7]],
+		})
+		.collect();
+
+	object_vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	unsafe {
 		gl::BufferData(
 			gl::ARRAY_BUFFER,
-			(CUBE_VERTICES.len() * std::mem::size_of::<gl::types::GLfloat>())
+			(vertices.len() * std::mem::size_of::<CubeVertex>())
 				as gl::types::GLsizeiptr,
-			&CUBE_VERTICES[0] as *const f32 as *const std::os::raw::c_void,
+			vertices.as_ptr() as *const std::os::raw::c_void,
 			gl::STATIC_DRAW,
 		);
-
-		let stride =
-			8 * std::mem::size_of::<gl::types::GLfloat>() as gl::types::GLsizei;
-		gl::VertexAttribPointer(
-			0,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			std::ptr::null(),
-		);
-		gl::EnableVertexAttribArray(0);
-
-		gl::VertexAttribPointer(
-			1,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			(3 * std::mem::size_of::<gl::types::GLfloat>())
-				as *const std::os::raw::c_void,
-		);
-		gl::EnableVertexAttribArray(1);
-
-		gl::VertexAttribPointer(
-			2,
-			2,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			(6 * std::mem::size_of::<gl::types::GLfloat>())
-				as *const std::os::raw::c_void,
-		);
-		gl::EnableVertexAttribArray(2);
-
-		gl::GenVertexArrays(1, &mut light_vao);
-		gl::BindVertexArray(light_vao);
-		gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-		gl::VertexAttribPointer(
-			0,
-			3,
-			gl::FLOAT,
-			gl::FALSE,
-			stride,
-			std::ptr::null(),
-		);
-		gl::EnableVertexAttribArray(0);
 	}
-
-	(object_vao, light_vao)
-}
-
-fn load_texture(path: &str) -> u32 {
-	let mut texture: u32 = 0;
-	let img =
-		image::open(&std::path::Path::new(path)).expect("Failed to load texture");
-	let data = img.raw_pixels();
-	let format = match img {
-		image::ImageLuma8(_) => gl::RED,
-		image::ImageLumaA8(_) => gl::RG,
-		image::ImageRgb8(_) => gl::RGB,
-		image::ImageRgba8(_) => gl::RGBA,
-	};
-
-	unsafe {
-		gl::GenTextures(1, &mut texture);
-		gl::BindTexture(gl::TEXTURE_2D, texture);
-
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-		gl::TexParameteri(
-			gl::TEXTURE_2D,
-			gl::TEXTURE_MIN_FILTER,
-			gl::LINEAR as i32,
-		);
-		gl::TexParameteri(
-			gl::TEXTURE_2D,
-			gl::TEXTURE_MAG_FILTER,
-			gl::LINEAR as i32,
-		);
-
-		gl::TexImage2D(
-			gl::TEXTURE_2D,
-			0,
-			format as i32,
-			img.width() as i32,
-			img.height() as i32,
-			0,
-			format,
-			gl::UNSIGNED_BYTE,
-			&data[0] as *const u8 as *const std::os::raw::c_void,
-		);
-
-		gl::GenerateMipmap(gl::TEXTURE_2D);
+	vertex::set_attributes::<CubeVertex>(0, 0);
+	let object_instances = instance::create_instance_buffer(3);
+
+	// The light shader only reads the position.
+	light_vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	vertex::set_attributes::<CubeVertex>(0, 0);
+	let light_instances = instance::create_instance_buffer(3);
+
+	CubeArrays {
+		object: object_vao,
+		object_instances,
+		light: light_vao,
+		light_instances,
+		_vbo: vbo,
 	}
-
-	texture
 }
 
 fn main() {
 	let (mut glfw, mut window, events) = gl_init();
-	let mut camera_pos = cgmath::point3(0.0, 0.0, 6.0);
-	let mut camera_front = cgmath::vec3(0.0, 0.0, -1.0);
-	let camera_up = cgmath::vec3(0.0, 1.0, 0.0);
-	let camera_speed = 2.5;
+	let mut camera = camera::Camera::new(cgmath::point3(0.0, 0.0, 6.0));
+	let (width, height) = window.get_framebuffer_size();
+	camera.projection.resize(width, height);
+	let bindings = input::Bindings::load_or_default("bindings.toml");
+	let mut gamepad = gamepad::Gamepad::default();
+	let mut session = record::Session::from_args(&window);
+	let mut flythrough =
+		flythrough::Flythrough::load_or_default("flythrough.txt");
+	let mut wireframe = false;
 	let mut last_frame: f32 = 0.0;
-	let mut first_mouse = true;
-	let mut yaw: f32 = -90.0;
-	let mut pitch: f32 = 0.0;
-	let mut last_x: f32 = 400.0;
-	let mut last_y: f32 = 300.0;
 
 	let light_shader = shader::create_shader("light.vert", "light.frag");
 
 	let object_shader = shader::create_shader("object.vert", "object.frag");
 
-	let (object_vao, light_vao) = create_vao();
-	let diffuse_map = load_texture("../../assets/textures/container2.png");
-	let specular_map = load_texture("../../assets/textures/container2_specular.png");
-
-	let cubes: [cgmath::Vector3<f32>; 10] = [
+	let cube_arrays = create_vao();
+	let texture_options = texture::TextureOptions::default();
+	let diffuse_map = texture::load_texture(
+		"../../assets/textures/container2.png",
+		&texture_options.for_role("diffuse"),
+	);
+	let specular_map = texture::load_texture(
+		"../../assets/textures/container2_specular.png",
+		&texture_options.for_role("specular"),
+	);
+
+	let cube_positions: [cgmath::Vector3<f32>; 10] = [
 		cgmath::vec3(0.0, 0.0, 0.0),
 		cgmath::vec3(2.0, 5.0, -15.0),
 		cgmath::vec3(-1.5, -2.2, -2.5),
@@ -198,103 +176,210 @@ fn main() {
 		cgmath::vec3(-1.3, 1.0, -1.5),
 	];
 
-	let lights: [cgmath::Vector3<f32>; 4] = [
+	let light_positions: [cgmath::Vector3<f32>; 4] = [
 			cgmath::vec3( 0.7,  0.2,  2.0),
 			cgmath::vec3( 2.3, -3.3, -4.0),
 			cgmath::vec3(-4.0,  2.0, -12.0),
 			cgmath::vec3( 0.0,  0.0, -3.0)
 	];
 
+	// Where everything starts, moved around with the gizmo from then on.
+	let mut cubes: Vec<gizmo::Transform> = cube_positions
+		.iter()
+		.enumerate()
+		.map(|(i, &position)| gizmo::Transform {
+			rotation: cgmath::Quaternion::from_axis_angle(
+				cgmath::vec3(1.0, 0.3, 0.5).normalize(),
+				cgmath::Deg(20.0 * i as f32),
+			),
+			..gizmo::Transform::from_translation(position)
+		})
+		.collect();
+	let mut lights: Vec<gizmo::Transform> = light_positions
+		.iter()
+		.map(|&position| gizmo::Transform {
+			scale: cgmath::vec3(0.2, 0.2, 0.2),
+			..gizmo::Transform::from_translation(position)
+		})
+		.collect();
+	let cube_triangles: Vec<[cgmath::Point3<f32>; 3]> = CUBE_VERTICES
+		.chunks(8 * 3)
+		.map(|t| {
+			let corner = |i: usize| cgmath::point3(t[i], t[i + 1], t[i + 2]);
+			[corner(0), corner(8), corner(16)]
+		})
+		.collect();
+
+	// Ids for picking: the cubes from 1, then the lights.
+	let first_light = 1 + cubes.len() as u32;
+	let mut picked = ray::NOTHING;
+	let mut pick_requested = false;
+	let mut cursor = (0.0, 0.0);
+	let mut cursor_free = false;
+	let mut gizmo = gizmo::Gizmo::default();
+	let gizmo_renderer = gizmo::GizmoRenderer::new();
+
+	let sky = skybox::Background::Skybox(match arg("--skybox") {
+		Some(path) => skybox::Skybox::load(&path),
+		None => skybox::Skybox::gradient(),
+	});
+	let flat = skybox::Background::default_color();
+	let mut show_sky = true;
+
 	while !window.should_close() {
-		let current_frame = glfw.get_time() as f32;
+		let frame = match session.next_frame(
+			&glfw,
+			&window,
+			&events,
+			&bindings,
+			&mut gamepad,
+		) {
+			Some(frame) => frame,
+			None => break,
+		};
+		let current_frame = frame.time as f32;
 		let delta_time = current_frame - last_frame;
 		last_frame = current_frame;
 
-		for (_, event) in glfw::flush_messages(&events) {
+		let mut actions = gamepad.triggered();
+		for event in frame.events {
+			actions.extend(bindings.triggered(&event));
 			match event {
-				glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
-					gl::Viewport(0, 0, width, height)
-				},
-				glfw::WindowEvent::Key(
-					glfw::Key::Escape | glfw::Key::Q,
-					_,
-					glfw::Action::Release,
-					_,
-				) => {
-					window.set_should_close(true);
+				glfw::WindowEvent::FramebufferSize(width, height) => {
+					unsafe {
+						gl::Viewport(0, 0, width, height);
+					}
+					camera.projection.resize(width, height);
 				}
 				glfw::WindowEvent::CursorPos(xpos, ypos) => {
-					let (xpos, ypos) = (xpos as f32, ypos as f32);
-					if first_mouse {
-						last_x = xpos;
-						last_y = ypos;
-						first_mouse = false;
+					cursor = (xpos, ypos);
+					if !cursor_free {
+						camera.mouse_moved(xpos as f32, ypos as f32);
 					}
+				}
+				glfw::WindowEvent::Scroll(_, yoffset) => {
+					camera.scroll(yoffset as f32);
+				}
+				_ => {}
+			}
+		}
 
-					let mut xoffset = xpos - last_x;
-					let mut yoffset = last_y - ypos;
-					last_x = xpos;
-					last_y = ypos;
-
-					let sensitivity: f32 = 0.1;
-					xoffset *= sensitivity;
-					yoffset *= sensitivity;
-
-					yaw += xoffset;
-					pitch += yoffset;
-
-					if pitch > 89.0 {
-						pitch = 89.0;
-					}
-					if pitch < -89.0 {
-						pitch = -89.0;
+		for action in actions {
+			match action {
+				input::Action::Quit => {
+					window.set_should_close(true);
+				}
+				input::Action::ToggleSky => {
+					show_sky = !show_sky;
+				}
+				input::Action::NextCameraMode => {
+					camera.next_mode();
+				}
+				input::Action::ToggleProjection => {
+					camera.toggle_projection();
+				}
+				input::Action::AddKeyframe => {
+					flythrough.add_keyframe(&camera);
+				}
+				input::Action::ClearKeyframes => {
+					flythrough.clear();
+				}
+				input::Action::PlayFlythrough => {
+					flythrough.toggle(&mut camera);
+				}
+				input::Action::Pick => {
+					pick_requested = true;
+				}
+				input::Action::ToggleCursor => {
+					cursor_free = !cursor_free;
+					window.set_cursor_mode(if cursor_free {
+						glfw::CursorMode::Normal
+					} else {
+						glfw::CursorMode::Disabled
+					});
+					camera.reset_mouse();
+				}
+				input::Action::NextGizmoMode => {
+					gizmo.mode = gizmo.mode.next();
+				}
+				input::Action::ToggleWireframe => {
+					wireframe = !wireframe;
+					let mode = if wireframe { gl::LINE } else { gl::FILL };
+					unsafe {
+						gl::PolygonMode(gl::FRONT_AND_BACK, mode);
 					}
-
-					let front = cgmath::Vector3 {
-						x: yaw.to_radians().cos() * pitch.to_radians().cos(),
-						y: pitch.to_radians().sin(),
-						z: yaw.to_radians().sin() * pitch.to_radians().cos(),
-					};
-					camera_front = front.normalize();
 				}
 				_ => {}
 			}
 		}
 
-		if window.get_key(glfw::Key::W) == glfw::Action::Press {
-			camera_pos += camera_speed * camera_front * delta_time;
-		}
-		if window.get_key(glfw::Key::S) == glfw::Action::Press {
-			camera_pos -= camera_speed * camera_front * delta_time;
-		}
-		if window.get_key(glfw::Key::A) == glfw::Action::Press {
-			camera_pos -=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
-		}
-		if window.get_key(glfw::Key::D) == glfw::Action::Press {
-			camera_pos +=
-				camera_front.cross(camera_up).normalize() * camera_speed * delta_time;
+		if flythrough.playing() {
+			flythrough.update(&mut camera, delta_time);
+		} else {
+			camera.process_input(&bindings, &frame.held, delta_time);
+			camera.process_gamepad(&gamepad, delta_time);
+			camera.update(delta_time);
 		}
 
 		unsafe {
-			let view = cgmath::Matrix4::look_at_rh(
-				camera_pos,
-				camera_pos + camera_front,
-				camera_up,
-			);
-			let projection: cgmath::Matrix4<f32> = cgmath::perspective(
-				cgmath::Deg(45.0),
-				800 as f32 / 600 as f32,
-				0.1,
-				100.0,
-			);
+			let view = camera.view();
+			let projection = camera.projection();
+
+			// The middle of the screen while the cursor steers.
+			let ndc = if cursor_free {
+				ray::cursor_ndc(cursor, frame.window_size)
+			} else {
+				cgmath::vec2(0.0, 0.0)
+			};
+			let cursor_ray = ray::Ray::from_ndc(ndc, &(projection * view));
+
+			// The picked object's gizmo gets the mouse first, letting go of
+			// a handle doesn't pick whatever is under it.
+			let object = selected(picked, first_light, &mut cubes, &mut lights);
+			if let (Some(object), Some(ray)) = (object, cursor_ray) {
+				let size = gizmo.size(object.center(), &view, &projection);
+				let grabbing =
+					bindings.pressed(input::Action::Pick, &frame.held);
+				let snapping =
+					bindings.pressed(input::Action::Snap, &frame.held);
+				if gizmo.update(&ray, object, size, grabbing, snapping) {
+					pick_requested = false;
+				}
+			}
 
-			gl::ClearColor(0.22, 0.18, 0.22, 1.0);
-			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+			if pick_requested {
+				pick_requested = false;
+				let matrices = |objects: &[gizmo::Transform]| {
+					objects
+						.iter()
+						.map(gizmo::Transform::matrix)
+						.collect::<Vec<_>>()
+				};
+				let hit = cursor_ray.and_then(|ray| {
+					ray::nearest(
+						ray::pick_placed(
+							&ray,
+							&cube_triangles,
+							&matrices(&cubes),
+							1,
+						),
+						ray::pick_placed(
+							&ray,
+							&cube_triangles,
+							&matrices(&lights),
+							first_light,
+						),
+					)
+				});
+				picked = hit.map_or(ray::NOTHING, |hit| hit.id);
+			}
+
+			let background = if show_sky { &sky } else { &flat };
+			background.clear();
 
 			gl::UseProgram(object_shader);
 
-			shader::set_point3(object_shader, "viewPos", camera_pos);
+			shader::set_point3(object_shader, "viewPos", camera.position);
 			shader::set_mat4(object_shader, "view", view);
 			shader::set_mat4(object_shader, "projection", projection);
 
@@ -312,7 +397,7 @@ fn main() {
 			shader::set_int(object_shader, "material.specular", 1);
 			shader::set_float(object_shader, "material.shininess", 32.0);
 			for (i, light) in lights.iter().enumerate() {
-				shader::set_vec3(object_shader, &format!("pointLights[{}].position", i), *light);
+				shader::set_vec3(object_shader, &format!("pointLights[{}].position", i), light.translation);
 				shader::set_float(object_shader, &format!("pointLights[{}].constant", i), 1.0);
 				shader::set_float(object_shader, &format!("pointLights[{}].linear", i), 0.09);
 				shader::set_float(object_shader, &format!("pointLights[{}].quadratic", i), 0.032);
@@ -324,8 +409,8 @@ fn main() {
 			shader::set_vec3(object_shader, "dirLight.ambient", ambient_color);
 			shader::set_vec3(object_shader, "dirLight.diffuse", diffuse_color);
 			shader::set_vec3(object_shader, "dirLight.specular", specular_color);
-			shader::set_vec3(object_shader, "spotLight.position", camera_pos.to_vec());
-			shader::set_vec3(object_shader, "spotLight.direction", camera_front);
+			shader::set_vec3(object_shader, "spotLight.position", camera.position.to_vec());
+			shader::set_vec3(object_shader, "spotLight.direction", camera.front());
 			shader::set_vec3(object_shader, "spotLight.ambient", ambient_color);
 			shader::set_vec3(object_shader, "spotLight.diffuse", diffuse_color);
 			shader::set_vec3(object_shader, "spotLight.specular", specular_color);
@@ -334,39 +419,112 @@ fn main() {
 			shader::set_float(object_shader, "spotLight.quadratic", 0.032);
 			shader::set_float(object_shader, "spotLight.cutOff", 10.5f32.to_radians().cos());
 			shader::set_float(object_shader, "spotLight.outerCutOff", 13.5f32.to_radians().cos());
-			gl::BindVertexArray(object_vao);
+			let frustum = bounds::Frustum::from_matrix(&(projection * view));
+			let mut stats = bounds::CullStats::default();
+
+			cube_arrays.object.bind();
 			gl::ActiveTexture(gl::TEXTURE0);
-			gl::BindTexture(gl::TEXTURE_2D, diffuse_map);
+			diffuse_map.bind();
 			gl::ActiveTexture(gl::TEXTURE1);
-			gl::BindTexture(gl::TEXTURE_2D, specular_map);
-			for (i, cube) in cubes.iter().enumerate() {
-				let mut model = cgmath::Matrix4::from_translation(*cube);
-				let angle = 20.0 * i as f32;
-				model = model
-					* cgmath::Matrix4::from_axis_angle(
-						cgmath::vec3(1.0, 0.3, 0.5).normalize(),
-						cgmath::Deg(angle),
-					);
-				shader::set_mat4(object_shader, "model", model);
-				gl::DrawArrays(gl::TRIANGLES, 0, 36);
+			specular_map.bind();
+			let mut transforms = Vec::with_capacity(cubes.len());
+			for cube in cubes.iter() {
+				// Sphere around the unit cube, whatever its rotation.
+				let sphere = bounds::Sphere {
+					center: cube.center(),
+					radius: 0.87 * max_scale(cube),
+				};
+				if !frustum.intersects_sphere(&sphere) {
+					stats.culled += 1;
+					continue;
+				}
+				stats.drawn += 1;
+
+				transforms.push(cube.matrix());
 			}
+			instance::upload_instances(
+				&cube_arrays.object_instances,
+				&transforms,
+				None,
+			);
+			gl::DrawArraysInstanced(
+				gl::TRIANGLES,
+				0,
+				36,
+				transforms.len() as i32,
+			);
 
 			gl::UseProgram(light_shader);
 			shader::set_mat4(light_shader, "view", view);
 			shader::set_mat4(light_shader, "projection", projection);
 
-			gl::BindVertexArray(light_vao);
+			cube_arrays.light.bind();
+			let mut transforms = Vec::with_capacity(lights.len());
+			let mut colors = Vec::with_capacity(lights.len());
 			for light in lights.iter() {
-				let mut model = cgmath::Matrix4::from_translation(*light);
-				model = model * cgmath::Matrix4::from_scale(0.2);
-				shader::set_mat4(light_shader, "model", model);
-				gl::DrawArrays(gl::TRIANGLES, 0, 36);
+				let sphere = bounds::Sphere {
+					center: light.center(),
+					radius: 0.87 * max_scale(light),
+				};
+				if !frustum.intersects_sphere(&sphere) {
+					stats.culled += 1;
+					continue;
+				}
+				stats.drawn += 1;
+
+				transforms.push(light.matrix());
+				colors.push(light_color.extend(1.0));
+			}
+			instance::upload_instances(
+				&cube_arrays.light_instances,
+				&transforms,
+				Some(&colors[..]),
+			);
+			gl::DrawArraysInstanced(
+				gl::TRIANGLES,
+				0,
+				36,
+				transforms.len() as i32,
+			);
+			background.draw(view, projection);
+			if let Some(object) =
+				selected(picked, first_light, &mut cubes, &mut lights)
+			{
+				let size = gizmo.size(object.center(), &view, &projection);
+				gizmo_renderer.draw(&gizmo, object, size, view, projection);
 			}
+
+			window.set_title(&format!(
+				"Learn OpenGL - {} drawn, {} culled",
+				stats.drawn, stats.culled
+			));
 		}
 
 		glfw.poll_events();
 		window.swap_buffers();
 	}
+
+	drop((cube_arrays, diffuse_map, specular_map, sky, flat));
+	drop(gizmo_renderer);
+	gpu::report_leaks();
+}
+
+// The cube or light with a picked id.
+fn selected<'a>(
+	picked: u32,
+	first_light: u32,
+	cubes: &'a mut [gizmo::Transform],
+	lights: &'a mut [gizmo::Transform],
+) -> Option<&'a mut gizmo::Transform> {
+	match picked {
+		ray::NOTHING => None,
+		id if id < first_light => cubes.get_mut((id - 1) as usize),
+		id => lights.get_mut((id - first_light) as usize),
+	}
+}
+
+fn max_scale(transform: &gizmo::Transform) -> f32 {
+	transform.scale.x.max(transform.scale.y).max(transform.scale.z)
 }
 
 static CUBE_VERTICES: [f32; 288] = [
@@ -0,0 +1,167 @@
+use crate::cubemap;
+use crate::gpu;
+use crate::shader;
+use crate::texture;
+use crate::vertex;
+use cgmath::prelude::*;
+
+struct SkyVertex {
+	position: [f32; 3],
+}
+
+vertex::vertex_layout!(SkyVertex { position: 0 });
+
+pub struct Skybox {
+	texture: gpu::TextureCube,
+	program: u32,
+	vao: gpu::VertexArray,
+	_vbo: gpu::Buffer,
+}
+
+impl Skybox {
+	// Needs skybox.vert and skybox.frag next to the other shaders.
+	pub fn new(texture: gpu::TextureCube) -> Skybox {
+		// A unit cube around the camera, wound to face inwards.
+		let mut vertices = std::vec::Vec::with_capacity(36);
+		for face in 0..6 {
+			let corner = |s, t| SkyVertex {
+				position: cubemap::face_direction(face, s, t).into(),
+			};
+			for &(s, t) in &[
+				(-1.0, -1.0),
+				(1.0, -1.0),
+				(1.0, 1.0),
+				(-1.0, -1.0),
+				(1.0, 1.0),
+				(-1.0, 1.0),
+			] {
+				vertices.push(corner(s, t));
+			}
+		}
+
+		let vao = gpu::VertexArray::new();
+		let vbo = gpu::Buffer::new();
+		vao.bind();
+		vbo.bind(gl::ARRAY_BUFFER);
+		unsafe {
+			gl::BufferData(
+				gl::ARRAY_BUFFER,
+				(vertices.len() * std::mem::size_of::<SkyVertex>())
+					as gl::types::GLsizeiptr,
+				vertices.as_ptr() as *const std::os::raw::c_void,
+				gl::STATIC_DRAW,
+			);
+		}
+		vertex::set_attributes::<SkyVertex>(0, 0);
+		unsafe {
+			gl::BindVertexArray(0);
+		}
+
+		Skybox {
+			texture,
+			program: shader::create_shader("skybox.vert", "skybox.frag"),
+			vao,
+			_vbo: vbo,
+		}
+	}
+
+	// A blue sky fading to a pale horizon over dark ground, for chapters
+	// without skybox images.
+	pub fn gradient() -> Skybox {
+		let zenith = cgmath::vec3(0.2, 0.4, 0.8);
+		let horizon = cgmath::vec3(0.75, 0.8, 0.85);
+		let ground = cgmath::vec3(0.22, 0.18, 0.22);
+		let texture = cubemap::cubemap_from_fn(
+			64,
+			&texture::TextureOptions::default(),
+			|direction| {
+				if direction.y >= 0.0 {
+					horizon.lerp(zenith, direction.y.sqrt())
+				} else {
+					horizon.lerp(ground, (-direction.y).sqrt())
+				}
+			},
+		);
+		Skybox::new(texture)
+	}
+
+	// Either a directory holding the usual right, left, top, bottom, front
+	// and back .jpg faces, or a single equirectangular panorama.
+	pub fn load(path: &str) -> Skybox {
+		let options = texture::TextureOptions::default();
+		let texture = if std::path::Path::new(path).is_dir() {
+			let faces = ["right", "left", "top", "bottom", "front", "back"]
+				.map(|face| format!("{}/{}.jpg", path, face));
+			cubemap::load_cubemap(
+				faces.each_ref().map(|face| face.as_str()),
+				&options,
+			)
+		} else {
+			cubemap::cubemap_from_equirectangular(path, 512, &options)
+		};
+		Skybox::new(texture)
+	}
+
+	// Draw it after the opaque geometry. It sits on the far plane, so depth
+	// testing leaves only the pixels nothing else covered.
+	pub fn draw(
+		&self,
+		view: cgmath::Matrix4<f32>,
+		projection: cgmath::Matrix4<f32>,
+	) {
+		// Turning with the camera but never getting closer.
+		let mut rotation = view;
+		rotation.w = cgmath::vec4(0.0, 0.0, 0.0, 1.0);
+
+		unsafe {
+			gl::DepthFunc(gl::LEQUAL);
+			gl::UseProgram(self.program);
+			shader::set_mat4(self.program, "view", rotation);
+			shader::set_mat4(self.program, "projection", projection);
+			gl::ActiveTexture(gl::TEXTURE0);
+			self.texture.bind();
+			shader::set_int(self.program, "skybox", 0);
+			self.vao.bind();
+			gl::DrawArrays(gl::TRIANGLES, 0, 36);
+			gl::BindVertexArray(0);
+			gl::DepthFunc(gl::LESS);
+		}
+	}
+}
+
+// What shows where the scene doesn't cover the screen.
+pub enum Background {
+	Color(cgmath::Vector3<f32>),
+	Skybox(Skybox),
+}
+
+impl Background {
+	// The flat colour the chapters have always cleared to.
+	pub fn default_color() -> Background {
+		Background::Color(cgmath::vec3(0.22, 0.18, 0.22))
+	}
+
+	// At the start of the frame.
+	pub fn clear(&self) {
+		let color = match self {
+			Background::Color(color) => *color,
+			// Fully covered by the skybox later on.
+			Background::Skybox(_) => cgmath::vec3(0.0, 0.0, 0.0),
+		};
+		unsafe {
+			gl::ClearColor(color.x, color.y, color.z, 1.0);
+			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+		}
+	}
+
+	// After the opaque geometry.
+	pub fn draw(
+		&self,
+		view: cgmath::Matrix4<f32>,
+		projection: cgmath::Matrix4<f32>,
+	) {
+		if let Background::Skybox(skybox) = self {
+			skybox.draw(view, projection);
+		}
+	}
+}
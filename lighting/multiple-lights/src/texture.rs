@@ -0,0 +1,832 @@
+use crate::dds;
+use crate::gpu;
+use crate::ktx2;
+use image::GenericImage;
+use png::HasParameters;
+use std::path::Path;
+
+// From EXT_texture_filter_anisotropic, EXT_texture_compression_s3tc and
+// EXT_texture_sRGB, which every desktop driver has but the gl crate doesn't
+// generate.
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+const COMPRESSED_RGB_S3TC_DXT1: gl::types::GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: gl::types::GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: gl::types::GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: gl::types::GLenum = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1: gl::types::GLenum = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: gl::types::GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: gl::types::GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: gl::types::GLenum = 0x8C4F;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+	// Gamma encoded, decoded to linear when sampled. No chapter gamma
+	// corrects its output yet, so they all load textures as linear.
+	#[allow(dead_code)]
+	Srgb,
+	Linear,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+	pub color_space: ColorSpace,
+	// Image files start at the top row, OpenGL textures at the bottom one.
+	pub flip_vertically: bool,
+	pub wrap_s: gl::types::GLenum,
+	pub wrap_t: gl::types::GLenum,
+	// Mipmapped filters fall back to their plain versions without mipmaps.
+	pub min_filter: gl::types::GLenum,
+	pub mag_filter: gl::types::GLenum,
+	// Built when decoded images are loaded. DDS and KTX2 files bring their own
+	// and are only mipmapped if they store more than one level.
+	pub mipmaps: bool,
+	// 1.0 turns it off, anything above is clamped to what the driver allows.
+	pub anisotropy: f32,
+	// None picks the largest alignment the image rows allow.
+	pub unpack_alignment: Option<i32>,
+	// Floating point images are stored as 16-bit halves rather than full
+	// floats, which is plenty for colours and half the memory.
+	pub half_float: bool,
+}
+
+impl Default for TextureOptions {
+	fn default() -> Self {
+		TextureOptions {
+			color_space: ColorSpace::Linear,
+			flip_vertically: false,
+			wrap_s: gl::REPEAT,
+			wrap_t: gl::REPEAT,
+			min_filter: gl::LINEAR_MIPMAP_LINEAR,
+			mag_filter: gl::LINEAR,
+			mipmaps: true,
+			anisotropy: 1.0,
+			unpack_alignment: None,
+			half_float: true,
+		}
+	}
+}
+
+impl TextureOptions {
+	// Only colour textures are stored as sRGB. Specular and normal maps hold
+	// data and are always linear.
+	pub fn for_role(&self, type_name: &str) -> TextureOptions {
+		let color_space = match type_name {
+			"diffuse" | "ambient" | "emissive" => self.color_space,
+			_ => ColorSpace::Linear,
+		};
+		TextureOptions {
+			color_space,
+			..*self
+		}
+	}
+}
+
+pub enum Pixels {
+	U8(std::vec::Vec<u8>),
+	U16(std::vec::Vec<u16>),
+	F32(std::vec::Vec<f32>),
+}
+
+// Decoded pixels at the precision they were stored with, rows from the top.
+pub struct Image {
+	pub width: u32,
+	pub height: u32,
+	pub channels: usize,
+	pub pixels: Pixels,
+}
+
+impl Image {
+	// Radiance `.hdr` files come in as floats and 16-bit PNGs as 16-bit
+	// samples, everything else goes through `image` as 8-bit.
+	pub fn read(path: &Path) -> std::io::Result<Image> {
+		let extension = path
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.map(|extension| extension.to_lowercase());
+		match extension.as_deref() {
+			Some("hdr") => open_hdr(path),
+			Some("png") => open_png(path),
+			Some("exr") => Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!(
+					"OpenEXR isn't supported, convert {} to .hdr",
+					path.display()
+				),
+			)),
+			_ => Ok(Image::from_dynamic(
+				image::open(path).map_err(decode_error)?,
+			)),
+		}
+	}
+
+	// For textures the chapter can't do without.
+	pub fn open(path: &Path) -> Image {
+		match Image::read(path) {
+			Ok(img) => img,
+			Err(err) => {
+				panic!("Texture {} failed to load: {}", path.display(), err)
+			}
+		}
+	}
+
+	pub fn from_dynamic(img: image::DynamicImage) -> Image {
+		let channels = match img {
+			image::ImageLuma8(_) => 1,
+			image::ImageLumaA8(_) => 2,
+			image::ImageRgb8(_) => 3,
+			image::ImageRgba8(_) => 4,
+		};
+		Image {
+			width: img.width(),
+			height: img.height(),
+			channels,
+			pixels: Pixels::U8(img.raw_pixels()),
+		}
+	}
+
+	pub fn flip_vertically(&mut self) {
+		let row = self.width as usize * self.channels;
+		match &mut self.pixels {
+			Pixels::U8(pixels) => flip_rows(pixels, row),
+			Pixels::U16(pixels) => flip_rows(pixels, row),
+			Pixels::F32(pixels) => flip_rows(pixels, row),
+		}
+	}
+
+	// One channel of a pixel, integer samples scaled to 0..1. Only chapters
+	// with an atlas or a heightmap read pixels back.
+	#[allow(dead_code)]
+	pub fn sample(&self, x: u32, y: u32, channel: usize) -> f32 {
+		let i = (y as usize * self.width as usize + x as usize) * self.channels
+			+ channel;
+		match &self.pixels {
+			Pixels::U8(pixels) => pixels[i] as f32 / 255.0,
+			Pixels::U16(pixels) => pixels[i] as f32 / 65535.0,
+			Pixels::F32(pixels) => pixels[i],
+		}
+	}
+
+	// Luminance for colour images, the grey channel otherwise.
+	#[allow(dead_code)]
+	pub fn gray(&self, x: u32, y: u32) -> f32 {
+		if self.channels < 3 {
+			return self.sample(x, y, 0);
+		}
+		0.2126 * self.sample(x, y, 0)
+			+ 0.7152 * self.sample(x, y, 1)
+			+ 0.0722 * self.sample(x, y, 2)
+	}
+}
+
+fn decode_error<E: std::fmt::Display>(err: E) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn open_hdr(path: &Path) -> std::io::Result<Image> {
+	let file = std::fs::File::open(path)?;
+	let decoder = image::hdr::HDRDecoder::new(std::io::BufReader::new(file))
+		.map_err(decode_error)?;
+	let metadata = decoder.metadata();
+	let pixels = decoder.read_image_hdr().map_err(decode_error)?;
+	Ok(Image {
+		width: metadata.width,
+		height: metadata.height,
+		channels: 3,
+		pixels: Pixels::F32(
+			pixels
+				.iter()
+				.flat_map(|pixel| pixel.data.iter().copied())
+				.collect(),
+		),
+	})
+}
+
+// `image` only decodes 8 bits per sample, so 16-bit PNGs are read directly.
+fn open_png(path: &Path) -> std::io::Result<Image> {
+	let file = std::fs::File::open(path)?;
+	let mut decoder = png::Decoder::new(file);
+	// Palettes and low bit depths still expand to 8 bits, 16 stays 16.
+	decoder.set(png::Transformations::EXPAND);
+	let (info, mut reader) = decoder.read_info().map_err(decode_error)?;
+	if info.bit_depth != png::BitDepth::Sixteen {
+		return Ok(Image::from_dynamic(
+			image::open(path).map_err(decode_error)?,
+		));
+	}
+
+	let mut bytes = vec![0; info.buffer_size()];
+	reader.next_frame(&mut bytes).map_err(decode_error)?;
+	Ok(Image {
+		width: info.width,
+		height: info.height,
+		channels: info.color_type.samples(),
+		// Big endian in the file.
+		pixels: Pixels::U16(
+			bytes
+				.chunks(2)
+				.map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+				.collect(),
+		),
+	})
+}
+
+fn flip_rows<T>(pixels: &mut [T], row: usize) {
+	let rows = pixels.len() / row;
+	for y in 0..rows / 2 {
+		let (top, bottom) = pixels.split_at_mut((rows - 1 - y) * row);
+		top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+	}
+}
+
+// Pixel formats DDS and KTX2 files can hold, block compressed or not.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SurfaceFormat {
+	Bc1,
+	// BC1 with one bit of alpha.
+	Bc1Alpha,
+	Bc2,
+	Bc3,
+	Bc4,
+	Bc4Signed,
+	Bc5,
+	Bc5Signed,
+	Bc6hUnsigned,
+	Bc6hSigned,
+	Bc7,
+	R8,
+	Rg8,
+	Rgba8,
+	Bgra8,
+	Rgba16F,
+	Rgba32F,
+}
+
+impl SurfaceFormat {
+	// Bytes per 4x4 block, None for formats stored pixel by pixel.
+	pub fn block_size(self) -> Option<usize> {
+		match self {
+			SurfaceFormat::Bc1
+			| SurfaceFormat::Bc1Alpha
+			| SurfaceFormat::Bc4
+			| SurfaceFormat::Bc4Signed => Some(8),
+			SurfaceFormat::Bc2
+			| SurfaceFormat::Bc3
+			| SurfaceFormat::Bc5
+			| SurfaceFormat::Bc5Signed
+			| SurfaceFormat::Bc6hUnsigned
+			| SurfaceFormat::Bc6hSigned
+			| SurfaceFormat::Bc7 => Some(16),
+			_ => None,
+		}
+	}
+
+	fn pixel_size(self) -> usize {
+		match self {
+			SurfaceFormat::R8 => 1,
+			SurfaceFormat::Rg8 => 2,
+			SurfaceFormat::Rgba16F => 8,
+			SurfaceFormat::Rgba32F => 16,
+			_ => 4,
+		}
+	}
+
+	// Size in bytes of one mip level, blocks cover partial edges. Saturates
+	// rather than overflowing on the sizes a corrupt header can claim.
+	pub fn level_size(self, width: u32, height: u32) -> usize {
+		let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+		match self.block_size() {
+			Some(block) => width
+				.div_ceil(4)
+				.saturating_mul(height.div_ceil(4))
+				.saturating_mul(block),
+			None => width
+				.saturating_mul(height)
+				.saturating_mul(self.pixel_size()),
+		}
+	}
+
+	// Internal format, and for uncompressed formats the pixel format and type.
+	fn gl_format(
+		self,
+		srgb: bool,
+	) -> (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum) {
+		let compressed = |linear, srgb_format| {
+			(if srgb { srgb_format } else { linear }, 0, 0)
+		};
+		match self {
+			SurfaceFormat::Bc1 => {
+				compressed(COMPRESSED_RGB_S3TC_DXT1, COMPRESSED_SRGB_S3TC_DXT1)
+			}
+			SurfaceFormat::Bc1Alpha => compressed(
+				COMPRESSED_RGBA_S3TC_DXT1,
+				COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+			),
+			SurfaceFormat::Bc2 => compressed(
+				COMPRESSED_RGBA_S3TC_DXT3,
+				COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+			),
+			SurfaceFormat::Bc3 => compressed(
+				COMPRESSED_RGBA_S3TC_DXT5,
+				COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+			),
+			SurfaceFormat::Bc4 => (gl::COMPRESSED_RED_RGTC1, 0, 0),
+			SurfaceFormat::Bc4Signed => (gl::COMPRESSED_SIGNED_RED_RGTC1, 0, 0),
+			SurfaceFormat::Bc5 => (gl::COMPRESSED_RG_RGTC2, 0, 0),
+			SurfaceFormat::Bc5Signed => (gl::COMPRESSED_SIGNED_RG_RGTC2, 0, 0),
+			SurfaceFormat::Bc6hUnsigned => {
+				(gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT, 0, 0)
+			}
+			SurfaceFormat::Bc6hSigned => {
+				(gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT, 0, 0)
+			}
+			SurfaceFormat::Bc7 => compressed(
+				gl::COMPRESSED_RGBA_BPTC_UNORM,
+				gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+			),
+			SurfaceFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+			SurfaceFormat::Rg8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+			SurfaceFormat::Rgba8 => (
+				if srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 },
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+			),
+			SurfaceFormat::Bgra8 => (
+				if srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 },
+				gl::BGRA,
+				gl::UNSIGNED_BYTE,
+			),
+			SurfaceFormat::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+			SurfaceFormat::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+		}
+	}
+}
+
+// A texture as a DDS or KTX2 file stores it, every mip level ready to be
+// uploaded as is.
+pub struct Surface {
+	pub format: SurfaceFormat,
+	// Set by the file. The colour space in `TextureOptions` doesn't apply.
+	pub srgb: bool,
+	pub width: u32,
+	pub height: u32,
+	// Largest first, each half the size of the one before.
+	pub levels: std::vec::Vec<std::vec::Vec<u8>>,
+}
+
+pub fn load_texture(path: &str, options: &TextureOptions) -> gpu::Texture2D {
+	let path = Path::new(path);
+	let extension = path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.map(|extension| extension.to_lowercase());
+	match extension.as_deref() {
+		Some("dds") => texture_from_surface(
+			&dds::load_dds(path).expect("Texture failed to load"),
+			options,
+		),
+		Some("ktx2") => texture_from_surface(
+			&ktx2::load_ktx2(path).expect("Texture failed to load"),
+			options,
+		),
+		_ => texture_from_image(Image::open(path), options),
+	}
+}
+
+// Stored levels are uploaded as they are, none are generated. Flipping only
+// applies to decoded images, since compressed blocks can't be flipped by
+// reordering rows.
+pub fn texture_from_surface(
+	surface: &Surface,
+	options: &TextureOptions,
+) -> gpu::Texture2D {
+	let (internal_format, format, type_) =
+		surface.format.gl_format(surface.srgb);
+
+	let texture = gpu::Texture2D::new();
+	texture.bind();
+	unsafe {
+		gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+		for (level, data) in surface.levels.iter().enumerate() {
+			let width = (surface.width >> level).max(1) as i32;
+			let height = (surface.height >> level).max(1) as i32;
+			let pixels = data.as_ptr() as *const std::os::raw::c_void;
+			if surface.format.block_size().is_some() {
+				gl::CompressedTexImage2D(
+					gl::TEXTURE_2D,
+					level as i32,
+					internal_format,
+					width,
+					height,
+					0,
+					data.len() as i32,
+					pixels,
+				);
+			} else {
+				gl::TexImage2D(
+					gl::TEXTURE_2D,
+					level as i32,
+					internal_format as i32,
+					width,
+					height,
+					0,
+					format,
+					type_,
+					pixels,
+				);
+			}
+		}
+		gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+		// Chains that stop before 1x1 are still complete.
+		gl::TexParameteri(
+			gl::TEXTURE_2D,
+			gl::TEXTURE_MAX_LEVEL,
+			surface.levels.len() as i32 - 1,
+		);
+	}
+	apply_sampling(gl::TEXTURE_2D, options, surface.levels.len() > 1);
+
+	texture
+}
+
+pub fn texture_from_image(
+	mut img: Image,
+	options: &TextureOptions,
+) -> gpu::Texture2D {
+	if options.flip_vertically {
+		img.flip_vertically();
+	}
+
+	let texture = gpu::Texture2D::new();
+	texture.bind();
+	upload_image(gl::TEXTURE_2D, &img, options);
+	unsafe {
+		if options.mipmaps {
+			gl::GenerateMipmap(gl::TEXTURE_2D);
+		}
+	}
+	apply_sampling(gl::TEXTURE_2D, options, options.mipmaps);
+
+	texture
+}
+
+// Fills level 0 of `target`, a 2D texture or a cubemap face, on the bound
+// texture. Flipping is left to the caller.
+pub fn upload_image(
+	target: gl::types::GLenum,
+	img: &Image,
+	options: &TextureOptions,
+) {
+	let transfer = Transfer::new(img, options);
+	unsafe {
+		gl::PixelStorei(gl::UNPACK_ALIGNMENT, transfer.alignment);
+		gl::TexImage2D(
+			target,
+			0,
+			transfer.internal_format as i32,
+			img.width as i32,
+			img.height as i32,
+			0,
+			transfer.format,
+			transfer.type_,
+			transfer.data,
+		);
+		gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+	}
+}
+
+// How an image's pixels are described to OpenGL.
+struct Transfer {
+	internal_format: gl::types::GLenum,
+	format: gl::types::GLenum,
+	type_: gl::types::GLenum,
+	alignment: i32,
+	data: *const std::os::raw::c_void,
+}
+
+impl Transfer {
+	fn new(img: &Image, options: &TextureOptions) -> Transfer {
+		let format = match img.channels {
+			1 => gl::RED,
+			2 => gl::RG,
+			3 => gl::RGB,
+			_ => gl::RGBA,
+		};
+		let (internal_format, type_, sample_size, data) = match &img.pixels {
+			Pixels::U8(pixels) => (
+				// There are no single or two channel sRGB formats.
+				match (options.color_space, format) {
+					(ColorSpace::Srgb, gl::RGB) => gl::SRGB8,
+					(ColorSpace::Srgb, gl::RGBA) => gl::SRGB8_ALPHA8,
+					_ => format,
+				},
+				gl::UNSIGNED_BYTE,
+				1,
+				pixels.as_ptr() as *const std::os::raw::c_void,
+			),
+			Pixels::U16(pixels) => (
+				[gl::R16, gl::RG16, gl::RGB16, gl::RGBA16][img.channels - 1],
+				gl::UNSIGNED_SHORT,
+				2,
+				pixels.as_ptr() as *const std::os::raw::c_void,
+			),
+			Pixels::F32(pixels) => (
+				if options.half_float {
+					[gl::R16F, gl::RG16F, gl::RGB16F, gl::RGBA16F]
+						[img.channels - 1]
+				} else {
+					[gl::R32F, gl::RG32F, gl::RGB32F, gl::RGBA32F]
+						[img.channels - 1]
+				},
+				gl::FLOAT,
+				4,
+				pixels.as_ptr() as *const std::os::raw::c_void,
+			),
+		};
+		let alignment = options.unpack_alignment.unwrap_or_else(|| {
+			row_alignment(img.width as usize * img.channels * sample_size)
+		});
+
+		Transfer {
+			internal_format,
+			format,
+			type_,
+			alignment,
+			data,
+		}
+	}
+}
+
+// No chapter has meshes sharing same-sized textures yet, atlases cover the
+// ones that exist.
+#[allow(dead_code)]
+pub fn load_texture_array(
+	paths: &[&str],
+	options: &TextureOptions,
+) -> std::io::Result<gpu::Texture2DArray> {
+	let images = paths
+		.iter()
+		.map(|path| Image::read(Path::new(path)))
+		.collect::<std::io::Result<_>>()?;
+	texture_array_from_images(images, options)
+}
+
+// One layer per image, in order. They all need the same size, channels and
+// precision, pack differently sized ones into an atlas instead.
+#[allow(dead_code)]
+pub fn texture_array_from_images(
+	mut images: std::vec::Vec<Image>,
+	options: &TextureOptions,
+) -> std::io::Result<gpu::Texture2DArray> {
+	if options.flip_vertically {
+		for img in images.iter_mut() {
+			img.flip_vertically();
+		}
+	}
+	let layers = array_layers(&images, options)?;
+	let first = &layers[0];
+	let (width, height) = (images[0].width, images[0].height);
+
+	let texture = gpu::Texture2DArray::new();
+	texture.bind();
+	unsafe {
+		gl::TexImage3D(
+			gl::TEXTURE_2D_ARRAY,
+			0,
+			first.internal_format as i32,
+			width as i32,
+			height as i32,
+			images.len() as i32,
+			0,
+			first.format,
+			first.type_,
+			std::ptr::null(),
+		);
+		for (layer, transfer) in layers.iter().enumerate() {
+			gl::PixelStorei(gl::UNPACK_ALIGNMENT, transfer.alignment);
+			gl::TexSubImage3D(
+				gl::TEXTURE_2D_ARRAY,
+				0,
+				0,
+				0,
+				layer as i32,
+				width as i32,
+				height as i32,
+				1,
+				transfer.format,
+				transfer.type_,
+				transfer.data,
+			);
+		}
+		gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+		if options.mipmaps {
+			gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+		}
+	}
+	apply_sampling(gl::TEXTURE_2D_ARRAY, options, options.mipmaps);
+
+	Ok(texture)
+}
+
+// How each image is uploaded as a layer, as long as they all have the same
+// size and internal format.
+fn array_layers(
+	images: &[Image],
+	options: &TextureOptions,
+) -> std::io::Result<std::vec::Vec<Transfer>> {
+	let first = match images.first() {
+		Some(first) => first,
+		None => {
+			return Err(decode_error("texture arrays need at least one image"))
+		}
+	};
+	let layers: std::vec::Vec<Transfer> = images
+		.iter()
+		.map(|img| Transfer::new(img, options))
+		.collect();
+	let matching = images.iter().zip(layers.iter()).all(|(img, layer)| {
+		img.width == first.width
+			&& img.height == first.height
+			&& layer.internal_format == layers[0].internal_format
+	});
+	if !matching {
+		return Err(decode_error(
+			"texture array layers differ in size or format",
+		));
+	}
+	Ok(layers)
+}
+
+// Sets wrapping and filtering on the texture bound to `target`, falling back
+// to plain filters unless it has mipmaps.
+pub fn apply_sampling(
+	target: gl::types::GLenum,
+	options: &TextureOptions,
+	mipmapped: bool,
+) {
+	let min_filter = if mipmapped {
+		options.min_filter
+	} else {
+		without_mipmaps(options.min_filter)
+	};
+
+	unsafe {
+		gl::TexParameteri(target, gl::TEXTURE_WRAP_S, options.wrap_s as i32);
+		gl::TexParameteri(target, gl::TEXTURE_WRAP_T, options.wrap_t as i32);
+		gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+		gl::TexParameteri(
+			target,
+			gl::TEXTURE_MAG_FILTER,
+			options.mag_filter as i32,
+		);
+
+		if options.anisotropy > 1.0 {
+			let mut max = 0.0;
+			gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+			if max > 1.0 {
+				gl::TexParameterf(
+					target,
+					TEXTURE_MAX_ANISOTROPY,
+					options.anisotropy.min(max),
+				);
+			}
+		}
+	}
+}
+
+// Decoded images are tightly packed, so rows only line up with alignments
+// that divide their size.
+fn row_alignment(row_size: usize) -> i32 {
+	[8, 4, 2]
+		.iter()
+		.copied()
+		.find(|&alignment| row_size.is_multiple_of(alignment as usize))
+		.unwrap_or(1)
+}
+
+fn without_mipmaps(filter: gl::types::GLenum) -> gl::types::GLenum {
+	match filter {
+		gl::NEAREST_MIPMAP_NEAREST | gl::NEAREST_MIPMAP_LINEAR => gl::NEAREST,
+		gl::LINEAR_MIPMAP_NEAREST | gl::LINEAR_MIPMAP_LINEAR => gl::LINEAR,
+		_ => filter,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn level_sizes() {
+		// Whole blocks, partial edges round up, and mips below 4x4 still
+		// take a block.
+		assert_eq!(SurfaceFormat::Bc1.level_size(8, 8), 32);
+		assert_eq!(SurfaceFormat::Bc1.level_size(5, 3), 16);
+		assert_eq!(SurfaceFormat::Bc1.level_size(1, 1), 8);
+		assert_eq!(SurfaceFormat::Bc4.level_size(4, 4), 8);
+		assert_eq!(SurfaceFormat::Bc3.level_size(8, 4), 32);
+		assert_eq!(SurfaceFormat::Bc7.level_size(2, 2), 16);
+		assert_eq!(SurfaceFormat::R8.level_size(3, 5), 15);
+		assert_eq!(SurfaceFormat::Rg8.level_size(3, 5), 30);
+		assert_eq!(SurfaceFormat::Rgba8.level_size(3, 5), 60);
+		assert_eq!(SurfaceFormat::Rgba16F.level_size(2, 2), 32);
+		assert_eq!(SurfaceFormat::Rgba32F.level_size(2, 2), 64);
+		// Shifted past the last level, a side is still one pixel.
+		assert_eq!(SurfaceFormat::Rgba8.level_size(4, 0), 16);
+		assert_eq!(SurfaceFormat::Rgba8.level_size(0, 0), 4);
+	}
+
+	#[test]
+	fn huge_levels_saturate() {
+		let max = u32::MAX;
+		assert_eq!(SurfaceFormat::Rgba32F.level_size(max, max), usize::MAX);
+		assert_eq!(SurfaceFormat::Bc7.level_size(max, max), usize::MAX);
+	}
+
+	fn image(channels: usize, pixels: Pixels) -> Image {
+		Image {
+			width: 1,
+			height: 1,
+			channels,
+			pixels,
+		}
+	}
+
+	fn srgb() -> TextureOptions {
+		TextureOptions {
+			color_space: ColorSpace::Srgb,
+			..TextureOptions::default()
+		}
+	}
+
+	#[test]
+	fn srgb_picks_srgb_formats() {
+		let internal_format = |channels: usize, options: &TextureOptions| {
+			let img = image(channels, Pixels::U8(vec![0; channels]));
+			Transfer::new(&img, options).internal_format
+		};
+		assert_eq!(internal_format(3, &srgb()), gl::SRGB8);
+		assert_eq!(internal_format(4, &srgb()), gl::SRGB8_ALPHA8);
+		assert_eq!(internal_format(3, &TextureOptions::default()), gl::RGB);
+		assert_eq!(internal_format(4, &TextureOptions::default()), gl::RGBA);
+		// There are no single or two channel sRGB formats.
+		assert_eq!(internal_format(1, &srgb()), gl::RED);
+		assert_eq!(internal_format(2, &srgb()), gl::RG);
+		// Only 8-bit images are gamma encoded.
+		let img = image(3, Pixels::U16(vec![0; 3]));
+		assert_eq!(Transfer::new(&img, &srgb()).internal_format, gl::RGB16);
+
+		assert_eq!(
+			SurfaceFormat::Bc1.gl_format(true).0,
+			COMPRESSED_SRGB_S3TC_DXT1
+		);
+		assert_eq!(
+			SurfaceFormat::Bc3.gl_format(true).0,
+			COMPRESSED_SRGB_ALPHA_S3TC_DXT5
+		);
+		assert_eq!(
+			SurfaceFormat::Bc7.gl_format(true).0,
+			gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+		);
+		assert_eq!(SurfaceFormat::Rgba8.gl_format(true).0, gl::SRGB8_ALPHA8);
+		assert_eq!(SurfaceFormat::Rgba8.gl_format(false).0, gl::RGBA8);
+		assert_eq!(
+			SurfaceFormat::Bc4.gl_format(true).0,
+			gl::COMPRESSED_RED_RGTC1
+		);
+	}
+
+	#[test]
+	fn only_colour_roles_keep_srgb() {
+		for role in ["diffuse", "ambient", "emissive"].iter() {
+			assert_eq!(srgb().for_role(role).color_space, ColorSpace::Srgb);
+		}
+		for role in ["specular", "normal", "height"].iter() {
+			assert_eq!(srgb().for_role(role).color_space, ColorSpace::Linear);
+		}
+		let linear = TextureOptions::default().for_role("diffuse");
+		assert_eq!(linear.color_space, ColorSpace::Linear);
+	}
+
+	#[test]
+	fn array_layers_need_matching_images() {
+		let rgb = |width| Image {
+			width,
+			height: 1,
+			channels: 3,
+			pixels: Pixels::U8(vec![0; width as usize * 3]),
+		};
+		let options = TextureOptions::default();
+		let layers = array_layers(&[rgb(2), rgb(2), rgb(2)], &options).unwrap();
+		assert_eq!(layers.len(), 3);
+		assert!(layers.iter().all(|layer| layer.internal_format == gl::RGB));
+
+		assert!(array_layers(&[], &options).is_err());
+		assert!(array_layers(&[rgb(2), rgb(3)], &options).is_err());
+		let gray = image(1, Pixels::U8(vec![0]));
+		assert!(array_layers(&[rgb(1), gray], &options).is_err());
+		// Same channels at another precision is another format too.
+		let deep = image(3, Pixels::U16(vec![0; 3]));
+		assert!(array_layers(&[rgb(1), deep], &options).is_err());
+	}
+}
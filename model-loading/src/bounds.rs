@@ -0,0 +1,283 @@
+use cgmath::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+	pub min: cgmath::Point3<f32>,
+	pub max: cgmath::Point3<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sphere {
+	pub center: cgmath::Point3<f32>,
+	pub radius: f32,
+}
+
+// Six planes as (normal, distance) packed in a Vector4, normals pointing into
+// the frustum.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+	pub planes: [cgmath::Vector4<f32>; 6],
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+	pub drawn: usize,
+	pub culled: usize,
+}
+
+impl Default for Aabb {
+	fn default() -> Self {
+		Aabb {
+			min: cgmath::point3(f32::MAX, f32::MAX, f32::MAX),
+			max: cgmath::point3(f32::MIN, f32::MIN, f32::MIN),
+		}
+	}
+}
+
+impl Default for Sphere {
+	fn default() -> Self {
+		Sphere {
+			center: cgmath::point3(0.0, 0.0, 0.0),
+			radius: 0.0,
+		}
+	}
+}
+
+impl Aabb {
+	pub fn from_points<I>(points: I) -> Aabb
+	where
+		I: IntoIterator<Item = cgmath::Point3<f32>>,
+	{
+		let mut aabb = Aabb::default();
+		for p in points {
+			aabb.min = cgmath::point3(
+				aabb.min.x.min(p.x),
+				aabb.min.y.min(p.y),
+				aabb.min.z.min(p.z),
+			);
+			aabb.max = cgmath::point3(
+				aabb.max.x.max(p.x),
+				aabb.max.y.max(p.y),
+				aabb.max.z.max(p.z),
+			);
+		}
+		aabb
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.min.x > self.max.x
+	}
+
+	pub fn center(&self) -> cgmath::Point3<f32> {
+		self.min.midpoint(self.max)
+	}
+}
+
+impl Sphere {
+	// Centred on the bounding box rather than the optimal centre, which is
+	// within a few percent for the meshes this is used on and much simpler.
+	// multiple-lights only bounds its cubes by hand.
+	#[allow(dead_code)]
+	pub fn from_points<I>(points: I) -> Sphere
+	where
+		I: IntoIterator<Item = cgmath::Point3<f32>> + Clone,
+	{
+		let aabb = Aabb::from_points(points.clone());
+		if aabb.is_empty() {
+			return Sphere::default();
+		}
+		let center = aabb.center();
+		let radius = points
+			.into_iter()
+			.map(|p| p.distance2(center))
+			.fold(0.0, f32::max)
+			.sqrt();
+		Sphere { center, radius }
+	}
+}
+
+impl Frustum {
+	// Gribb & Hartmann's plane extraction. Passing projection * view gives
+	// world space planes, projection * view * model gives them in the
+	// model's own space so its bounds can be tested untransformed.
+	pub fn from_matrix(matrix: &cgmath::Matrix4<f32>) -> Frustum {
+		let row = |i: usize| {
+			cgmath::vec4(matrix.x[i], matrix.y[i], matrix.z[i], matrix.w[i])
+		};
+		let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+		let normalize = |plane: cgmath::Vector4<f32>| {
+			let length = plane.truncate().magnitude();
+			if length > 0.0 {
+				plane / length
+			} else {
+				plane
+			}
+		};
+
+		Frustum {
+			planes: [
+				normalize(r3 + r0),
+				normalize(r3 - r0),
+				normalize(r3 + r1),
+				normalize(r3 - r1),
+				normalize(r3 + r2),
+				normalize(r3 - r2),
+			],
+		}
+	}
+
+	pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+		self.planes.iter().all(|plane| {
+			plane.truncate().dot(sphere.center.to_vec()) + plane.w
+				>= -sphere.radius
+		})
+	}
+
+	// Conservative: boxes near a frustum corner can pass while being outside,
+	// but a box that fails is never visible. multiple-lights only culls
+	// spheres.
+	#[allow(dead_code)]
+	pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+		if aabb.is_empty() {
+			return false;
+		}
+		self.planes.iter().all(|plane| {
+			let positive = cgmath::vec3(
+				if plane.x >= 0.0 {
+					aabb.max.x
+				} else {
+					aabb.min.x
+				},
+				if plane.y >= 0.0 {
+					aabb.max.y
+				} else {
+					aabb.min.y
+				},
+				if plane.z >= 0.0 {
+					aabb.max.z
+				} else {
+					aabb.min.z
+				},
+			);
+			plane.truncate().dot(positive) + plane.w >= 0.0
+		})
+	}
+}
+
+impl std::ops::AddAssign for CullStats {
+	fn add_assign(&mut self, other: CullStats) {
+		self.drawn += other.drawn;
+		self.culled += other.culled;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Looking down -z from (0, 0, 5) with a 90 degree field of view.
+	fn frustum() -> Frustum {
+		let projection =
+			cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, 100.0);
+		let view = cgmath::Matrix4::look_at_rh(
+			cgmath::point3(0.0, 0.0, 5.0),
+			cgmath::point3(0.0, 0.0, 0.0),
+			cgmath::vec3(0.0, 1.0, 0.0),
+		);
+		Frustum::from_matrix(&(projection * view))
+	}
+
+	fn signed_distance(plane: cgmath::Vector4<f32>, p: [f32; 3]) -> f32 {
+		plane.truncate().dot(cgmath::Vector3::from(p)) + plane.w
+	}
+
+	fn cube(center: [f32; 3], half: f32) -> Aabb {
+		let c = cgmath::Point3::from(center);
+		Aabb::from_points(vec![
+			c - cgmath::vec3(half, half, half),
+			c + cgmath::vec3(half, half, half),
+		])
+	}
+
+	#[test]
+	fn planes_are_extracted_facing_inwards() {
+		let frustum = frustum();
+		for plane in frustum.planes.iter() {
+			assert!((plane.truncate().magnitude() - 1.0).abs() < 1e-5);
+			// The middle of the view is inside every plane.
+			assert!(signed_distance(*plane, [0.0, 0.0, 0.0]) > 0.0);
+		}
+		let [left, right, bottom, top, near, far] = frustum.planes;
+		let h = std::f32::consts::FRAC_1_SQRT_2;
+		assert!(
+			(left.truncate() - cgmath::vec3(h, 0.0, -h)).magnitude() < 1e-5
+		);
+		assert!(
+			(right.truncate() - cgmath::vec3(-h, 0.0, -h)).magnitude() < 1e-5
+		);
+		assert!(
+			(bottom.truncate() - cgmath::vec3(0.0, h, -h)).magnitude() < 1e-5
+		);
+		assert!(
+			(top.truncate() - cgmath::vec3(0.0, -h, -h)).magnitude() < 1e-5
+		);
+		assert!(signed_distance(near, [0.0, 0.0, 4.9]).abs() < 1e-4);
+		assert!(signed_distance(far, [0.0, 0.0, -95.0]).abs() < 1e-2);
+	}
+
+	#[test]
+	fn boxes_inside_outside_and_straddling() {
+		let frustum = frustum();
+		assert!(frustum.intersects_aabb(&cube([0.0, 0.0, 0.0], 1.0)));
+		assert!(!frustum.intersects_aabb(&cube([20.0, 0.0, 0.0], 1.0)));
+		assert!(!frustum.intersects_aabb(&cube([0.0, 0.0, -200.0], 1.0)));
+		// Crossing the left plane, which is at x = -5 at the origin's depth.
+		assert!(frustum.intersects_aabb(&cube([-5.5, 0.0, 0.0], 1.0)));
+		// Crossing the far plane.
+		assert!(frustum.intersects_aabb(&cube([0.0, 0.0, -95.0], 1.0)));
+		assert!(!frustum.intersects_aabb(&Aabb::default()));
+	}
+
+	#[test]
+	fn boxes_behind_the_camera_are_culled() {
+		let frustum = frustum();
+		// Inside the side planes' backwards extension, but behind the eye.
+		assert!(!frustum.intersects_aabb(&cube([0.0, 0.0, 10.0], 1.0)));
+		assert!(!frustum.intersects_aabb(&cube([0.0, 0.0, 5.05], 0.01)));
+		assert!(!frustum.intersects_sphere(&Sphere {
+			center: cgmath::point3(0.0, 0.0, 10.0),
+			radius: 1.0,
+		}));
+	}
+
+	#[test]
+	fn spheres_inside_outside_and_straddling() {
+		let frustum = frustum();
+		let sphere = |center: [f32; 3], radius| Sphere {
+			center: cgmath::Point3::from(center),
+			radius,
+		};
+		assert!(frustum.intersects_sphere(&sphere([0.0, 0.0, 0.0], 1.0)));
+		assert!(!frustum.intersects_sphere(&sphere([20.0, 0.0, 0.0], 1.0)));
+		assert!(frustum.intersects_sphere(&sphere([0.0, 5.5, 0.0], 1.0)));
+	}
+
+	#[test]
+	fn bounds_from_points() {
+		let points = vec![
+			cgmath::point3(-1.0, 0.0, 2.0),
+			cgmath::point3(3.0, -2.0, 0.0),
+			cgmath::point3(1.0, 4.0, 1.0),
+		];
+		let aabb = Aabb::from_points(points.clone());
+		assert_eq!(aabb.min, cgmath::point3(-1.0, -2.0, 0.0));
+		assert_eq!(aabb.max, cgmath::point3(3.0, 4.0, 2.0));
+		assert_eq!(aabb.center(), cgmath::point3(1.0, 1.0, 1.0));
+		let sphere = Sphere::from_points(points.clone());
+		assert_eq!(sphere.center, aabb.center());
+		for p in points {
+			assert!(p.distance(sphere.center) <= sphere.radius + 1e-6);
+		}
+		assert!(Aabb::from_points(std::vec::Vec::new()).is_empty());
+	}
+}
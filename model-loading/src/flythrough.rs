@@ -0,0 +1,474 @@
+use crate::camera::{Camera, CameraMode};
+use cgmath::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+	pub position: cgmath::Point3<f32>,
+	pub orientation: cgmath::Quaternion<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spline {
+	// Through every keyframe.
+	CatmullRom,
+	// One curve with the keyframes as control points, only through the
+	// first and last. Smooths out a shaky set of keyframes.
+	Bezier,
+}
+
+// Points along the curve per keyframe for measuring its length.
+const SAMPLES: usize = 32;
+
+// A curve through the keyframes travelled at constant speed: positions
+// follow the spline, orientations turn keyframe to keyframe by slerp.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+	keyframes: std::vec::Vec<Keyframe>,
+	spline: Spline,
+	// Distance along the curve and the point there at evenly spaced spline
+	// parameters, measured when the keyframes change.
+	lengths: std::vec::Vec<f32>,
+	points: std::vec::Vec<cgmath::Point3<f32>>,
+}
+
+impl CameraPath {
+	pub fn new(
+		keyframes: std::vec::Vec<Keyframe>,
+		spline: Spline,
+	) -> CameraPath {
+		let mut path = CameraPath {
+			keyframes,
+			spline,
+			lengths: std::vec::Vec::new(),
+			points: std::vec::Vec::new(),
+		};
+		path.measure();
+		path
+	}
+
+	pub fn keyframes(&self) -> &[Keyframe] {
+		&self.keyframes
+	}
+
+	pub fn push(&mut self, keyframe: Keyframe) {
+		self.keyframes.push(keyframe);
+		self.measure();
+	}
+
+	pub fn clear(&mut self) {
+		self.keyframes.clear();
+		self.measure();
+	}
+
+	pub fn length(&self) -> f32 {
+		self.lengths.last().copied().unwrap_or(0.0)
+	}
+
+	fn measure(&mut self) {
+		self.lengths.clear();
+		self.points.clear();
+		if self.keyframes.is_empty() {
+			return;
+		}
+		let steps = (self.keyframes.len() - 1) * SAMPLES;
+		let mut scratch = std::vec::Vec::with_capacity(self.keyframes.len());
+		let mut length = 0.0;
+		for step in 0..=steps {
+			let point =
+				self.position(step as f32 / SAMPLES as f32, &mut scratch);
+			if let Some(&previous) = self.points.last() {
+				length += point.distance(previous);
+			}
+			self.lengths.push(length);
+			self.points.push(point);
+		}
+	}
+
+	// Where the camera is `distance` along the curve, clamped to its ends.
+	pub fn sample(&self, distance: f32) -> Option<Keyframe> {
+		if self.keyframes.is_empty() {
+			return None;
+		}
+		let distance = distance.clamp(0.0, self.length());
+		// The last sample not past the distance, then part way to the next.
+		let i = self
+			.lengths
+			.iter()
+			.position(|&length| length > distance)
+			.unwrap_or(self.lengths.len())
+			.saturating_sub(1)
+			.min(self.lengths.len().saturating_sub(2));
+		let part = match self.lengths.get(i + 1) {
+			Some(&next) if next > self.lengths[i] => {
+				(distance - self.lengths[i]) / (next - self.lengths[i])
+			}
+			_ => 0.0,
+		};
+		let position = match self.points.get(i + 1) {
+			Some(&next) => self.points[i] + (next - self.points[i]) * part,
+			None => self.points[i],
+		};
+		Some(Keyframe {
+			position,
+			orientation: self.orientation((i as f32 + part) / SAMPLES as f32),
+		})
+	}
+
+	// `u` runs from 0 at the first keyframe to one less than the number of
+	// keyframes at the last. Bezier curves work in `scratch`.
+	fn position(
+		&self,
+		u: f32,
+		scratch: &mut std::vec::Vec<cgmath::Vector3<f32>>,
+	) -> cgmath::Point3<f32> {
+		if self.keyframes.len() == 1 {
+			return self.keyframes[0].position;
+		}
+		let keyframe = |i: usize| self.keyframes[i].position.to_vec();
+		let last = self.keyframes.len() - 1;
+		let point = match self.spline {
+			Spline::CatmullRom => {
+				let (i, t) = segment(u, last);
+				// The ends repeat their keyframe.
+				let p0 = keyframe(i.saturating_sub(1));
+				let p1 = keyframe(i);
+				let p2 = keyframe(i + 1);
+				let p3 = keyframe((i + 2).min(last));
+				(p1 * 2.0
+					+ (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3)
+					* t * t + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t * t * t)
+					* 0.5
+			}
+			Spline::Bezier => {
+				let t = (u / last as f32).clamp(0.0, 1.0);
+				scratch.clear();
+				scratch.extend((0..=last).map(keyframe));
+				// de Casteljau.
+				for n in (1..scratch.len()).rev() {
+					for j in 0..n {
+						scratch[j] = scratch[j].lerp(scratch[j + 1], t);
+					}
+				}
+				scratch[0]
+			}
+		};
+		cgmath::Point3::from_vec(point)
+	}
+
+	fn orientation(&self, u: f32) -> cgmath::Quaternion<f32> {
+		if self.keyframes.len() == 1 {
+			return self.keyframes[0].orientation;
+		}
+		let (i, t) = segment(u, self.keyframes.len() - 1);
+		let from = self.keyframes[i].orientation;
+		let mut to = self.keyframes[i + 1].orientation;
+		// q and -q are the same turn, the other one would go the long way.
+		if from.dot(to) < 0.0 {
+			to = -to;
+		}
+		from.slerp(to, t).normalize()
+	}
+
+	// One keyframe per line as position then orientation, after a line
+	// naming the spline:
+	//
+	//     spline catmull_rom
+	//     keyframe 0.0 1.5 6.0 1.0 0.0 0.0 0.0
+	pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+		let mut text = format!(
+			"spline {}\n",
+			match self.spline {
+				Spline::CatmullRom => "catmull_rom",
+				Spline::Bezier => "bezier",
+			}
+		);
+		for keyframe in self.keyframes.iter() {
+			let p = keyframe.position;
+			let q = keyframe.orientation;
+			text += &format!(
+				"keyframe {:?} {:?} {:?} {:?} {:?} {:?} {:?}\n",
+				p.x, p.y, p.z, q.s, q.v.x, q.v.y, q.v.z
+			);
+		}
+		std::fs::write(path, text)
+	}
+
+	pub fn load(path: &std::path::Path) -> std::io::Result<CameraPath> {
+		parse_camera_path(&std::fs::read_to_string(path)?)
+	}
+}
+
+// The keyframe before `u` and how far past it, up to the last segment.
+fn segment(u: f32, last: usize) -> (usize, f32) {
+	let i = (u.max(0.0).floor() as usize).min(last - 1);
+	(i, (u - i as f32).clamp(0.0, 1.0))
+}
+
+fn invalid(line: usize, message: &str) -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::InvalidData,
+		format!("line {}: {}", line, message),
+	)
+}
+
+pub fn parse_camera_path(text: &str) -> std::io::Result<CameraPath> {
+	let mut spline = Spline::CatmullRom;
+	let mut keyframes = std::vec::Vec::new();
+	for (number, line) in text.lines().enumerate() {
+		let number = number + 1;
+		let words: std::vec::Vec<&str> = line.split_whitespace().collect();
+		match words.first() {
+			None => {}
+			Some(&"spline") => {
+				spline = match words.get(1) {
+					Some(&"catmull_rom") => Spline::CatmullRom,
+					Some(&"bezier") => Spline::Bezier,
+					_ => return Err(invalid(number, "unknown spline")),
+				};
+			}
+			Some(&"keyframe") => {
+				let values = words[1..]
+					.iter()
+					.map(|word| word.parse::<f32>())
+					.collect::<Result<std::vec::Vec<f32>, _>>()
+					.map_err(|_| invalid(number, "bad number"))?;
+				if values.len() != 7 {
+					return Err(invalid(number, "expected 7 numbers"));
+				}
+				keyframes.push(Keyframe {
+					position: cgmath::point3(values[0], values[1], values[2]),
+					orientation: cgmath::Quaternion::new(
+						values[3], values[4], values[5], values[6],
+					)
+					.normalize(),
+				});
+			}
+			Some(_) => return Err(invalid(number, "unknown line")),
+		}
+	}
+	Ok(CameraPath::new(keyframes, spline))
+}
+
+// Keyframes captured from the camera, saved as they're added, and played
+// back by flying the camera along them.
+pub struct Flythrough {
+	pub path: CameraPath,
+	// Units per second along the curve.
+	pub speed: f32,
+	file: std::path::PathBuf,
+	// How far along, and the mode to go back to afterwards.
+	playing: Option<(f32, CameraMode)>,
+}
+
+impl Flythrough {
+	// Starts from the keyframes saved in the file if there are any.
+	pub fn load_or_default(file: &str) -> Flythrough {
+		let file = std::path::PathBuf::from(file);
+		let path = if file.exists() {
+			CameraPath::load(&file).unwrap_or_else(|err| {
+				eprintln!("Ignoring {}: {}", file.display(), err);
+				CameraPath::new(std::vec::Vec::new(), Spline::CatmullRom)
+			})
+		} else {
+			CameraPath::new(std::vec::Vec::new(), Spline::CatmullRom)
+		};
+		Flythrough {
+			path,
+			speed: 1.5,
+			file,
+			playing: None,
+		}
+	}
+
+	fn save(&self) {
+		if let Err(err) = self.path.save(&self.file) {
+			eprintln!("Failed to save {}: {}", self.file.display(), err);
+		}
+	}
+
+	pub fn add_keyframe(&mut self, camera: &Camera) {
+		self.path.push(Keyframe {
+			position: camera.position,
+			orientation: camera.orientation,
+		});
+		self.save();
+	}
+
+	pub fn clear(&mut self) {
+		self.path.clear();
+		self.save();
+	}
+
+	pub fn playing(&self) -> bool {
+		self.playing.is_some()
+	}
+
+	// Plays from the start, or stops. The camera flies while playing so
+	// it can take any orientation.
+	pub fn toggle(&mut self, camera: &mut Camera) {
+		match self.playing.take() {
+			Some((_, mode)) => camera.set_mode(mode),
+			None if self.path.keyframes().len() >= 2 => {
+				self.playing = Some((0.0, camera.mode));
+				camera.set_mode(CameraMode::Fly);
+				self.update(camera, 0.0);
+			}
+			None => {}
+		}
+	}
+
+	// Once a frame instead of the camera's own input while playing.
+	pub fn update(&mut self, camera: &mut Camera, delta_time: f32) {
+		let (distance, mode) = match self.playing {
+			Some((distance, mode)) => {
+				(distance + self.speed * delta_time, mode)
+			}
+			None => return,
+		};
+		if let Some(keyframe) = self.path.sample(distance) {
+			camera.position = keyframe.position;
+			camera.orientation = keyframe.orientation;
+		}
+		if distance >= self.path.length() {
+			self.playing = None;
+			camera.set_mode(mode);
+		} else {
+			self.playing = Some((distance, mode));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn keyframe(x: f32, y: f32, z: f32, yaw: f32) -> Keyframe {
+		Keyframe {
+			position: cgmath::point3(x, y, z),
+			orientation: cgmath::Quaternion::from_angle_y(cgmath::Deg(yaw)),
+		}
+	}
+
+	// Bunched up at the start, far apart at the end.
+	fn path(spline: Spline) -> CameraPath {
+		CameraPath::new(
+			vec![
+				keyframe(0.0, 0.0, 0.0, 0.0),
+				keyframe(1.0, 0.5, 0.0, 30.0),
+				keyframe(2.0, 0.0, 1.0, 60.0),
+				keyframe(12.0, 2.0, -3.0, 90.0),
+			],
+			spline,
+		)
+	}
+
+	fn assert_close(a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) {
+		assert!(a.distance(b) < 1e-4, "{:?} != {:?}", a, b);
+	}
+
+	#[test]
+	fn catmull_rom_runs_through_every_keyframe() {
+		let path = path(Spline::CatmullRom);
+		for (i, keyframe) in path.keyframes().iter().enumerate() {
+			assert_close(path.points[i * SAMPLES], keyframe.position);
+		}
+		let start = path.sample(-1.0).unwrap();
+		assert_close(start.position, path.keyframes()[0].position);
+		let end = path.sample(path.length() + 1.0).unwrap();
+		assert_close(end.position, path.keyframes()[3].position);
+		assert!(
+			(end.orientation.dot(path.keyframes()[3].orientation) - 1.0).abs()
+				< 1e-5
+		);
+	}
+
+	#[test]
+	fn bezier_runs_through_the_ends() {
+		let path = path(Spline::Bezier);
+		assert_close(
+			path.sample(0.0).unwrap().position,
+			path.keyframes()[0].position,
+		);
+		assert_close(
+			path.sample(path.length()).unwrap().position,
+			path.keyframes()[3].position,
+		);
+		assert!(
+			path.points[SAMPLES].distance(path.keyframes()[1].position) > 0.1
+		);
+	}
+
+	#[test]
+	fn speed_is_constant() {
+		for &spline in &[Spline::CatmullRom, Spline::Bezier] {
+			let path = path(spline);
+			let steps = 1000;
+			let step = path.length() / steps as f32;
+			let mut previous = path.sample(0.0).unwrap().position;
+			for i in 1..=steps {
+				let point = path.sample(i as f32 * step).unwrap().position;
+				// Chords are a little shorter around the tightest bends,
+				// stepping the spline parameter evenly would be several
+				// times out.
+				let moved = point.distance(previous);
+				assert!(
+					(moved - step).abs() < step * 0.02,
+					"{} != {}",
+					moved,
+					step
+				);
+				previous = point;
+			}
+		}
+	}
+
+	#[test]
+	fn turns_take_the_short_way() {
+		let mut to = keyframe(1.0, 0.0, 0.0, 20.0);
+		// The same turn, written the other way round.
+		to.orientation = -to.orientation;
+		let path = CameraPath::new(
+			vec![keyframe(0.0, 0.0, 0.0, 0.0), to],
+			Spline::CatmullRom,
+		);
+		let middle = path.sample(path.length() / 2.0).unwrap().orientation;
+		let expected = cgmath::Quaternion::from_angle_y(cgmath::Deg(10.0));
+		assert!((middle.dot(expected).abs() - 1.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn empty_and_single_keyframe_paths() {
+		let mut path = CameraPath::new(vec![], Spline::CatmullRom);
+		assert_eq!(path.sample(1.0), None);
+		assert_eq!(path.length(), 0.0);
+		path.push(keyframe(1.0, 2.0, 3.0, 45.0));
+		assert_eq!(path.sample(1.0), Some(keyframe(1.0, 2.0, 3.0, 45.0)));
+		path.clear();
+		assert_eq!(path.sample(0.0), None);
+	}
+
+	#[test]
+	fn saved_paths_load_back() {
+		let path = path(Spline::Bezier);
+		let file = std::env::temp_dir()
+			.join(format!("flythrough-test-{}.txt", std::process::id()));
+		path.save(&file).unwrap();
+		let loaded = CameraPath::load(&file).unwrap();
+		std::fs::remove_file(&file).unwrap();
+		assert_eq!(loaded.spline, Spline::Bezier);
+		for (loaded, saved) in loaded.keyframes().iter().zip(path.keyframes()) {
+			assert_eq!(loaded.position, saved.position);
+			// Normalised again on the way in.
+			assert!(
+				(loaded.orientation.dot(saved.orientation) - 1.0).abs() < 1e-6
+			);
+		}
+		assert_eq!(loaded.keyframes().len(), 4);
+
+		let error = |text| parse_camera_path(text).err().unwrap().to_string();
+		assert_eq!(error("spline hermite"), "line 1: unknown spline");
+		assert_eq!(error("\nkeyframe 1 2 3"), "line 2: expected 7 numbers");
+		assert_eq!(error("keyframe 1 2 3 x 0 0 0"), "line 1: bad number");
+		assert_eq!(error("camera 1"), "line 1: unknown line");
+	}
+}
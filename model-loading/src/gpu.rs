@@ -0,0 +1,169 @@
+// Owning handles for OpenGL objects. Each deletes its object when dropped,
+// so it has to go before the context does, and none of them are `Clone`,
+// share one through an `Rc` instead.
+
+pub struct VertexArray {
+	id: u32,
+}
+
+pub struct Buffer {
+	id: u32,
+}
+
+pub struct Texture2D {
+	id: u32,
+}
+
+pub struct Texture2DArray {
+	id: u32,
+}
+
+impl VertexArray {
+	pub fn new() -> VertexArray {
+		let mut id: u32 = 0;
+		unsafe {
+			gl::GenVertexArrays(1, &mut id);
+		}
+		track("vertex array", id);
+		VertexArray { id }
+	}
+
+	pub fn bind(&self) {
+		unsafe {
+			gl::BindVertexArray(self.id);
+		}
+	}
+}
+
+impl Drop for VertexArray {
+	fn drop(&mut self) {
+		untrack("vertex array", self.id);
+		unsafe {
+			gl::DeleteVertexArrays(1, &self.id);
+		}
+	}
+}
+
+impl Buffer {
+	pub fn new() -> Buffer {
+		let mut id: u32 = 0;
+		unsafe {
+			gl::GenBuffers(1, &mut id);
+		}
+		track("buffer", id);
+		Buffer { id }
+	}
+
+	pub fn bind(&self, target: gl::types::GLenum) {
+		unsafe {
+			gl::BindBuffer(target, self.id);
+		}
+	}
+}
+
+impl Drop for Buffer {
+	fn drop(&mut self) {
+		untrack("buffer", self.id);
+		unsafe {
+			gl::DeleteBuffers(1, &self.id);
+		}
+	}
+}
+
+impl Texture2D {
+	pub fn new() -> Texture2D {
+		let mut id: u32 = 0;
+		unsafe {
+			gl::GenTextures(1, &mut id);
+		}
+		track("texture", id);
+		Texture2D { id }
+	}
+
+	pub fn bind(&self) {
+		unsafe {
+			gl::BindTexture(gl::TEXTURE_2D, self.id);
+		}
+	}
+}
+
+impl Drop for Texture2D {
+	fn drop(&mut self) {
+		untrack("texture", self.id);
+		unsafe {
+			gl::DeleteTextures(1, &self.id);
+		}
+	}
+}
+
+impl Texture2DArray {
+	pub fn new() -> Texture2DArray {
+		let mut id: u32 = 0;
+		unsafe {
+			gl::GenTextures(1, &mut id);
+		}
+		track("texture array", id);
+		Texture2DArray { id }
+	}
+
+	pub fn bind(&self) {
+		unsafe {
+			gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+		}
+	}
+}
+
+impl Drop for Texture2DArray {
+	fn drop(&mut self) {
+		untrack("texture array", self.id);
+		unsafe {
+			gl::DeleteTextures(1, &self.id);
+		}
+	}
+}
+
+// Debug builds remember every live object so `report_leaks` can list the
+// ones nobody dropped. GL calls only happen on the context's thread, so a
+// thread local is enough.
+#[cfg(debug_assertions)]
+thread_local! {
+	static LIVE: std::cell::RefCell<std::vec::Vec<(&'static str, u32)>> =
+		const { std::cell::RefCell::new(std::vec::Vec::new()) };
+}
+
+#[cfg(debug_assertions)]
+fn track(kind: &'static str, id: u32) {
+	LIVE.with(|live| live.borrow_mut().push((kind, id)));
+}
+
+#[cfg(debug_assertions)]
+fn untrack(kind: &'static str, id: u32) {
+	LIVE.with(|live| {
+		let mut live = live.borrow_mut();
+		if let Some(i) = live.iter().position(|&object| object == (kind, id)) {
+			live.swap_remove(i);
+		}
+	});
+}
+
+#[cfg(not(debug_assertions))]
+fn track(_kind: &'static str, _id: u32) {}
+
+#[cfg(not(debug_assertions))]
+fn untrack(_kind: &'static str, _id: u32) {}
+
+// Call right before the context goes away, once everything that should have
+// been released is dropped. Does nothing in release builds.
+pub fn report_leaks() {
+	#[cfg(debug_assertions)]
+	LIVE.with(|live| {
+		let live = live.borrow();
+		if live.is_empty() {
+			return;
+		}
+		eprintln!("{} OpenGL objects still alive at shutdown:", live.len());
+		for (kind, id) in live.iter() {
+			eprintln!("  {} {}", kind, id);
+		}
+	});
+}
@@ -0,0 +1,251 @@
+use crate::texture::{Surface, SurfaceFormat};
+use std::io::Read;
+use std::path::Path;
+
+const IDENTIFIER: [u8; 12] = [
+	0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+// Identifier, header and index, after which comes the level index.
+const LEVEL_INDEX_OFFSET: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn invalid(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+	let mut word = [0; 4];
+	word.copy_from_slice(&bytes[offset..offset + 4]);
+	u32::from_le_bytes(word)
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+	let mut word = [0; 8];
+	word.copy_from_slice(&bytes[offset..offset + 8]);
+	u64::from_le_bytes(word)
+}
+
+pub fn load_ktx2(path: &Path) -> std::io::Result<Surface> {
+	let mut bytes = std::vec::Vec::new();
+	std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+	parse_ktx2(&bytes)
+}
+
+// Single 2D textures with their mip chain. Cubemaps, arrays, volumes and
+// supercompressed (Basis or Zstandard) data are rejected.
+pub fn parse_ktx2(bytes: &[u8]) -> std::io::Result<Surface> {
+	if bytes.len() < LEVEL_INDEX_OFFSET || bytes[0..12] != IDENTIFIER {
+		return Err(invalid("not a KTX2 file"));
+	}
+
+	let (format, srgb) = vk_format(u32_at(bytes, 12))?;
+	let width = u32_at(bytes, 20);
+	let height = u32_at(bytes, 24);
+	if u32_at(bytes, 28) != 0 || u32_at(bytes, 32) > 1 || u32_at(bytes, 36) != 1
+	{
+		return Err(invalid("KTX2 texture is not a single 2D texture"));
+	}
+	if u32_at(bytes, 44) != 0 {
+		return Err(invalid("supercompressed KTX2 files are not supported"));
+	}
+	// Zero asks the loader to generate mipmaps, there is still one level.
+	let level_count = u32_at(bytes, 40).clamp(1, 32) as usize;
+	if bytes.len() < LEVEL_INDEX_OFFSET + level_count * LEVEL_INDEX_ENTRY_SIZE {
+		return Err(invalid("KTX2 level index is truncated"));
+	}
+
+	let mut levels = std::vec::Vec::with_capacity(level_count);
+	for level in 0..level_count {
+		let entry = LEVEL_INDEX_OFFSET + level * LEVEL_INDEX_ENTRY_SIZE;
+		let offset = u64_at(bytes, entry) as usize;
+		let length = u64_at(bytes, entry + 8) as usize;
+		let size = format.level_size(width >> level, height >> level);
+		if length < size || offset.saturating_add(size) > bytes.len() {
+			return Err(invalid("KTX2 mip level is truncated"));
+		}
+		levels.push(bytes[offset..offset + size].to_vec());
+	}
+
+	Ok(Surface {
+		format,
+		srgb,
+		width,
+		height,
+		levels,
+	})
+}
+
+// KTX2 names formats by their Vulkan `VkFormat` value.
+fn vk_format(format: u32) -> std::io::Result<(SurfaceFormat, bool)> {
+	Ok(match format {
+		9 => (SurfaceFormat::R8, false),
+		16 => (SurfaceFormat::Rg8, false),
+		37 => (SurfaceFormat::Rgba8, false),
+		43 => (SurfaceFormat::Rgba8, true),
+		44 => (SurfaceFormat::Bgra8, false),
+		50 => (SurfaceFormat::Bgra8, true),
+		97 => (SurfaceFormat::Rgba16F, false),
+		109 => (SurfaceFormat::Rgba32F, false),
+		131 => (SurfaceFormat::Bc1, false),
+		132 => (SurfaceFormat::Bc1, true),
+		133 => (SurfaceFormat::Bc1Alpha, false),
+		134 => (SurfaceFormat::Bc1Alpha, true),
+		135 => (SurfaceFormat::Bc2, false),
+		136 => (SurfaceFormat::Bc2, true),
+		137 => (SurfaceFormat::Bc3, false),
+		138 => (SurfaceFormat::Bc3, true),
+		139 => (SurfaceFormat::Bc4, false),
+		140 => (SurfaceFormat::Bc4Signed, false),
+		141 => (SurfaceFormat::Bc5, false),
+		142 => (SurfaceFormat::Bc5Signed, false),
+		143 => (SurfaceFormat::Bc6hUnsigned, false),
+		144 => (SurfaceFormat::Bc6hSigned, false),
+		145 => (SurfaceFormat::Bc7, false),
+		146 => (SurfaceFormat::Bc7, true),
+		0 => {
+			return Err(invalid(
+				"KTX2 files without a VkFormat are not supported",
+			))
+		}
+		_ => return Err(invalid("unsupported KTX2 VkFormat")),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn put(bytes: &mut [u8], offset: usize, value: u32) {
+		bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+	}
+
+	// A 2D texture with `levels` as its mip chain, stored smallest first
+	// the way KTX2 writers do, each filled with its level's index.
+	fn ktx2(
+		vk_format: u32,
+		width: u32,
+		height: u32,
+		sizes: &[usize],
+	) -> std::vec::Vec<u8> {
+		let mut bytes = vec![0; LEVEL_INDEX_OFFSET];
+		bytes[0..12].copy_from_slice(&IDENTIFIER);
+		put(&mut bytes, 12, vk_format);
+		put(&mut bytes, 16, 1);
+		put(&mut bytes, 20, width);
+		put(&mut bytes, 24, height);
+		put(&mut bytes, 36, 1);
+		put(&mut bytes, 40, sizes.len() as u32);
+
+		let mut offset =
+			LEVEL_INDEX_OFFSET + sizes.len() * LEVEL_INDEX_ENTRY_SIZE;
+		let mut entries = vec![[0u64; 3]; sizes.len()];
+		for level in (0..sizes.len()).rev() {
+			entries[level] =
+				[offset as u64, sizes[level] as u64, sizes[level] as u64];
+			offset += sizes[level];
+		}
+		for entry in entries.iter() {
+			for value in entry.iter() {
+				bytes.extend_from_slice(&value.to_le_bytes());
+			}
+		}
+		for level in (0..sizes.len()).rev() {
+			bytes.extend(std::iter::repeat_n(level as u8, sizes[level]));
+		}
+		bytes
+	}
+
+	fn error(bytes: &[u8]) -> std::string::String {
+		match parse_ktx2(bytes) {
+			Ok(_) => panic!("parsed an invalid KTX2 file"),
+			Err(err) => {
+				assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+				err.to_string()
+			}
+		}
+	}
+
+	#[test]
+	fn levels_are_found_by_their_index() {
+		let bytes = ktx2(138, 8, 8, &[64, 16, 16, 16]);
+		let surface = parse_ktx2(&bytes).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Bc3);
+		assert!(surface.srgb);
+		assert_eq!((surface.width, surface.height), (8, 8));
+		let sizes: std::vec::Vec<usize> =
+			surface.levels.iter().map(|level| level.len()).collect();
+		assert_eq!(sizes, vec![64, 16, 16, 16]);
+		for (i, level) in surface.levels.iter().enumerate() {
+			assert!(level.iter().all(|&byte| byte == i as u8));
+		}
+	}
+
+	#[test]
+	fn uncompressed_levels() {
+		let surface = parse_ktx2(&ktx2(37, 3, 2, &[24, 4])).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::Rgba8);
+		assert!(!surface.srgb);
+		assert_eq!(surface.levels, vec![vec![0; 24], vec![1; 4]]);
+
+		// A level count of zero still has the top level.
+		let mut bytes = ktx2(9, 4, 4, &[16]);
+		put(&mut bytes, 40, 0);
+		let surface = parse_ktx2(&bytes).unwrap();
+		assert_eq!(surface.format, SurfaceFormat::R8);
+		assert_eq!(surface.levels.len(), 1);
+	}
+
+	#[test]
+	fn malformed_headers() {
+		let good = ktx2(131, 4, 4, &[8]);
+		parse_ktx2(&good).unwrap();
+
+		let mut bytes = good.clone();
+		bytes[1] = b'k';
+		assert_eq!(error(&bytes), "not a KTX2 file");
+		let mut bytes = good.clone();
+		put(&mut bytes, 36, 6);
+		assert_eq!(error(&bytes), "KTX2 texture is not a single 2D texture");
+		let mut bytes = good.clone();
+		put(&mut bytes, 32, 2);
+		assert_eq!(error(&bytes), "KTX2 texture is not a single 2D texture");
+		let mut bytes = good.clone();
+		put(&mut bytes, 44, 1);
+		assert_eq!(
+			error(&bytes),
+			"supercompressed KTX2 files are not supported"
+		);
+		let mut bytes = good.clone();
+		put(&mut bytes, 12, 0);
+		assert_eq!(
+			error(&bytes),
+			"KTX2 files without a VkFormat are not supported"
+		);
+		let mut bytes = good;
+		put(&mut bytes, 12, 1000);
+		assert_eq!(error(&bytes), "unsupported KTX2 VkFormat");
+	}
+
+	#[test]
+	fn truncated_files() {
+		let bytes = ktx2(131, 8, 8, &[32, 8, 8, 8]);
+		for length in 0..bytes.len() {
+			error(&bytes[..length]);
+		}
+
+		// A level shorter than its size, or pointing past the end.
+		let mut bytes = ktx2(131, 4, 4, &[8]);
+		let entry = LEVEL_INDEX_OFFSET;
+		bytes[entry + 8..entry + 16].copy_from_slice(&4u64.to_le_bytes());
+		assert_eq!(error(&bytes), "KTX2 mip level is truncated");
+		let mut bytes = ktx2(131, 4, 4, &[8]);
+		bytes[entry..entry + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+		assert_eq!(error(&bytes), "KTX2 mip level is truncated");
+
+		// Sizes a corrupt header can claim don't overflow.
+		let mut bytes = ktx2(109, 4, 4, &[256]);
+		put(&mut bytes, 20, u32::MAX);
+		put(&mut bytes, 24, u32::MAX);
+		assert_eq!(error(&bytes), "KTX2 mip level is truncated");
+	}
+}
@@ -0,0 +1,821 @@
+use crate::model::Vertex;
+use cgmath::prelude::*;
+
+// Boundary edges get a plane perpendicular to the surface with this weight
+// so the outline of open meshes (like the terrain) survives simplification.
+const BOUNDARY_WEIGHT: f64 = 10.0;
+
+#[derive(Clone, Debug)]
+pub struct LodOptions {
+	// Fraction of the original triangle count each level should aim for.
+	pub ratios: std::vec::Vec<f32>,
+	// Collapses whose quadric error exceeds this, relative to the mesh's
+	// bounding box diagonal, are never made.
+	pub max_error: f32,
+}
+
+impl Default for LodOptions {
+	fn default() -> Self {
+		LodOptions {
+			ratios: vec![0.5, 0.25, 0.125],
+			max_error: 0.02,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+	pub indices: std::vec::Vec<u32>,
+	// Largest distance from an original vertex to the simplified surface, in
+	// model units.
+	pub error: f32,
+}
+
+pub struct LodView {
+	pub model: cgmath::Matrix4<f32>,
+	pub camera_pos: cgmath::Point3<f32>,
+	pub projection_scale: f32,
+	// Orthographic projections don't shrink things with distance.
+	pub orthographic: bool,
+	// Largest on-screen error, in pixels, a level may have to be picked.
+	pub pixel_error: f32,
+}
+
+// Pixels covered by one world unit at distance 1 from the camera, or at any
+// distance for orthographic projections.
+pub fn projection_scale(
+	projection: &cgmath::Matrix4<f32>,
+	viewport_height: f32,
+) -> f32 {
+	projection.y.y * viewport_height * 0.5
+}
+
+// Picks the coarsest level whose error projects to at most
+// `view.pixel_error` pixels. `None` means the full-detail mesh.
+pub fn select_level(
+	errors: &[f32],
+	center: cgmath::Point3<f32>,
+	view: &LodView,
+) -> Option<usize> {
+	let world_center = view.model.transform_point(center);
+	let distance = if view.orthographic {
+		1.0
+	} else {
+		view.camera_pos.distance(world_center).max(1e-4)
+	};
+	let scale = view.model.x.truncate().magnitude();
+
+	let mut selected = None;
+	for (i, &error) in errors.iter().enumerate() {
+		let pixels = error * scale * view.projection_scale / distance;
+		if pixels > view.pixel_error {
+			break;
+		}
+		selected = Some(i);
+	}
+
+	selected
+}
+
+pub fn generate_lods(
+	vertices: &[Vertex],
+	indices: &[u32],
+	options: &LodOptions,
+) -> std::vec::Vec<LodLevel> {
+	let mut simplifier = Simplifier::new(vertices, indices, options.max_error);
+	let triangle_count = indices.len() / 3;
+	let mut levels: std::vec::Vec<LodLevel> = std::vec::Vec::new();
+
+	for &ratio in options.ratios.iter() {
+		let target = (triangle_count as f32 * ratio) as usize;
+		simplifier.collapse_to(target);
+
+		let previous = levels
+			.last()
+			.map(|level| level.indices.len())
+			.unwrap_or(indices.len());
+		if simplifier.live_triangles == 0
+			|| simplifier.live_triangles * 3 >= previous
+		{
+			// Ran into the error bound, coarser levels would be identical.
+			break;
+		}
+
+		let error = levels.last().map(|level| level.error).unwrap_or(0.0);
+		levels.push(LodLevel {
+			indices: simplifier.indices(vertices),
+			error: error.max(simplifier.measure_error() as f32),
+		});
+	}
+
+	levels
+}
+
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+	// Upper triangle of the symmetric 4x4 matrix, row by row.
+	a: [f64; 10],
+	weight: f64,
+}
+
+impl Quadric {
+	fn from_plane(
+		normal: cgmath::Vector3<f64>,
+		point: cgmath::Vector3<f64>,
+		weight: f64,
+	) -> Quadric {
+		let (a, b, c) = (normal.x, normal.y, normal.z);
+		let d = -normal.dot(point);
+		Quadric {
+			a: [
+				a * a * weight,
+				a * b * weight,
+				a * c * weight,
+				a * d * weight,
+				b * b * weight,
+				b * c * weight,
+				b * d * weight,
+				c * c * weight,
+				c * d * weight,
+				d * d * weight,
+			],
+			weight,
+		}
+	}
+
+	fn add(&self, other: &Quadric) -> Quadric {
+		let mut sum = *self;
+		for i in 0..10 {
+			sum.a[i] += other.a[i];
+		}
+		sum.weight += other.weight;
+		sum
+	}
+
+	// Weighted mean squared distance of `p` to the accumulated planes.
+	fn error(&self, p: cgmath::Vector3<f64>) -> f64 {
+		if self.weight <= 0.0 {
+			return 0.0;
+		}
+		let a = &self.a;
+		let (x, y, z) = (p.x, p.y, p.z);
+		let e = a[0] * x * x
+			+ 2.0 * a[1] * x * y
+			+ 2.0 * a[2] * x * z
+			+ 2.0 * a[3] * x
+			+ a[4] * y * y
+			+ 2.0 * a[5] * y * z
+			+ 2.0 * a[6] * y
+			+ a[7] * z * z
+			+ 2.0 * a[8] * z
+			+ a[9];
+
+		(e / self.weight).max(0.0)
+	}
+}
+
+fn point_triangle_distance(
+	p: cgmath::Vector3<f64>,
+	a: cgmath::Vector3<f64>,
+	b: cgmath::Vector3<f64>,
+	c: cgmath::Vector3<f64>,
+) -> f64 {
+	// Closest point on a triangle, from Ericson's Real-Time Collision
+	// Detection, chapter 5.1.5.
+	let (ab, ac, ap) = (b - a, c - a, p - a);
+	let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+	if d1 <= 0.0 && d2 <= 0.0 {
+		return ap.magnitude();
+	}
+
+	let bp = p - b;
+	let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+	if d3 >= 0.0 && d4 <= d3 {
+		return bp.magnitude();
+	}
+
+	let vc = d1 * d4 - d3 * d2;
+	if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+		let v = d1 / (d1 - d3);
+		return (p - (a + ab * v)).magnitude();
+	}
+
+	let cp = p - c;
+	let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+	if d6 >= 0.0 && d5 <= d6 {
+		return cp.magnitude();
+	}
+
+	let vb = d5 * d2 - d1 * d6;
+	if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+		let w = d2 / (d2 - d6);
+		return (p - (a + ac * w)).magnitude();
+	}
+
+	let va = d3 * d6 - d5 * d4;
+	if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+		let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+		return (p - (b + (c - b) * w)).magnitude();
+	}
+
+	let denom = 1.0 / (va + vb + vc);
+	let (v, w) = (vb * denom, vc * denom);
+	(p - (a + ab * v + ac * w)).magnitude()
+}
+
+// Triangles bucketed into a uniform grid with no more cells than triangles,
+// for the nearest triangle to a point.
+struct TriangleGrid {
+	triangles: std::vec::Vec<[cgmath::Vector3<f64>; 3]>,
+	cells: std::vec::Vec<std::vec::Vec<usize>>,
+	min: cgmath::Vector3<f64>,
+	cell_size: f64,
+	size: [usize; 3],
+}
+
+impl TriangleGrid {
+	fn new(
+		triangles: std::vec::Vec<[cgmath::Vector3<f64>; 3]>,
+	) -> TriangleGrid {
+		let mut min = cgmath::vec3(f64::MAX, f64::MAX, f64::MAX);
+		let mut max = cgmath::vec3(f64::MIN, f64::MIN, f64::MIN);
+		for p in triangles.iter().flatten() {
+			min = cgmath::vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+			max = cgmath::vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+		}
+		let extent = max - min;
+		let longest = extent.x.max(extent.y).max(extent.z).max(1e-9);
+		let cell_size =
+			longest / (triangles.len() as f64).cbrt().ceil().max(1.0);
+		let mut size = [1; 3];
+		if !triangles.is_empty() {
+			for axis in 0..3 {
+				size[axis] = (extent[axis] / cell_size) as usize + 1;
+			}
+		}
+
+		let mut grid = TriangleGrid {
+			triangles: std::vec::Vec::new(),
+			cells: vec![std::vec::Vec::new(); size[0] * size[1] * size[2]],
+			min,
+			cell_size,
+			size,
+		};
+		for (t, corners) in triangles.iter().enumerate() {
+			let [a, b, c] = *corners;
+			let low = grid.cell_of(cgmath::vec3(
+				a.x.min(b.x).min(c.x),
+				a.y.min(b.y).min(c.y),
+				a.z.min(b.z).min(c.z),
+			));
+			let high = grid.cell_of(cgmath::vec3(
+				a.x.max(b.x).max(c.x),
+				a.y.max(b.y).max(c.y),
+				a.z.max(b.z).max(c.z),
+			));
+			for z in low[2]..=high[2] {
+				for y in low[1]..=high[1] {
+					for x in low[0]..=high[0] {
+						let cell = grid.index([x, y, z]);
+						grid.cells[cell].push(t);
+					}
+				}
+			}
+		}
+		grid.triangles = triangles;
+		grid
+	}
+
+	fn cell_of(&self, p: cgmath::Vector3<f64>) -> [usize; 3] {
+		let mut cell = [0; 3];
+		for axis in 0..3 {
+			let i = ((p[axis] - self.min[axis]) / self.cell_size).max(0.0);
+			cell[axis] = (i as usize).min(self.size[axis] - 1);
+		}
+		cell
+	}
+
+	fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+		(z * self.size[1] + y) * self.size[0] + x
+	}
+
+	// Searches rings of cells around `p` until everything further out is
+	// further away than the nearest triangle so far. Infinite when there are
+	// no triangles.
+	fn distance(&self, p: cgmath::Vector3<f64>) -> f64 {
+		let center = self.cell_of(p);
+		let rings = self.size.iter().copied().max().unwrap_or(0);
+		let mut nearest = f64::INFINITY;
+		for ring in 0..=rings {
+			let low = |axis: usize| center[axis].saturating_sub(ring);
+			let high =
+				|axis: usize| (center[axis] + ring).min(self.size[axis] - 1);
+			for z in low(2)..=high(2) {
+				for y in low(1)..=high(1) {
+					for x in low(0)..=high(0) {
+						let cell = [x, y, z];
+						let on_ring = (0..3).any(|axis| {
+							(cell[axis] as isize - center[axis] as isize).abs()
+								== ring as isize
+						});
+						if !on_ring {
+							continue;
+						}
+						for &t in self.cells[self.index(cell)].iter() {
+							let [a, b, c] = self.triangles[t];
+							nearest = nearest
+								.min(point_triangle_distance(p, a, b, c));
+						}
+					}
+				}
+			}
+			if nearest <= ring as f64 * self.cell_size {
+				break;
+			}
+		}
+		nearest
+	}
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+	if a < b {
+		(a, b)
+	} else {
+		(b, a)
+	}
+}
+
+// The cost's bits, which order like the cost as it's never negative, the
+// collapsed and kept positions, and their versions when it was queued.
+type Candidate = (u64, usize, usize, u32, u32);
+
+// Edge collapse simplifier after Garland & Heckbert, working on positions so
+// vertices split along UV or normal seams move together. Collapses always
+// move a position onto one of its neighbours, so every level can reuse the
+// original vertex buffer and only needs its own index buffer.
+struct Simplifier {
+	position_of: std::vec::Vec<usize>,
+	vertices_at: std::vec::Vec<std::vec::Vec<u32>>,
+	positions: std::vec::Vec<cgmath::Vector3<f64>>,
+	quadrics: std::vec::Vec<Quadric>,
+	collapsed_into: std::vec::Vec<usize>,
+	version: std::vec::Vec<u32>,
+	boundary: std::vec::Vec<bool>,
+	boundary_edges: std::collections::HashSet<(usize, usize)>,
+	triangle_vertices: std::vec::Vec<[u32; 3]>,
+	corners: std::vec::Vec<[usize; 3]>,
+	alive: std::vec::Vec<bool>,
+	position_triangles: std::vec::Vec<std::vec::Vec<usize>>,
+	heap: std::collections::BinaryHeap<std::cmp::Reverse<Candidate>>,
+	live_triangles: usize,
+	max_error: f64,
+}
+
+impl Simplifier {
+	fn new(vertices: &[Vertex], indices: &[u32], max_error: f32) -> Simplifier {
+		let mut unique: std::collections::HashMap<[u32; 3], usize> =
+			std::collections::HashMap::new();
+		let mut positions = std::vec::Vec::new();
+		let mut vertices_at: std::vec::Vec<std::vec::Vec<u32>> =
+			std::vec::Vec::new();
+		let mut position_of = std::vec::Vec::with_capacity(vertices.len());
+		for (i, vertex) in vertices.iter().enumerate() {
+			let p = vertex.position;
+			let key = [
+				(p.x + 0.0).to_bits(),
+				(p.y + 0.0).to_bits(),
+				(p.z + 0.0).to_bits(),
+			];
+			let id = *unique.entry(key).or_insert_with(|| {
+				positions.push(p.cast::<f64>().unwrap());
+				vertices_at.push(std::vec::Vec::new());
+				positions.len() - 1
+			});
+			vertices_at[id].push(i as u32);
+			position_of.push(id);
+		}
+
+		let position_count = positions.len();
+		let mut quadrics = vec![Quadric::default(); position_count];
+		let mut position_triangles = vec![std::vec::Vec::new(); position_count];
+		let mut triangle_vertices = std::vec::Vec::new();
+		let mut corners = std::vec::Vec::new();
+		let mut edge_use: std::collections::HashMap<(usize, usize), u32> =
+			std::collections::HashMap::new();
+
+		for tri in indices.chunks(3) {
+			let c = [
+				position_of[tri[0] as usize],
+				position_of[tri[1] as usize],
+				position_of[tri[2] as usize],
+			];
+			if c[0] == c[1] || c[1] == c[2] || c[0] == c[2] {
+				continue;
+			}
+
+			let t = corners.len();
+			corners.push(c);
+			triangle_vertices.push([tri[0], tri[1], tri[2]]);
+			for k in 0..3 {
+				position_triangles[c[k]].push(t);
+				*edge_use.entry(edge_key(c[k], c[(k + 1) % 3])).or_insert(0) +=
+					1;
+			}
+
+			let (p0, p1, p2) =
+				(positions[c[0]], positions[c[1]], positions[c[2]]);
+			let normal = (p1 - p0).cross(p2 - p0);
+			let area = normal.magnitude() * 0.5;
+			if area > 0.0 {
+				let plane = Quadric::from_plane(normal.normalize(), p0, area);
+				for k in 0..3 {
+					quadrics[c[k]] = quadrics[c[k]].add(&plane);
+				}
+			}
+		}
+
+		let mut boundary = vec![false; position_count];
+		let mut boundary_edges = std::collections::HashSet::new();
+		for c in corners.iter() {
+			for k in 0..3 {
+				let (a, b) = (c[k], c[(k + 1) % 3]);
+				if edge_use[&edge_key(a, b)] != 1 {
+					continue;
+				}
+				boundary[a] = true;
+				boundary[b] = true;
+				boundary_edges.insert(edge_key(a, b));
+
+				let (pa, pb, pc) =
+					(positions[a], positions[b], positions[c[(k + 2) % 3]]);
+				let edge = pb - pa;
+				let face_normal = edge.cross(pc - pa);
+				let normal = edge.cross(face_normal);
+				if normal.magnitude2() > 0.0 {
+					let weight = BOUNDARY_WEIGHT * edge.magnitude2();
+					let plane =
+						Quadric::from_plane(normal.normalize(), pa, weight);
+					quadrics[a] = quadrics[a].add(&plane);
+					quadrics[b] = quadrics[b].add(&plane);
+				}
+			}
+		}
+
+		let mut min = cgmath::vec3(f64::MAX, f64::MAX, f64::MAX);
+		let mut max = cgmath::vec3(f64::MIN, f64::MIN, f64::MIN);
+		for p in positions.iter() {
+			min = cgmath::vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+			max = cgmath::vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+		}
+		let extent = if positions.is_empty() {
+			0.0
+		} else {
+			(max - min).magnitude()
+		};
+
+		let live_triangles = corners.len();
+		let mut simplifier = Simplifier {
+			position_of,
+			vertices_at,
+			positions,
+			quadrics,
+			collapsed_into: (0..position_count).collect(),
+			version: vec![0; position_count],
+			boundary,
+			boundary_edges,
+			triangle_vertices,
+			alive: vec![true; corners.len()],
+			corners,
+			position_triangles,
+			heap: std::collections::BinaryHeap::new(),
+			live_triangles,
+			max_error: max_error as f64 * extent,
+		};
+
+		for (a, b) in edge_use.keys() {
+			simplifier.push_candidate(*a, *b);
+			simplifier.push_candidate(*b, *a);
+		}
+
+		simplifier
+	}
+
+	fn push_candidate(&mut self, from: usize, to: usize) {
+		let quadric = self.quadrics[from].add(&self.quadrics[to]);
+		let cost = quadric.error(self.positions[to]);
+		self.heap.push(std::cmp::Reverse((
+			cost.to_bits(),
+			from,
+			to,
+			self.version[from],
+			self.version[to],
+		)));
+	}
+
+	fn neighbours(&self, p: usize) -> std::vec::Vec<usize> {
+		let mut result = std::vec::Vec::new();
+		for &t in self.position_triangles[p].iter() {
+			if !self.alive[t] {
+				continue;
+			}
+			for &q in self.corners[t].iter() {
+				if q != p && !result.contains(&q) {
+					result.push(q);
+				}
+			}
+		}
+		result
+	}
+
+	fn can_collapse(&self, from: usize, to: usize) -> bool {
+		if self.boundary[from]
+			&& !self.boundary_edges.contains(&edge_key(from, to))
+		{
+			return false;
+		}
+
+		// Link condition: an interior edge shares exactly two neighbours,
+		// anything more would pinch the surface into a non-manifold fan.
+		let to_neighbours = self.neighbours(to);
+		let shared = self
+			.neighbours(from)
+			.iter()
+			.filter(|q| to_neighbours.contains(q))
+			.count();
+		if shared > 2 {
+			return false;
+		}
+
+		for &t in self.position_triangles[from].iter() {
+			let c = self.corners[t];
+			if !self.alive[t] || c.contains(&to) {
+				continue;
+			}
+			let p = |q: usize| self.positions[q];
+			let before = (p(c[1]) - p(c[0])).cross(p(c[2]) - p(c[0]));
+			let moved = |q: usize| if q == from { p(to) } else { p(q) };
+			let after =
+				(moved(c[1]) - moved(c[0])).cross(moved(c[2]) - moved(c[0]));
+			if before.dot(after) <= 0.0 {
+				return false;
+			}
+		}
+
+		true
+	}
+
+	fn collapse(&mut self, from: usize, to: usize) {
+		let from_neighbours = self.neighbours(from);
+
+		self.quadrics[to] = self.quadrics[to].add(&self.quadrics[from]);
+		self.collapsed_into[from] = to;
+		self.boundary[to] = self.boundary[to] || self.boundary[from];
+
+		for &q in from_neighbours.iter() {
+			if self.boundary_edges.remove(&edge_key(from, q)) && q != to {
+				self.boundary_edges.insert(edge_key(to, q));
+			}
+		}
+
+		let triangles = std::mem::take(&mut self.position_triangles[from]);
+		for t in triangles {
+			if !self.alive[t] {
+				continue;
+			}
+			if self.corners[t].contains(&to) {
+				self.alive[t] = false;
+				self.live_triangles -= 1;
+				continue;
+			}
+			for corner in self.corners[t].iter_mut() {
+				if *corner == from {
+					*corner = to;
+				}
+			}
+			self.position_triangles[to].push(t);
+		}
+		let alive = &self.alive;
+		self.position_triangles[to].retain(|&t| alive[t]);
+
+		self.version[to] += 1;
+		for q in self.neighbours(to) {
+			self.push_candidate(q, to);
+			self.push_candidate(to, q);
+		}
+	}
+
+	fn collapse_to(&mut self, target: usize) {
+		while self.live_triangles > target {
+			let (cost, from, to, from_version, to_version) =
+				match self.heap.pop() {
+					Some(std::cmp::Reverse(candidate)) => candidate,
+					None => return,
+				};
+
+			if self.collapsed_into[from] != from
+				|| self.collapsed_into[to] != to
+				|| self.version[from] != from_version
+				|| self.version[to] != to_version
+			{
+				continue;
+			}
+
+			let error = f64::from_bits(cost).sqrt();
+			if error > self.max_error {
+				return;
+			}
+			if !self.can_collapse(from, to) {
+				continue;
+			}
+
+			self.collapse(from, to);
+		}
+	}
+
+	// Distance from every original position to the nearest surviving
+	// triangle. The quadric cost only steers the collapse order, this is
+	// what gets reported as the level's error.
+	fn measure_error(&self) -> f64 {
+		let triangles = self
+			.corners
+			.iter()
+			.zip(self.alive.iter())
+			.filter(|(_, &alive)| alive)
+			.map(|(c, _)| {
+				[
+					self.positions[c[0]],
+					self.positions[c[1]],
+					self.positions[c[2]],
+				]
+			})
+			.collect();
+		let grid = TriangleGrid::new(triangles);
+		self.positions
+			.iter()
+			.map(|&p| grid.distance(p))
+			.fold(0.0, f64::max)
+	}
+
+	fn resolve(&self, mut p: usize) -> usize {
+		while self.collapsed_into[p] != p {
+			p = self.collapsed_into[p];
+		}
+		p
+	}
+
+	// Of the vertices sharing the collapsed-to position, the one whose normal
+	// and texture coordinates are closest keeps seams from smearing.
+	fn closest_vertex(&self, vertices: &[Vertex], v: u32, p: usize) -> u32 {
+		let original = &vertices[v as usize];
+		*self.vertices_at[p]
+			.iter()
+			.min_by(|&&a, &&b| {
+				let distance = |i: u32| {
+					let candidate = &vertices[i as usize];
+					(1.0 - candidate.normal.dot(original.normal))
+						+ (candidate.tex_coords - original.tex_coords)
+							.magnitude2()
+				};
+				distance(a).partial_cmp(&distance(b)).unwrap()
+			})
+			.unwrap()
+	}
+
+	fn indices(&self, vertices: &[Vertex]) -> std::vec::Vec<u32> {
+		let mut indices = std::vec::Vec::with_capacity(self.live_triangles * 3);
+		for (t, tri) in self.triangle_vertices.iter().enumerate() {
+			if !self.alive[t] {
+				continue;
+			}
+			for &v in tri.iter() {
+				let original = self.position_of[v as usize];
+				let p = self.resolve(original);
+				if p == original {
+					indices.push(v);
+				} else {
+					indices.push(self.closest_vertex(vertices, v, p));
+				}
+			}
+		}
+		indices
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A size x size grid of quads in the xy plane, raised by `height`.
+	fn grid(
+		size: usize,
+		height: impl Fn(f32, f32) -> f32,
+	) -> (std::vec::Vec<Vertex>, std::vec::Vec<u32>) {
+		let mut vertices = std::vec::Vec::new();
+		for y in 0..=size {
+			for x in 0..=size {
+				let (x, y) = (x as f32, y as f32);
+				vertices.push(Vertex {
+					position: cgmath::vec3(x, y, height(x, y)),
+					normal: cgmath::vec3(0.0, 0.0, 1.0),
+					tex_coords: cgmath::vec2(x, y) / size as f32,
+				});
+			}
+		}
+		let row = size as u32 + 1;
+		let mut indices = std::vec::Vec::new();
+		for y in 0..size as u32 {
+			for x in 0..size as u32 {
+				let i = y * row + x;
+				indices.extend_from_slice(&[i, i + 1, i + row + 1]);
+				indices.extend_from_slice(&[i, i + row + 1, i + row]);
+			}
+		}
+		(vertices, indices)
+	}
+
+	// Brute force version of `measure_error`.
+	fn hausdorff(vertices: &[Vertex], indices: &[u32]) -> f64 {
+		let p = |i: u32| vertices[i as usize].position.cast::<f64>().unwrap();
+		vertices
+			.iter()
+			.map(|vertex| {
+				let point = vertex.position.cast::<f64>().unwrap();
+				indices
+					.chunks(3)
+					.map(|t| {
+						point_triangle_distance(
+							point,
+							p(t[0]),
+							p(t[1]),
+							p(t[2]),
+						)
+					})
+					.fold(f64::INFINITY, f64::min)
+			})
+			.fold(0.0, f64::max)
+	}
+
+	#[test]
+	fn flat_grid_reaches_every_target() {
+		let (vertices, indices) = grid(16, |_, _| 0.0);
+		let options = LodOptions::default();
+		let levels = generate_lods(&vertices, &indices, &options);
+		assert_eq!(levels.len(), options.ratios.len());
+		let triangles = indices.len() / 3;
+		for (level, ratio) in levels.iter().zip(options.ratios.iter()) {
+			let target = (triangles as f32 * ratio) as usize;
+			let count = level.indices.len() / 3;
+			// A collapse removes one or two triangles.
+			assert!(count <= target && count + 2 > target, "{}", count);
+			assert!(level
+				.indices
+				.iter()
+				.all(|&i| (i as usize) < vertices.len()));
+			// Nothing moves off the plane and the outline is kept.
+			assert!(level.error < 1e-5, "{}", level.error);
+		}
+	}
+
+	#[test]
+	fn errors_match_the_simplified_surface() {
+		let (vertices, indices) =
+			grid(24, |x, y| (x * 0.4).sin() * (y * 0.3).cos());
+		let options = LodOptions {
+			ratios: vec![0.5, 0.25, 0.125, 0.0625],
+			max_error: 0.02,
+		};
+		let levels = generate_lods(&vertices, &indices, &options);
+		assert!(!levels.is_empty());
+		let diagonal = (24.0f64 * 24.0 * 2.0 + 4.0).sqrt();
+		let mut previous = 0.0;
+		for level in levels.iter() {
+			let measured = hausdorff(&vertices, &level.indices);
+			// Levels report the largest error of any finer level too.
+			assert!(level.error as f64 >= measured - 1e-5);
+			assert!(level.error >= previous);
+			assert!(level.error as f64 <= options.max_error as f64 * diagonal);
+			previous = level.error;
+		}
+	}
+
+	#[test]
+	fn measured_error_counts_collapsed_away_regions() {
+		let (vertices, indices) =
+			grid(8, |x, y| if x == 4.0 && y == 4.0 { 1.0 } else { 0.0 });
+		let mut simplifier = Simplifier::new(&vertices, &indices, 1.0);
+		simplifier.collapse_to(indices.len() / 3 / 4);
+		let measured = hausdorff(&vertices, &simplifier.indices(&vertices));
+		assert!((simplifier.measure_error() - measured).abs() < 1e-6);
+	}
+
+	#[test]
+	fn never_makes_an_empty_level() {
+		let (vertices, indices) = grid(1, |_, _| 0.0);
+		let options = LodOptions {
+			ratios: vec![0.0],
+			max_error: 10.0,
+		};
+		let levels = generate_lods(&vertices, &indices, &options);
+		assert!(levels.iter().all(|level| !level.indices.is_empty()));
+	}
+}
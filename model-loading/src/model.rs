@@ -3,53 +3,107 @@ extern crate gl;
 extern crate tobj;
 
 use crate::shader::*;
-use crate::image::GenericImage;
+use cgmath::prelude::*;
+use crate::bounds;
+use crate::cache;
+use crate::gpu;
+use crate::lod;
+use crate::optimize;
+use crate::texture;
+use crate::vertex;
 use std::path::Path;
-
-macro_rules! offset_of {
-    ($ty:ty, $field:ident) => {
-        &(*(std::ptr::null() as *const $ty)).$field as *const _ as usize
-    }
-}
+use std::rc::Rc;
 
 #[derive(Clone)]
-struct Vertex {
-	position: cgmath::Vector3<f32>,
-	normal: cgmath::Vector3<f32>,
-	tex_coords: cgmath::Vector2<f32>,
+pub struct Vertex {
+	pub position: cgmath::Vector3<f32>,
+	pub normal: cgmath::Vector3<f32>,
+	pub tex_coords: cgmath::Vector2<f32>,
 }
 
+vertex::vertex_layout!(Vertex { position: 0, normal: 1, tex_coords: 2 });
+
 #[derive(Clone)]
 struct Texture {
-	id: u32,
+	texture: Rc<gpu::Texture2D>,
 	type_: std::string::String,
 	path: std::string::String,
 }
 
+#[derive(Clone)]
+pub struct Lod {
+	pub indices: std::vec::Vec<u32>,
+	pub error: f32,
+	pub offset: usize,
+}
+
+// The GL side of a mesh, created by `setup_mesh`.
+struct MeshBuffers {
+	vao: gpu::VertexArray,
+	// Kept alive for as long as the vertex array refers to them.
+	_vbo: gpu::Buffer,
+	_ebo: gpu::Buffer,
+}
+
 #[derive(Default, Clone)]
-struct Mesh {
-	vertices: std::vec::Vec<Vertex>,
-	indices: std::vec::Vec<u32>,
+pub struct Material {
+	pub name: std::string::String,
+	pub ambient: [f32; 3],
+	pub diffuse: [f32; 3],
+	pub specular: [f32; 3],
+	pub shininess: f32,
+	pub dissolve: f32,
+	pub diffuse_texture: std::string::String,
+	pub specular_texture: std::string::String,
+	pub normal_texture: std::string::String,
+}
+
+pub struct Mesh {
+	pub vertices: std::vec::Vec<Vertex>,
+	pub indices: std::vec::Vec<u32>,
+	// `gl::UNSIGNED_SHORT` when the vertices are few enough, see
+	// `optimize::index_type_for`.
+	pub index_type: u32,
+	pub lods: std::vec::Vec<Lod>,
+	pub aabb: bounds::Aabb,
+	// Its center is where the level of detail is picked for.
+	pub sphere: bounds::Sphere,
+	pub material: Option<Material>,
 	textures: std::vec::Vec<Texture>,
-	vao: u32,
-	vbo: u32,
-	ebo: u32,
+	buffers: Option<MeshBuffers>,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default)]
 pub struct Model {
 	meshes: std::vec::Vec<Mesh>,
 	textures_loaded: std::vec::Vec<Texture>,
 	directory: std::string::String,
+	// One per mesh, empty when the meshes came from the cache.
+	pub optimize_reports: std::vec::Vec<optimize::OptimizeReport>,
+}
+
+impl Mesh {
+	// Before `setup_mesh` and without textures loaded.
+	pub fn new(
+		vertices: std::vec::Vec<Vertex>,
+		indices: std::vec::Vec<u32>,
+		index_type: u32,
+		lods: std::vec::Vec<Lod>,
+		aabb: bounds::Aabb,
+		sphere: bounds::Sphere,
+		material: Option<Material>,
+	) -> Mesh {
+		Mesh { vertices, indices, index_type, lods, aabb, sphere, material, textures: std::vec::Vec::new(), buffers: None }
+	}
 }
 
-fn draw_mesh(mesh: &Mesh, shader: u32) {
+fn draw_mesh(mesh: &Mesh, shader: u32, level: Option<usize>) {
 	let mut diffuse_nr: u32 = 1;
 	let mut specular_nr: u32 = 1;
 
 	for (i, texture) in mesh.textures.iter().enumerate() {
 		unsafe {
-			gl::ActiveTexture(gl::TEXTURE0 as u32 + i as u32);
+			gl::ActiveTexture(gl::TEXTURE0 + i as u32);
 			let number;
 			if texture.type_ == "diffuse" {
 				number = diffuse_nr;
@@ -62,68 +116,143 @@ fn draw_mesh(mesh: &Mesh, shader: u32) {
 				number = 0;
 			}
 			set_int(shader, &format!("material.texture_{}{}", texture.type_, number), i as i32);
-			gl::BindTexture(gl::TEXTURE_2D, texture.id);
+			texture.texture.bind();
 		}
 	}
 	unsafe {
 		gl::ActiveTexture(gl::TEXTURE0);
-		gl::BindVertexArray(mesh.vao);
-		gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, gl::UNSIGNED_INT, std::ptr::null());
+	}
+	mesh.buffers.as_ref().expect("Mesh was drawn before setup_mesh").vao.bind();
+	unsafe {
+		match level {
+			Some(level) => {
+				let lod = &mesh.lods[level];
+				gl::DrawElements(gl::TRIANGLES, lod.indices.len() as i32, mesh.index_type, lod.offset as *const std::os::raw::c_void);
+			}
+			None => gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, mesh.index_type, std::ptr::null()),
+		}
 	}
 }
 
 	
 	
 	
-fn setup_mesh(vertices: std::vec::Vec<Vertex>, indices: std::vec::Vec<u32>, textures: std::vec::Vec<Texture>) -> Mesh {
-	let mut mesh: Mesh = Mesh::default();
-	mesh.vertices = vertices;
-	mesh.indices = indices;
-	mesh.textures = textures;
-	unsafe {
-		gl::GenVertexArrays(1, &mut mesh.vao);
-		gl::GenBuffers(1, &mut mesh.vbo);
-		gl::GenBuffers(1, &mut mesh.ebo);
+fn setup_mesh(mut mesh: Mesh) -> Mesh {
+	// Every level of detail shares the vertex buffer, their index lists are
+	// appended to the full-detail ones in a single element buffer.
+	let mut all_indices = mesh.indices.clone();
+	let index_size = optimize::index_size(mesh.index_type);
+	for lod in mesh.lods.iter_mut() {
+		lod.offset = all_indices.len() * index_size;
+		all_indices.extend_from_slice(&lod.indices);
+	}
 
-		gl::BindVertexArray(mesh.vao);
+	let vao = gpu::VertexArray::new();
+	let vbo = gpu::Buffer::new();
+	let ebo = gpu::Buffer::new();
 
-		gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
+	vao.bind();
+	vbo.bind(gl::ARRAY_BUFFER);
+	ebo.bind(gl::ELEMENT_ARRAY_BUFFER);
+	unsafe {
 		let size = (mesh.vertices.len() *  std::mem::size_of::<Vertex>()) as isize;
 		let data = &mesh.vertices[0] as *const Vertex as *const std::os::raw::c_void;
 		gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
 
-		gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
-		let size = (mesh.indices.len() * std::mem::size_of::<u32>()) as isize;
-		let data = &mesh.indices[0] as *const u32 as *const std::os::raw::c_void;
-		gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-		let size = std::mem::size_of::<Vertex>() as i32;
-		gl::EnableVertexAttribArray(0);
-		gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, position) as *const std::os::raw::c_void);
-
-		gl::EnableVertexAttribArray(1);
-		gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, normal) as *const std::os::raw::c_void);
-
-		gl::EnableVertexAttribArray(2);
-		gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coords) as *const std::os::raw::c_void);
+		if mesh.index_type == gl::UNSIGNED_SHORT {
+			let short_indices: std::vec::Vec<u16> = all_indices.iter().map(|&i| i as u16).collect();
+			let size = (short_indices.len() * std::mem::size_of::<u16>()) as isize;
+			let data = &short_indices[0] as *const u16 as *const std::os::raw::c_void;
+			gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+		} else {
+			let size = (all_indices.len() * std::mem::size_of::<u32>()) as isize;
+			let data = &all_indices[0] as *const u32 as *const std::os::raw::c_void;
+			gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+		}
+	}
+	vertex::set_attributes::<Vertex>(0, 0);
 
+	unsafe {
 		gl::BindVertexArray(0);
 	}
+	mesh.buffers = Some(MeshBuffers { vao, _vbo: vbo, _ebo: ebo });
 	mesh
 }
 
-pub fn draw_model(model: &Model, shader: u32) {
+// Each mesh is drawn at the coarsest level of detail that stays within
+// `view.pixel_error` pixels of the full one, unless it's outside `frustum`.
+// The frustum has to be extracted from projection * view * model so the
+// planes are in the same space as the mesh bounds.
+pub fn draw_model(model: &Model, shader: u32, frustum: Option<&bounds::Frustum>, view: &lod::LodView) -> bounds::CullStats {
+	let mut stats = bounds::CullStats::default();
 	for mesh in model.meshes.iter() {
-		draw_mesh(mesh, shader);
+		if let Some(frustum) = frustum {
+			if !frustum.intersects_sphere(&mesh.sphere) || !frustum.intersects_aabb(&mesh.aabb) {
+				stats.culled += 1;
+				continue;
+			}
+		}
+
+		let errors: std::vec::Vec<f32> = mesh.lods.iter().map(|lod| lod.error).collect();
+		let level = lod::select_level(&errors, mesh.sphere.center, view);
+		draw_mesh(mesh, shader, level);
+		stats.drawn += 1;
+	}
+	stats
+}
+
+// Textures are loaded with `options`, adjusted for their role.
+pub fn load_model(path: &str, options: &texture::TextureOptions) -> Model {
+	let mut loaded_model = Model::default();
+	let obj_path = Path::new(path);
+	loaded_model.directory = obj_path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+
+	for mut mesh in load_meshes(path, &mut loaded_model.optimize_reports) {
+		if let Some(material) = mesh.material.clone() {
+			mesh.textures = load_mesh_textures(&mut loaded_model, &material, options);
+		}
+		loaded_model.meshes.push(setup_mesh(mesh));
 	}
+
+	loaded_model
 }
 
-pub fn load_model(path: &str) -> Model {
+// The meshes of an OBJ, optimised and with their levels of detail, from the
+// cache when it's up to date and otherwise parsed and processed.
+pub(crate) fn load_meshes(path: &str, reports: &mut std::vec::Vec<optimize::OptimizeReport>) -> std::vec::Vec<Mesh> {
+	let obj_path = Path::new(path);
+	let lod_options = lod::LodOptions::default();
+	if let Some(meshes) = cache::read(obj_path, &lod_options) {
+		println!("{}: {} meshes loaded from the cache", path, meshes.len());
+		return meshes;
+	}
+
+	let mut meshes = parse_obj(path);
+	for mesh in meshes.iter_mut() {
+		let optimized = optimize::optimize(std::mem::take(&mut mesh.vertices), std::mem::take(&mut mesh.indices));
+		reports.push(optimized.report);
+		mesh.vertices = optimized.vertices;
+		mesh.indices = optimized.indices;
+		mesh.index_type = optimized.index_type;
+
+		mesh.lods = lod::generate_lods(&mesh.vertices, &mesh.indices, &lod_options)
+			.into_iter()
+			.map(|level| Lod { indices: optimize::optimize_vertex_cache(&level.indices, mesh.vertices.len()), error: level.error, offset: 0 })
+			.collect();
+		let positions = mesh.vertices.iter().map(|v| cgmath::Point3::from_vec(v.position));
+		mesh.aabb = bounds::Aabb::from_points(positions.clone());
+		mesh.sphere = bounds::Sphere::from_points(positions);
+	}
+	if let Err(err) = cache::write(obj_path, &lod_options, &meshes) {
+		eprintln!("Failed to write mesh cache for {}: {}", path, err);
+	}
+	meshes
+}
+
+fn parse_obj(path: &str) -> std::vec::Vec<Mesh> {
 	let obj = tobj::load_obj(path, true);
 	let (models, materials) = obj.unwrap();
-	let mut loaded_model = Model::default();
-	let path = Path::new(path);
-	loaded_model.directory = path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+	let mut meshes = std::vec::Vec::with_capacity(models.len());
 
 	for model in models {
 		let mesh = &model.mesh;
@@ -141,39 +270,55 @@ pub fn load_model(path: &str) -> Model {
 			})
 		}
 
-		let mut textures: std::vec::Vec<Texture> = std::vec::Vec::new();
-		if let Some(material_id) = mesh.material_id {
+		let material = mesh.material_id.map(|material_id| {
 			let material = &materials[material_id];
-
-			if !material.diffuse_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.diffuse_texture, "diffuse");
-				textures.push(texture);
+			Material {
+				name: material.name.clone(),
+				ambient: material.ambient,
+				diffuse: material.diffuse,
+				specular: material.specular,
+				shininess: material.shininess,
+				dissolve: material.dissolve,
+				diffuse_texture: material.diffuse_texture.clone(),
+				specular_texture: material.specular_texture.clone(),
+				normal_texture: material.normal_texture.clone(),
 			}
+		});
 
-			if !material.specular_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.specular_texture, "specular");
-				textures.push(texture);
-			}
+		meshes.push(Mesh::new(vertices, indices, gl::UNSIGNED_INT, std::vec::Vec::new(), bounds::Aabb::default(), bounds::Sphere::default(), material));
+	}
 
-			if !material.normal_texture.is_empty() {
-				let texture = load_material_texture(&mut loaded_model, &material.normal_texture, "normal");
-				textures.push(texture);
-			}
-		}
+	meshes
+}
+
+fn load_mesh_textures(model: &mut Model, material: &Material, options: &texture::TextureOptions) -> std::vec::Vec<Texture> {
+	let mut textures: std::vec::Vec<Texture> = std::vec::Vec::new();
 
-		loaded_model.meshes.push(setup_mesh(vertices, indices, textures));
+	if !material.diffuse_texture.is_empty() {
+		let texture = load_material_texture(model, &material.diffuse_texture, "diffuse", options);
+		textures.push(texture);
 	}
 
-	loaded_model
+	if !material.specular_texture.is_empty() {
+		let texture = load_material_texture(model, &material.specular_texture, "specular", options);
+		textures.push(texture);
+	}
+
+	if !material.normal_texture.is_empty() {
+		let texture = load_material_texture(model, &material.normal_texture, "normal", options);
+		textures.push(texture);
+	}
+
+	textures
 }
 
-fn load_material_texture(model: &mut Model, path: &str, type_name: &str) -> Texture {
+fn load_material_texture(model: &mut Model, path: &str, type_name: &str, options: &texture::TextureOptions) -> Texture {
 	let texture = model.textures_loaded.iter().find(|t| t.path == path);
 	if let Some(texture) = texture {
 		return texture.clone();
 	}
 	let texture = Texture {
-		id: texture_from_file(path, &model.directory),
+		texture: Rc::new(texture::load_texture(&format!("{}/{}", model.directory, path), &options.for_role(type_name))),
 		type_: type_name.into(),
 		path: path.into(),
 	};
@@ -182,32 +327,3 @@ fn load_material_texture(model: &mut Model, path: &str, type_name: &str) -> Text
 
 	texture
 }
-
-fn texture_from_file(path: &str, _pp: &str) -> u32 {
-	let mut texture_id = 0;
-	let path = format!("{}/{}", _pp, path);
-	let img = image::open(path).expect("Texture failed to load");
-	let format = match img {
-		image::ImageLuma8(_) => gl::RED,
-		image::ImageLumaA8(_) => gl::RG,
-		image::ImageRgb8(_) => gl::RGB,
-		image::ImageRgba8(_) => gl::RGBA,
-	};
-
-	let data = img.raw_pixels();
-
-	unsafe {
-		gl::GenTextures(1, &mut texture_id);
-
-		gl::BindTexture(gl::TEXTURE_2D, texture_id);
-		gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, img.width() as i32, img.height() as i32, 0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const std::os::raw::c_void);
-		gl::GenerateMipmap(gl::TEXTURE_2D);
-
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-		gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-	}
-
-	texture_id
-}
\ No newline at end of file